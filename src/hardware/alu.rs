@@ -0,0 +1,469 @@
+use super::registers::{Flag, Register, RegisterFile};
+
+// 8-bit arithmetic and logic. Every operation works on the accumulator and updates the flags in
+// the given register file, matching what the SM83 does for the register, (HL) and immediate forms.
+
+fn accumulator(registers: &RegisterFile) -> u8 {
+    registers.read_register(Register::A) as u8
+}
+
+fn set_flags(registers: &mut RegisterFile, z: bool, n: bool, h: bool, c: bool) {
+    registers.set_flag(Flag::Z, z);
+    registers.set_flag(Flag::N, n);
+    registers.set_flag(Flag::H, h);
+    registers.set_flag(Flag::C, c);
+}
+
+pub fn add(registers: &mut RegisterFile, value: u8) {
+    add_with_carry_in(registers, value, false);
+}
+
+pub fn add_with_carry(registers: &mut RegisterFile, value: u8) {
+    let carry = registers.get_flag(Flag::C);
+    add_with_carry_in(registers, value, carry);
+}
+
+fn add_with_carry_in(registers: &mut RegisterFile, value: u8, carry: bool) {
+    let a_reg = accumulator(registers);
+    let carry = carry as u8;
+
+    let result = a_reg.wrapping_add(value).wrapping_add(carry);
+    let half_carry = (a_reg & 0xF) + (value & 0xF) + carry > 0xF;
+    let full_carry = a_reg as u16 + value as u16 + carry as u16 > 0xFF;
+
+    registers.write_register(Register::A, result as u16);
+    set_flags(registers, result == 0, false, half_carry, full_carry);
+}
+
+pub fn subtract(registers: &mut RegisterFile, value: u8) {
+    let result = subtract_with_carry_in(registers, value, false);
+    registers.write_register(Register::A, result as u16);
+}
+
+pub fn subtract_with_carry(registers: &mut RegisterFile, value: u8) {
+    let carry = registers.get_flag(Flag::C);
+    let result = subtract_with_carry_in(registers, value, carry);
+    registers.write_register(Register::A, result as u16);
+}
+
+// CP is a subtraction that throws away the result
+pub fn compare(registers: &mut RegisterFile, value: u8) {
+    subtract_with_carry_in(registers, value, false);
+}
+
+fn subtract_with_carry_in(registers: &mut RegisterFile, value: u8, carry: bool) -> u8 {
+    let a_reg = accumulator(registers);
+    let carry = carry as u8;
+
+    let result = a_reg.wrapping_sub(value).wrapping_sub(carry);
+    let half_carry = (a_reg & 0xF) < (value & 0xF) + carry;
+    let full_carry = (a_reg as u16) < value as u16 + carry as u16;
+
+    set_flags(registers, result == 0, true, half_carry, full_carry);
+
+    result
+}
+
+pub fn and(registers: &mut RegisterFile, value: u8) {
+    let result = accumulator(registers) & value;
+
+    registers.write_register(Register::A, result as u16);
+    set_flags(registers, result == 0, false, true, false);
+}
+
+pub fn xor(registers: &mut RegisterFile, value: u8) {
+    let result = accumulator(registers) ^ value;
+
+    registers.write_register(Register::A, result as u16);
+    set_flags(registers, result == 0, false, false, false);
+}
+
+pub fn or(registers: &mut RegisterFile, value: u8) {
+    let result = accumulator(registers) | value;
+
+    registers.write_register(Register::A, result as u16);
+    set_flags(registers, result == 0, false, false, false);
+}
+
+// INC and DEC return the new value rather than writing it, as the operand may live in memory.
+// Neither touches the carry flag.
+pub fn increment(registers: &mut RegisterFile, value: u8) -> u8 {
+    let result = value.wrapping_add(1);
+
+    registers.set_flag(Flag::Z, result == 0);
+    registers.set_flag(Flag::N, false);
+    registers.set_flag(Flag::H, value & 0xF == 0xF);
+
+    result
+}
+
+pub fn decrement(registers: &mut RegisterFile, value: u8) -> u8 {
+    let result = value.wrapping_sub(1);
+
+    registers.set_flag(Flag::Z, result == 0);
+    registers.set_flag(Flag::N, true);
+    registers.set_flag(Flag::H, value & 0xF == 0);
+
+    result
+}
+
+// ADD HL, rr. The half carry comes from bit 11 and the carry from bit 15, and Z is left alone.
+pub fn add_word(registers: &mut RegisterFile, value: u16) {
+    let hl = registers.read_register(Register::HL);
+
+    let result = hl.wrapping_add(value);
+    let half_carry = (hl & 0xFFF) + (value & 0xFFF) > 0xFFF;
+    let full_carry = hl as u32 + value as u32 > 0xFFFF;
+
+    registers.write_register(Register::HL, result);
+    registers.set_flag(Flag::N, false);
+    registers.set_flag(Flag::H, half_carry);
+    registers.set_flag(Flag::C, full_carry);
+}
+
+// Adds a signed 8-bit offset to a 16-bit value, as done by ADD SP, s8 and LD HL, SP+s8. The
+// carries come from the unsigned addition of the low byte of the value and the raw offset byte,
+// whatever the sign of the offset, and Z and N are always cleared.
+pub fn add_signed_byte(registers: &mut RegisterFile, value: u16, offset: u8) -> u16 {
+    let result = value.wrapping_add(offset as i8 as u16);
+    let half_carry = (value & 0xF) + (offset as u16 & 0xF) > 0xF;
+    let full_carry = (value & 0xFF) + offset as u16 > 0xFF;
+
+    set_flags(registers, false, false, half_carry, full_carry);
+
+    result
+}
+
+// Corrects the accumulator into packed BCD after an addition or subtraction, using N, H and C
+// to work out which operation came before
+pub fn decimal_adjust(registers: &mut RegisterFile) {
+    let mut a_reg = accumulator(registers);
+    let subtract = registers.get_flag(Flag::N);
+    let mut carry = registers.get_flag(Flag::C);
+    let half_carry = registers.get_flag(Flag::H);
+
+    if subtract {
+        if carry {
+            a_reg = a_reg.wrapping_sub(0x60);
+        }
+        if half_carry {
+            a_reg = a_reg.wrapping_sub(0x06);
+        }
+    } else {
+        if carry || a_reg > 0x99 {
+            a_reg = a_reg.wrapping_add(0x60);
+            carry = true;
+        }
+        if half_carry || a_reg & 0xF > 0x9 {
+            a_reg = a_reg.wrapping_add(0x06);
+        }
+    }
+
+    registers.write_register(Register::A, a_reg as u16);
+    set_flags(registers, a_reg == 0, subtract, false, carry);
+}
+
+pub fn complement(registers: &mut RegisterFile) {
+    let a_reg = accumulator(registers);
+
+    registers.write_register(Register::A, !a_reg as u16);
+    registers.set_flag(Flag::N, true);
+    registers.set_flag(Flag::H, true);
+}
+
+pub fn set_carry_flag(registers: &mut RegisterFile) {
+    registers.set_flag(Flag::N, false);
+    registers.set_flag(Flag::H, false);
+    registers.set_flag(Flag::C, true);
+}
+
+pub fn complement_carry_flag(registers: &mut RegisterFile) {
+    let carry = registers.get_flag(Flag::C);
+
+    registers.set_flag(Flag::N, false);
+    registers.set_flag(Flag::H, false);
+    registers.set_flag(Flag::C, !carry);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Each case is (A, operand, F before, A after, F after)
+    type Case = (u8, u8, u8, u8, u8);
+
+    fn run_cases(operation: fn(&mut RegisterFile, u8), cases: &[Case]) {
+        for &(a, operand, flags, expected_a, expected_flags) in cases {
+            let mut registers = RegisterFile::new();
+            registers.write_register(Register::A, a as u16);
+            registers.write_register(Register::F, flags as u16);
+
+            operation(&mut registers, operand);
+
+            let case = format!("A={:#04X} operand={:#04X} F={:#04X}", a, operand, flags);
+            assert_eq!(
+                registers.read_register(Register::A),
+                expected_a as u16,
+                "{}",
+                case
+            );
+            assert_eq!(
+                registers.read_register(Register::F),
+                expected_flags as u16,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn add_cases() {
+        run_cases(
+            add,
+            &[
+                (0x00, 0x00, 0x00, 0x00, 0x80),
+                (0x12, 0x34, 0x00, 0x46, 0x00),
+                (0x0F, 0x01, 0x00, 0x10, 0x20),
+                (0xF0, 0x10, 0x00, 0x00, 0x90),
+                (0x3A, 0xC6, 0x00, 0x00, 0xB0),
+                // The incoming carry is ignored
+                (0x12, 0x34, 0x10, 0x46, 0x00),
+            ],
+        );
+    }
+
+    #[test]
+    fn add_with_carry_cases() {
+        run_cases(
+            add_with_carry,
+            &[
+                (0xE1, 0x0F, 0x10, 0xF1, 0x20),
+                (0xE1, 0x3B, 0x10, 0x1D, 0x10),
+                (0xE1, 0x1E, 0x10, 0x00, 0xB0),
+                (0x0E, 0x01, 0x10, 0x10, 0x20),
+                (0xFF, 0x00, 0x10, 0x00, 0xB0),
+                (0xE1, 0x1E, 0x00, 0xFF, 0x00),
+            ],
+        );
+    }
+
+    #[test]
+    fn subtract_cases() {
+        run_cases(
+            subtract,
+            &[
+                (0x3E, 0x3E, 0x00, 0x00, 0xC0),
+                (0x3E, 0x0F, 0x00, 0x2F, 0x60),
+                (0x3E, 0x40, 0x00, 0xFE, 0x50),
+                (0x10, 0x01, 0x00, 0x0F, 0x60),
+                (0x3E, 0x3E, 0x10, 0x00, 0xC0),
+            ],
+        );
+    }
+
+    #[test]
+    fn subtract_with_carry_cases() {
+        run_cases(
+            subtract_with_carry,
+            &[
+                (0x3B, 0x2A, 0x10, 0x10, 0x40),
+                (0x3B, 0x4F, 0x10, 0xEB, 0x70),
+                (0x3B, 0x3A, 0x10, 0x00, 0xC0),
+                (0x10, 0x0F, 0x10, 0x00, 0xE0),
+                (0x00, 0xFF, 0x10, 0x00, 0xF0),
+                (0x3B, 0x3A, 0x00, 0x01, 0x40),
+            ],
+        );
+    }
+
+    #[test]
+    fn and_cases() {
+        run_cases(
+            and,
+            &[
+                (0x5A, 0x3F, 0x00, 0x1A, 0x20),
+                (0x5A, 0x38, 0xD0, 0x18, 0x20),
+                (0x5A, 0x00, 0x00, 0x00, 0xA0),
+            ],
+        );
+    }
+
+    #[test]
+    fn xor_cases() {
+        run_cases(
+            xor,
+            &[
+                (0xFF, 0xFF, 0x00, 0x00, 0x80),
+                (0xFF, 0x0F, 0x70, 0xF0, 0x00),
+                (0xFF, 0x8A, 0x00, 0x75, 0x00),
+            ],
+        );
+    }
+
+    #[test]
+    fn or_cases() {
+        run_cases(
+            or,
+            &[
+                (0x5A, 0x5A, 0x00, 0x5A, 0x00),
+                (0x5A, 0x03, 0xF0, 0x5B, 0x00),
+                (0x00, 0x00, 0x00, 0x00, 0x80),
+            ],
+        );
+    }
+
+    #[test]
+    fn compare_cases() {
+        run_cases(
+            compare,
+            &[
+                (0x3C, 0x2F, 0x00, 0x3C, 0x60),
+                (0x3C, 0x3C, 0x00, 0x3C, 0xC0),
+                (0x3C, 0x40, 0x00, 0x3C, 0x50),
+            ],
+        );
+    }
+
+    #[test]
+    fn increment_cases() {
+        // The result is returned rather than stored, so route it through A for the table
+        fn increment_a(registers: &mut RegisterFile, value: u8) {
+            let result = increment(registers, value);
+            registers.write_register(Register::A, result as u16);
+        }
+
+        run_cases(
+            increment_a,
+            &[
+                (0x00, 0xFF, 0x00, 0x00, 0xA0),
+                (0x00, 0x50, 0x00, 0x51, 0x00),
+                (0x00, 0x0F, 0x00, 0x10, 0x20),
+                (0x00, 0x50, 0x50, 0x51, 0x10),
+            ],
+        );
+    }
+
+    #[test]
+    fn decrement_cases() {
+        fn decrement_a(registers: &mut RegisterFile, value: u8) {
+            let result = decrement(registers, value);
+            registers.write_register(Register::A, result as u16);
+        }
+
+        run_cases(
+            decrement_a,
+            &[
+                (0x00, 0x01, 0x00, 0x00, 0xC0),
+                (0x00, 0x00, 0x00, 0xFF, 0x60),
+                (0x00, 0x10, 0x00, 0x0F, 0x60),
+                (0x00, 0x42, 0x10, 0x41, 0x50),
+            ],
+        );
+    }
+
+    #[test]
+    fn decimal_adjust_cases() {
+        run_cases(
+            |registers, _| decimal_adjust(registers),
+            &[
+                // 0x45 + 0x38
+                (0x7D, 0x00, 0x00, 0x83, 0x00),
+                (0x9A, 0x00, 0x00, 0x00, 0x90),
+                (0x0A, 0x00, 0x00, 0x10, 0x00),
+                // 0x83 - 0x38
+                (0x4B, 0x00, 0x60, 0x45, 0x40),
+                (0x00, 0x00, 0x50, 0xA0, 0x50),
+            ],
+        );
+    }
+
+    #[test]
+    fn flag_operation_cases() {
+        run_cases(
+            |registers, _| complement(registers),
+            &[
+                (0x35, 0x00, 0x90, 0xCA, 0xF0),
+                (0xFF, 0x00, 0x00, 0x00, 0x60),
+            ],
+        );
+        run_cases(
+            |registers, _| set_carry_flag(registers),
+            &[
+                (0x00, 0x00, 0xE0, 0x00, 0x90),
+                (0x00, 0x00, 0x10, 0x00, 0x10),
+            ],
+        );
+        run_cases(
+            |registers, _| complement_carry_flag(registers),
+            &[
+                (0x00, 0x00, 0x70, 0x00, 0x00),
+                (0x00, 0x00, 0x80, 0x00, 0x90),
+            ],
+        );
+    }
+
+    #[test]
+    fn add_word_cases() {
+        // Each case is (HL, operand, F before, HL after, F after)
+        let cases: [(u16, u16, u8, u16, u8); 5] = [
+            (0x8A23, 0x0605, 0x00, 0x9028, 0x20),
+            (0x8A23, 0x8A23, 0x00, 0x1446, 0x30),
+            (0xFFFF, 0x0001, 0xC0, 0x0000, 0xB0),
+            (0x1234, 0x0001, 0x50, 0x1235, 0x00),
+            (0x0000, 0x0000, 0x80, 0x0000, 0x80),
+        ];
+
+        for &(hl, operand, flags, expected_hl, expected_flags) in cases.iter() {
+            let mut registers = RegisterFile::new();
+            registers.write_register(Register::HL, hl);
+            registers.write_register(Register::F, flags as u16);
+
+            add_word(&mut registers, operand);
+
+            let case = format!("HL={:#06X} operand={:#06X}", hl, operand);
+            assert_eq!(
+                registers.read_register(Register::HL),
+                expected_hl,
+                "{}",
+                case
+            );
+            assert_eq!(
+                registers.read_register(Register::F),
+                expected_flags as u16,
+                "{}",
+                case
+            );
+        }
+    }
+
+    #[test]
+    fn add_signed_byte_cases() {
+        // Each case is (SP, offset, SP + offset, F after). F always starts with every flag set.
+        let cases: [(u16, u8, u16, u8); 8] = [
+            (0xFFF8, 0x08, 0x0000, 0x30),
+            (0x0000, 0xFF, 0xFFFF, 0x00),
+            (0x0001, 0xFF, 0x0000, 0x30),
+            (0x00FF, 0x01, 0x0100, 0x30),
+            (0xFF00, 0x80, 0xFE80, 0x00),
+            (0x000F, 0x01, 0x0010, 0x20),
+            (0x1234, 0xF0, 0x1224, 0x10),
+            (0xD00F, 0xFE, 0xD00D, 0x30),
+        ];
+
+        for &(sp, offset, expected, expected_flags) in cases.iter() {
+            let mut registers = RegisterFile::new();
+            registers.write_register(Register::F, 0xF0);
+
+            let result = add_signed_byte(&mut registers, sp, offset);
+
+            let case = format!("SP={:#06X} offset={:#04X}", sp, offset);
+            assert_eq!(result, expected, "{}", case);
+            assert_eq!(
+                registers.read_register(Register::F),
+                expected_flags as u16,
+                "{}",
+                case
+            );
+        }
+    }
+}
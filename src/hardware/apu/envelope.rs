@@ -0,0 +1,110 @@
+// Volume control shared by the square and noise channels, set through NRx2. The volume starts at
+// the top 4 bits when the channel is started and moves up or down by one every period/64 seconds,
+// stopping at 0 or 15. A period of 0 leaves it where it is.
+pub(super) struct Envelope {
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub(super) fn new() -> Self {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub(super) fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    // The channel's DAC is off when the starting volume is 0 and the volume is going down, as
+    // then there's nothing to play
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    fn period(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    pub(super) fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub(super) fn trigger(&mut self) {
+        self.volume = self.register >> 4;
+        self.timer = self.period();
+    }
+
+    // Clocked at 64Hz by the frame sequencer
+    pub(super) fn clock(&mut self) {
+        if self.period() == 0 || self.timer == 0 {
+            return;
+        }
+
+        self.timer -= 1;
+        if self.timer > 0 {
+            return;
+        }
+        self.timer = self.period();
+
+        let increase = self.register & 0x08 != 0;
+        if increase && self.volume < 15 {
+            self.volume += 1;
+        } else if !increase && self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_moves_once_a_period() {
+        // Starting at 2, going down every 3 clocks
+        let mut envelope = Envelope::new();
+        envelope.write(0x23);
+        envelope.trigger();
+        assert_eq!(envelope.volume(), 2);
+
+        envelope.clock();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 2);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 1);
+
+        // Stops at 0
+        for _ in 0..10 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 0);
+    }
+
+    #[test]
+    fn volume_stops_at_15_going_up() {
+        let mut envelope = Envelope::new();
+        envelope.write(0xE9);
+        envelope.trigger();
+
+        for _ in 0..5 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 15);
+    }
+
+    #[test]
+    fn dac_is_off_with_nothing_to_play() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x07);
+        assert!(!envelope.dac_enabled());
+        envelope.write(0x08);
+        assert!(envelope.dac_enabled());
+        envelope.write(0x10);
+        assert!(envelope.dac_enabled());
+    }
+}
@@ -0,0 +1,81 @@
+// Silences a channel after a set time. It counts down at 256Hz while enabled, and turns the
+// channel off when it reaches 0.
+pub(super) struct LengthCounter {
+    counter: u16,
+    // 64 for most channels, 256 for the wave channel
+    max: u16,
+    enabled: bool,
+}
+
+impl LengthCounter {
+    pub(super) fn new(max: u16) -> Self {
+        LengthCounter {
+            counter: 0,
+            max,
+            enabled: false,
+        }
+    }
+
+    // The register holds how far through the length to start, rather than the length itself
+    pub(super) fn load(&mut self, value: u8) {
+        self.counter = self.max - (value as u16 & (self.max - 1));
+    }
+
+    pub(super) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    // A channel started with its length run out gets the full length
+    pub(super) fn trigger(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    // Returns true when the length runs out, turning the channel off
+    pub(super) fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn runs_out_after_the_loaded_length() {
+        let mut length = LengthCounter::new(64);
+        length.load(60);
+        length.set_enabled(true);
+
+        for _ in 0..3 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock());
+        assert!(!length.clock());
+
+        // Only counts while enabled
+        length.trigger();
+        length.set_enabled(false);
+        for _ in 0..100 {
+            assert!(!length.clock());
+        }
+    }
+
+    #[test]
+    fn trigger_reloads_an_empty_counter() {
+        let mut length = LengthCounter::new(256);
+        length.set_enabled(true);
+        length.trigger();
+
+        for _ in 0..255 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock());
+    }
+}
@@ -0,0 +1,369 @@
+mod envelope;
+mod length;
+mod noise;
+mod square;
+mod wave;
+
+use super::{bus::MemoryMapped, cpu::CLOCK_SPEED};
+
+use self::{noise::Noise, square::Square, wave::Wave};
+
+pub const NR10: u16 = 0xFF10;
+pub const NR14: u16 = 0xFF14;
+pub const NR24: u16 = 0xFF19;
+pub const NR34: u16 = 0xFF1E;
+pub const NR44: u16 = 0xFF23;
+pub const NR50: u16 = 0xFF24;
+pub const NR51: u16 = 0xFF25;
+pub const NR52: u16 = 0xFF26;
+pub const WAVE_RAM: u16 = 0xFF30;
+
+// Output samples per second, unless the frontend asks for something else
+pub const SAMPLE_RATE: u32 = 48_000;
+
+// Samples kept waiting to be taken before new ones are dropped, so nothing grows without bound
+// when no one is listening
+const MAX_BUFFERED_SAMPLES: usize = SAMPLE_RATE as usize;
+
+// Bits that always read as 1 in each register from NR10 to 0xFF2F, including the write-only ones
+// and the unused gaps
+const READ_MASKS: [u8; 0x20] = [
+    0x80, 0x3F, 0x00, 0xFF, 0xBF, // NR10-NR14
+    0xFF, 0x3F, 0x00, 0xFF, 0xBF, // NR20-NR24
+    0x7F, 0xFF, 0x9F, 0xFF, 0xBF, // NR30-NR34
+    0xFF, 0xFF, 0x00, 0x00, 0xBF, // NR40-NR44
+    0x00, 0x00, 0x70, // NR50-NR52
+    0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+];
+
+// The 4 sound channels, mixed into stereo through NR50 and NR51:
+//
+// 1. Square wave with a frequency sweep
+// 2. Square wave
+// 3. 4-bit samples from wave RAM
+// 4. Noise
+//
+// Everything besides the channels' frequency timers is clocked by the frame sequencer, which the
+// bus moves on at 512Hz off the timer's divider. Over its 8 steps it clocks the length counters
+// at 256Hz, the sweep at 128Hz and the envelopes at 64Hz.
+//
+// The APU always runs at the normal speed clock, even in double speed.
+pub struct Apu {
+    enabled: bool,
+    // What was last written to each register from NR10 to NR51, for reading back
+    registers: [u8; 0x20],
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    frame_step: u8,
+    sample_rate: u32,
+    // Counts up by the sample rate each T-cycle, taking a sample each time it passes the clock
+    // speed
+    sample_clock: u32,
+    samples: Vec<(f32, f32)>,
+}
+
+impl Default for Apu {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Apu {
+    pub fn new() -> Self {
+        Apu {
+            enabled: false,
+            registers: [0; 0x20],
+            square1: Square::new(true),
+            square2: Square::new(false),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            frame_step: 0,
+            sample_rate: SAMPLE_RATE,
+            sample_clock: 0,
+            samples: Vec::new(),
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: u32) {
+        self.sample_rate = sample_rate;
+    }
+
+    // Left and right samples from -1.0 to 1.0 produced since this was last called
+    pub fn take_samples(&mut self) -> Vec<(f32, f32)> {
+        std::mem::take(&mut self.samples)
+    }
+
+    // Switching off clears every register and stops the channels, leaving only wave RAM
+    fn power_off(&mut self) {
+        self.registers = [0; 0x20];
+        self.square1 = Square::new(true);
+        self.square2 = Square::new(false);
+        self.wave.power_off();
+        self.noise = Noise::new();
+    }
+
+    // Called by the bus at 512Hz
+    pub fn clock_frame_sequencer(&mut self) {
+        if !self.enabled {
+            return;
+        }
+
+        if self.frame_step & 0x01 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if self.frame_step == 2 || self.frame_step == 6 {
+            self.square1.clock_sweep();
+        }
+        if self.frame_step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+
+        self.frame_step = (self.frame_step + 1) % 8;
+    }
+
+    // Moves on by the given number of T-cycles
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            if self.enabled {
+                self.square1.tick();
+                self.square2.tick();
+                self.wave.tick();
+                self.noise.tick();
+            }
+
+            self.sample_clock += self.sample_rate;
+            if self.sample_clock >= CLOCK_SPEED {
+                self.sample_clock -= CLOCK_SPEED;
+                if self.samples.len() < MAX_BUFFERED_SAMPLES {
+                    let sample = self.sample();
+                    self.samples.push(sample);
+                }
+            }
+        }
+    }
+
+    // Each channel's DAC turns its 0-15 output into -1.0 to 1.0. NR51 picks which channels go to
+    // each side, and NR50 sets each side's volume from 1/8 to 8/8.
+    fn sample(&self) -> (f32, f32) {
+        let channels = [
+            (self.square1.dac_enabled(), self.square1.output()),
+            (self.square2.dac_enabled(), self.square2.output()),
+            (self.wave.dac_enabled(), self.wave.output()),
+            (self.noise.dac_enabled(), self.noise.output()),
+        ];
+        let panning = self.registers[(NR51 - NR10) as usize];
+        let volume = self.registers[(NR50 - NR10) as usize];
+
+        let (mut left, mut right) = (0.0, 0.0);
+        for (channel, &(dac_enabled, output)) in channels.iter().enumerate() {
+            if !dac_enabled {
+                continue;
+            }
+
+            let analogue = output as f32 / 7.5 - 1.0;
+            if panning & (0x10 << channel) != 0 {
+                left += analogue;
+            }
+            if panning & (0x01 << channel) != 0 {
+                right += analogue;
+            }
+        }
+
+        let left_volume = ((volume >> 4) & 0x07) as f32 + 1.0;
+        let right_volume = (volume & 0x07) as f32 + 1.0;
+        (
+            left / 4.0 * left_volume / 8.0,
+            right / 4.0 * right_volume / 8.0,
+        )
+    }
+
+    fn read_status(&self) -> u8 {
+        let channels = [
+            self.square1.enabled(),
+            self.square2.enabled(),
+            self.wave.enabled(),
+            self.noise.enabled(),
+        ];
+        let playing = channels
+            .iter()
+            .enumerate()
+            .fold(0, |bits, (channel, &enabled)| {
+                bits | (enabled as u8) << channel
+            });
+
+        (self.enabled as u8) << 7 | 0x70 | playing
+    }
+}
+
+impl MemoryMapped for Apu {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            NR52 => self.read_status(),
+            NR10..=0xFF2F => {
+                let index = (address - NR10) as usize;
+                self.registers[index] | READ_MASKS[index]
+            }
+            WAVE_RAM..=0xFF3F => self.wave.read_ram(address - WAVE_RAM),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            NR52 => {
+                let enabled = value & 0x80 != 0;
+                if self.enabled && !enabled {
+                    self.power_off();
+                } else if !self.enabled && enabled {
+                    self.frame_step = 0;
+                }
+                self.enabled = enabled;
+            }
+            WAVE_RAM..=0xFF3F => self.wave.write_ram(address - WAVE_RAM, value),
+            // Nothing else can be written while the APU is off
+            NR10..=NR51 if self.enabled => {
+                self.registers[(address - NR10) as usize] = value;
+                match address {
+                    0xFF10..=0xFF14 => self.square1.write(address - 0xFF10, value),
+                    0xFF15..=0xFF19 => self.square2.write(address - 0xFF15, value),
+                    0xFF1A..=0xFF1E => self.wave.write(address - 0xFF1A, value),
+                    0xFF1F..=0xFF23 => self.noise.write(address - 0xFF1F, value),
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn powered_apu() -> Apu {
+        let mut apu = Apu::new();
+        apu.write(NR52, 0x80);
+        apu
+    }
+
+    #[test]
+    fn registers_read_back_with_unused_bits_set() {
+        let mut apu = powered_apu();
+        for address in NR10..=NR51 {
+            apu.write(address, 0x00);
+        }
+
+        let values: Vec<u8> = (NR10..=0xFF2F).map(|address| apu.read(address)).collect();
+        let mut expected = READ_MASKS.to_vec();
+        expected[(NR52 - NR10) as usize] = 0xF0;
+        assert_eq!(values, expected);
+
+        apu.write(0xFF11, 0xC5);
+        assert_eq!(apu.read(0xFF11), 0xFF);
+        apu.write(0xFF24, 0x5A);
+        assert_eq!(apu.read(0xFF24), 0x5A);
+    }
+
+    #[test]
+    fn status_shows_playing_channels() {
+        let mut apu = powered_apu();
+        apu.write(0xFF17, 0xF0);
+        apu.write(NR24, 0x80);
+        apu.write(0xFF21, 0xF0);
+        apu.write(NR44, 0x80);
+
+        assert_eq!(apu.read(NR52), 0xFA);
+    }
+
+    #[test]
+    fn powering_off_clears_registers_but_not_wave_ram() {
+        let mut apu = powered_apu();
+        apu.write(NR50, 0x77);
+        apu.write(0xFF12, 0xF0);
+        apu.write(NR14, 0x80);
+        apu.write(WAVE_RAM, 0x12);
+
+        apu.write(NR52, 0x00);
+        assert_eq!(apu.read(NR52), 0x70);
+        assert_eq!(apu.read(NR50), 0x00);
+        assert_eq!(apu.read(WAVE_RAM), 0x12);
+
+        // Writes are ignored until it's back on
+        apu.write(NR50, 0x77);
+        assert_eq!(apu.read(NR50), 0x00);
+        apu.write(NR52, 0x80);
+        apu.write(NR50, 0x77);
+        assert_eq!(apu.read(NR50), 0x77);
+    }
+
+    #[test]
+    fn frame_sequencer_clocks_length_at_256hz() {
+        let mut apu = powered_apu();
+        apu.write(0xFF12, 0xF0);
+        // 2 clocks of length left
+        apu.write(0xFF11, 0x3E);
+        apu.write(NR14, 0xC0);
+
+        // Steps 0 and 2 clock length, step 1 doesn't
+        apu.clock_frame_sequencer();
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52) & 0x01, 0x01);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.read(NR52) & 0x01, 0x00);
+    }
+
+    #[test]
+    fn frame_sequencer_clocks_envelope_on_its_last_step() {
+        let mut apu = powered_apu();
+        apu.write(NR51, 0x11);
+        apu.write(NR50, 0x77);
+        // Volume 15 going down every clock, on the high part of a 75% wave
+        apu.write(0xFF11, 0xC0);
+        apu.write(0xFF12, 0xF1);
+        apu.write(0xFF13, 0xFF);
+        apu.write(NR14, 0x87);
+        apu.tick(8);
+        assert_eq!(apu.square1.output(), 15);
+
+        for _ in 0..7 {
+            apu.clock_frame_sequencer();
+        }
+        assert_eq!(apu.square1.output(), 15);
+        apu.clock_frame_sequencer();
+        assert_eq!(apu.square1.output(), 14);
+    }
+
+    #[test]
+    fn samples_are_panned_and_scaled() {
+        let mut apu = powered_apu();
+        // Channel 2 at full volume on a wave that's high for 6 of every 8 steps, left only
+        apu.write(0xFF16, 0xC0);
+        apu.write(0xFF17, 0xF0);
+        apu.write(0xFF18, 0xFF);
+        apu.write(NR24, 0x87);
+        apu.write(NR51, 0x20);
+        apu.write(NR50, 0x37);
+        apu.tick(8);
+
+        let (left, right) = apu.sample();
+        assert_eq!(left, 1.0 / 4.0 * 4.0 / 8.0);
+        assert_eq!(right, 0.0);
+    }
+
+    #[test]
+    fn produces_samples_at_the_sample_rate() {
+        let mut apu = Apu::new();
+        apu.set_sample_rate(44_100);
+        apu.tick(CLOCK_SPEED);
+
+        assert_eq!(apu.take_samples().len(), 44_100);
+        assert!(apu.take_samples().is_empty());
+    }
+}
@@ -0,0 +1,188 @@
+use super::{envelope::Envelope, length::LengthCounter};
+
+// Base periods in T-cycles for the divisor codes in NR43
+const DIVISORS: [u32; 8] = [8, 16, 32, 48, 64, 80, 96, 112];
+
+// Channel 4, which plays pseudo-random noise from a linear feedback shift register. Each time the
+// frequency timer runs out the register shifts right, feeding the XOR of its lowest 2 bits back
+// in at the top. In 7-bit mode the feedback also goes into bit 6, giving a shorter, more tonal
+// pattern.
+pub(super) struct Noise {
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    // NR43: clock shift in the top 4 bits, width in bit 3 and divisor code in the bottom 3
+    register: u8,
+    timer: u32,
+    lfsr: u16,
+}
+
+impl Noise {
+    pub(super) fn new() -> Self {
+        Noise {
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            register: 0,
+            timer: 0,
+            lfsr: 0,
+        }
+    }
+
+    pub(super) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn shift(&self) -> u8 {
+        self.register >> 4
+    }
+
+    fn period(&self) -> u32 {
+        DIVISORS[(self.register & 0x07) as usize] << self.shift()
+    }
+
+    // Takes the register's position within the channel's 5, from NR40 to NR44
+    pub(super) fn write(&mut self, register: u16, value: u8) {
+        match register {
+            1 => self.length.load(value & 0x3F),
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.register = value,
+            4 => {
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+        self.lfsr = 0x7FFF;
+    }
+
+    // Moves on by one T-cycle
+    pub(super) fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = self.period();
+
+        // Shifts of 14 and 15 leave the register without a clock
+        if self.shift() >= 14 {
+            return;
+        }
+
+        let feedback = (self.lfsr ^ (self.lfsr >> 1)) & 0x01;
+        self.lfsr = (self.lfsr >> 1) | (feedback << 14);
+        if self.register & 0x08 != 0 {
+            self.lfsr = (self.lfsr & !0x40) | (feedback << 6);
+        }
+    }
+
+    // Clocked at 256Hz by the frame sequencer
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // Clocked at 64Hz by the frame sequencer
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // 0-15, before the DAC. The channel is high while bit 0 of the register is clear.
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled || self.lfsr & 0x01 != 0 {
+            return 0;
+        }
+
+        self.envelope.volume()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Steps the register until its pattern repeats, returning how long that took
+    fn pattern_length(register: u8) -> usize {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0);
+        noise.write(3, register);
+        noise.write(4, 0x80);
+
+        let start = noise.lfsr;
+        let period = noise.period();
+        let mut steps = 0;
+        loop {
+            for _ in 0..period {
+                noise.tick();
+            }
+            steps += 1;
+            // The 7-bit pattern only repeats in the bottom 7 bits
+            let mask = if register & 0x08 != 0 { 0x7F } else { 0x7FFF };
+            if noise.lfsr & mask == start & mask && steps > 1 {
+                return steps;
+            }
+        }
+    }
+
+    #[test]
+    fn lfsr_repeats_after_its_width() {
+        assert_eq!(pattern_length(0x00), 0x7FFF);
+        assert_eq!(pattern_length(0x08), 0x7F);
+    }
+
+    #[test]
+    fn period_comes_from_divisor_and_shift() {
+        let mut noise = Noise::new();
+        noise.write(3, 0x00);
+        assert_eq!(noise.period(), 8);
+        noise.write(3, 0x35);
+        assert_eq!(noise.period(), 80 << 3);
+    }
+
+    #[test]
+    fn high_shifts_stop_the_register() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xF0);
+        noise.write(3, 0xE0);
+        noise.write(4, 0x80);
+
+        for _ in 0..noise.period() * 3 {
+            noise.tick();
+        }
+        assert_eq!(noise.lfsr, 0x7FFF);
+    }
+
+    #[test]
+    fn output_follows_the_register() {
+        let mut noise = Noise::new();
+        noise.write(2, 0xA0);
+        noise.write(4, 0x80);
+        assert_eq!(noise.output(), 0);
+
+        // 0x7FFF shifts to 0x3FFF, with 0 fed back in, so bit 0 stays set for 14 steps
+        for _ in 0..8 * 15 {
+            noise.tick();
+        }
+        assert_eq!(noise.lfsr & 0x01, 0);
+        assert_eq!(noise.output(), 10);
+    }
+}
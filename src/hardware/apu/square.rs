@@ -0,0 +1,310 @@
+use super::{envelope::Envelope, length::LengthCounter};
+
+// Which of the 8 steps of a wave are high, for each duty cycle in NRx1
+const DUTY_PATTERNS: [[u8; 8]; 4] = [
+    [0, 0, 0, 0, 0, 0, 0, 1], // 12.5%
+    [1, 0, 0, 0, 0, 0, 0, 1], // 25%
+    [1, 0, 0, 0, 0, 1, 1, 1], // 50%
+    [0, 1, 1, 1, 1, 1, 1, 0], // 75%
+];
+
+// Channel 1 can sweep its frequency up or down, set through NR10. Each sweep period it works out
+// a new frequency from a shadow copy of the old one, and turns the channel off if that would go
+// past the top of the range.
+struct Sweep {
+    register: u8,
+    timer: u8,
+    enabled: bool,
+    shadow: u16,
+    // Set once a frequency has been worked out going down, after which switching to going up
+    // turns the channel off
+    negate_used: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        (self.register >> 4) & 0x07
+    }
+
+    fn negate(&self) -> bool {
+        self.register & 0x08 != 0
+    }
+
+    fn shift(&self) -> u8 {
+        self.register & 0x07
+    }
+
+    // A period of 0 is treated as 8 by the timer
+    fn reload_timer(&mut self) {
+        self.timer = match self.period() {
+            0 => 8,
+            period => period,
+        };
+    }
+
+    fn next_frequency(&mut self) -> u16 {
+        let delta = self.shadow >> self.shift();
+        if self.negate() {
+            self.negate_used = true;
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+// Channels 1 and 2, which play a square wave with one of 4 duty cycles. The frequency timer
+// moves through the 8 steps of the wave every (2048 - frequency) * 4 T-cycles.
+pub(super) struct Square {
+    sweep: Option<Sweep>,
+    enabled: bool,
+    length: LengthCounter,
+    envelope: Envelope,
+    duty: u8,
+    duty_step: u8,
+    frequency: u16,
+    timer: u16,
+}
+
+impl Square {
+    pub(super) fn new(sweep: bool) -> Self {
+        Square {
+            sweep: sweep.then_some(Sweep {
+                register: 0,
+                timer: 0,
+                enabled: false,
+                shadow: 0,
+                negate_used: false,
+            }),
+            enabled: false,
+            length: LengthCounter::new(64),
+            envelope: Envelope::new(),
+            duty: 0,
+            duty_step: 0,
+            frequency: 0,
+            timer: 0,
+        }
+    }
+
+    pub(super) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.envelope.dac_enabled()
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 4
+    }
+
+    // Takes the register's position within the channel's 5, from NRx0 to NRx4
+    pub(super) fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                if let Some(sweep) = &mut self.sweep {
+                    let was_negate = sweep.negate();
+                    sweep.register = value;
+                    if was_negate && !sweep.negate() && sweep.negate_used {
+                        self.enabled = false;
+                    }
+                }
+            }
+            1 => {
+                self.duty = value >> 6;
+                self.length.load(value & 0x3F);
+            }
+            2 => {
+                self.envelope.write(value);
+                if !self.dac_enabled() {
+                    self.enabled = false;
+                }
+            }
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | ((value & 0x07) as u16) << 8;
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Starts the channel playing, as long as its DAC is on
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled();
+        self.length.trigger();
+        self.envelope.trigger();
+        self.timer = self.period();
+
+        if let Some(sweep) = &mut self.sweep {
+            sweep.shadow = self.frequency;
+            sweep.negate_used = false;
+            sweep.reload_timer();
+            sweep.enabled = sweep.period() != 0 || sweep.shift() != 0;
+            if sweep.shift() != 0 && sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // Moves on by one T-cycle
+    pub(super) fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period();
+        self.duty_step = (self.duty_step + 1) % 8;
+    }
+
+    // Clocked at 256Hz by the frame sequencer
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // Clocked at 64Hz by the frame sequencer
+    pub(super) fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    // Clocked at 128Hz by the frame sequencer
+    pub(super) fn clock_sweep(&mut self) {
+        let Some(sweep) = &mut self.sweep else {
+            return;
+        };
+
+        sweep.timer = sweep.timer.saturating_sub(1);
+        if sweep.timer > 0 {
+            return;
+        }
+        sweep.reload_timer();
+        if !sweep.enabled || sweep.period() == 0 {
+            return;
+        }
+
+        // The new frequency is checked again straight away, which can turn the channel off a
+        // period early
+        let frequency = sweep.next_frequency();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if sweep.shift() != 0 {
+            sweep.shadow = frequency;
+            self.frequency = frequency;
+            if sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    // 0-15, before the DAC
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        DUTY_PATTERNS[self.duty as usize][self.duty_step as usize] * self.envelope.volume()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn playing_square(sweep: bool) -> Square {
+        let mut square = Square::new(sweep);
+        square.write(2, 0xF0);
+        square
+    }
+
+    #[test]
+    fn steps_through_the_duty_cycle() {
+        let mut square = playing_square(false);
+        // 50% duty, frequency 2047 so a step every 4 T-cycles
+        square.write(1, 0x80);
+        square.write(3, 0xFF);
+        square.write(4, 0x87);
+        assert!(square.enabled());
+
+        let mut steps = Vec::new();
+        for _ in 0..8 {
+            for _ in 0..4 {
+                square.tick();
+            }
+            steps.push(square.output());
+        }
+        assert_eq!(steps, [0, 0, 0, 0, 15, 15, 15, 15]);
+    }
+
+    #[test]
+    fn dac_off_stops_the_channel() {
+        let mut square = playing_square(false);
+        square.write(4, 0x80);
+        assert!(square.enabled());
+
+        square.write(2, 0x00);
+        assert!(!square.enabled());
+        square.write(4, 0x80);
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn length_stops_the_channel() {
+        let mut square = playing_square(false);
+        square.write(1, 0x3E);
+        square.write(4, 0xC0);
+
+        square.clock_length();
+        assert!(square.enabled());
+        square.clock_length();
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn sweep_raises_the_frequency() {
+        let mut square = playing_square(true);
+        // Every sweep clock, adding frequency / 2
+        square.write(0, 0x11);
+        square.write(3, 0x00);
+        square.write(4, 0x81);
+
+        for frequency in [0x180, 0x240, 0x360, 0x510] {
+            square.clock_sweep();
+            assert_eq!(square.frequency, frequency);
+            assert!(square.enabled());
+        }
+
+        // 0x798 next, but the check beyond that overflows
+        square.clock_sweep();
+        assert_eq!(square.frequency, 0x798);
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn sweep_overflow_on_trigger_stops_the_channel() {
+        let mut square = playing_square(true);
+        square.write(0, 0x01);
+        square.write(3, 0xFF);
+        square.write(4, 0x87);
+
+        assert!(!square.enabled());
+    }
+
+    #[test]
+    fn leaving_negate_mode_after_using_it_stops_the_channel() {
+        let mut square = playing_square(true);
+        square.write(0, 0x19);
+        square.write(3, 0x00);
+        square.write(4, 0x84);
+        square.clock_sweep();
+        assert!(square.enabled());
+
+        square.write(0, 0x11);
+        assert!(!square.enabled());
+    }
+}
@@ -0,0 +1,208 @@
+use super::length::LengthCounter;
+
+// How far each sample is shifted right for the volume codes in NR32: mute, 100%, 50% and 25%
+const VOLUME_SHIFTS: [u8; 4] = [4, 0, 1, 2];
+
+// Channel 3, which plays the 32 4-bit samples in wave RAM, high nibble first. The frequency timer
+// moves on a sample every (2048 - frequency) * 2 T-cycles.
+pub(super) struct Wave {
+    dac_enabled: bool,
+    enabled: bool,
+    length: LengthCounter,
+    volume_code: u8,
+    frequency: u16,
+    timer: u16,
+    position: u8,
+    sample: u8,
+    ram: [u8; 16],
+}
+
+impl Wave {
+    pub(super) fn new() -> Self {
+        Wave {
+            dac_enabled: false,
+            enabled: false,
+            length: LengthCounter::new(256),
+            volume_code: 0,
+            frequency: 0,
+            timer: 0,
+            position: 0,
+            sample: 0,
+            ram: [0; 16],
+        }
+    }
+
+    // Switching the APU off resets the channel, but wave RAM keeps its contents
+    pub(super) fn power_off(&mut self) {
+        *self = Wave {
+            ram: self.ram,
+            ..Wave::new()
+        };
+    }
+
+    pub(super) fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(super) fn dac_enabled(&self) -> bool {
+        self.dac_enabled
+    }
+
+    fn period(&self) -> u16 {
+        (2048 - self.frequency) * 2
+    }
+
+    // While the channel plays, wave RAM can only reach the byte being played, wherever the
+    // access is aimed
+    fn ram_index(&self, offset: u16) -> usize {
+        if self.enabled {
+            self.position as usize / 2
+        } else {
+            offset as usize
+        }
+    }
+
+    pub(super) fn read_ram(&self, offset: u16) -> u8 {
+        self.ram[self.ram_index(offset)]
+    }
+
+    pub(super) fn write_ram(&mut self, offset: u16, value: u8) {
+        let index = self.ram_index(offset);
+        self.ram[index] = value;
+    }
+
+    // Takes the register's position within the channel's 5, from NR30 to NR34
+    pub(super) fn write(&mut self, register: u16, value: u8) {
+        match register {
+            0 => {
+                self.dac_enabled = value & 0x80 != 0;
+                if !self.dac_enabled {
+                    self.enabled = false;
+                }
+            }
+            1 => self.length.load(value),
+            2 => self.volume_code = (value >> 5) & 0x03,
+            3 => self.frequency = (self.frequency & 0x0700) | value as u16,
+            4 => {
+                self.frequency = (self.frequency & 0x00FF) | ((value & 0x07) as u16) << 8;
+                self.length.set_enabled(value & 0x40 != 0);
+                if value & 0x80 != 0 {
+                    self.trigger();
+                }
+            }
+            _ => (),
+        }
+    }
+
+    // Starts from the first sample, though the one already read keeps playing until the timer
+    // next runs out
+    fn trigger(&mut self) {
+        self.enabled = self.dac_enabled;
+        self.length.trigger();
+        self.timer = self.period();
+        self.position = 0;
+    }
+
+    // Moves on by one T-cycle
+    pub(super) fn tick(&mut self) {
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+
+        self.timer = self.period();
+        self.position = (self.position + 1) % 32;
+        let byte = self.ram[self.position as usize / 2];
+        self.sample = if self.position & 0x01 == 0 {
+            byte >> 4
+        } else {
+            byte & 0x0F
+        };
+    }
+
+    // Clocked at 256Hz by the frame sequencer
+    pub(super) fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    // 0-15, before the DAC
+    pub(super) fn output(&self) -> u8 {
+        if !self.enabled {
+            return 0;
+        }
+
+        self.sample >> VOLUME_SHIFTS[self.volume_code as usize]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wave_with_ram() -> Wave {
+        let mut wave = Wave::new();
+        for offset in 0..16 {
+            let first = offset as u8 * 2 % 16;
+            wave.write_ram(offset, first << 4 | (first + 1));
+        }
+        wave.write(0, 0x80);
+        // Frequency 2047, so a sample every 2 T-cycles
+        wave.write(3, 0xFF);
+        wave
+    }
+
+    #[test]
+    fn plays_wave_ram_in_order() {
+        let mut wave = wave_with_ram();
+        wave.write(2, 0x20);
+        wave.write(4, 0x87);
+
+        let mut samples = Vec::new();
+        for _ in 0..32 {
+            wave.tick();
+            wave.tick();
+            samples.push(wave.output());
+        }
+        let expected: Vec<u8> = (1..32).chain([0]).map(|sample| sample % 16).collect();
+        assert_eq!(samples, expected);
+    }
+
+    #[test]
+    fn volume_code_shifts_samples() {
+        for (volume_code, expected) in [(0, 0), (1, 15), (2, 7), (3, 3)] {
+            let mut wave = wave_with_ram();
+            wave.write_ram(0, 0xF0);
+            wave.write(2, volume_code << 5);
+            wave.write(4, 0x87);
+            // Back round to the start
+            for _ in 0..64 {
+                wave.tick();
+            }
+            assert_eq!(wave.output(), expected, "volume code {}", volume_code);
+        }
+    }
+
+    #[test]
+    fn ram_reaches_the_playing_byte_while_enabled() {
+        let mut wave = wave_with_ram();
+        wave.write(4, 0x87);
+        for _ in 0..6 {
+            wave.tick();
+        }
+
+        assert_eq!(wave.read_ram(0), 0x23);
+        wave.write(0, 0x00);
+        assert_eq!(wave.read_ram(0), 0x01);
+    }
+
+    #[test]
+    fn power_off_keeps_wave_ram() {
+        let mut wave = wave_with_ram();
+        wave.power_off();
+
+        assert!(!wave.dac_enabled());
+        assert_eq!(wave.read_ram(15), 0xEF);
+    }
+}
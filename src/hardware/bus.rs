@@ -0,0 +1,886 @@
+use std::{error::Error, fmt};
+
+use super::{
+    apu::{Apu, NR10, NR14, NR24, NR34, NR44, NR52},
+    dma::{OamDma, DMA},
+    hdma::{Hdma, BLOCK_LENGTH, HDMA1, HDMA5},
+    interrupts::{Interrupt, InterruptController, INTERRUPT_ENABLE, INTERRUPT_FLAG},
+    model::Model,
+    ppu::{Ppu, Renderer, VBK},
+    timer::{Timer, DIV, TAC},
+};
+
+// Anything that can sit on the bus. Components are given the full 16-bit address, so a component
+// mapped at 0xC000 sees reads starting at 0xC000 rather than 0.
+pub trait MemoryMapped {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+
+    // Called at the end of every frame, for anything that has to happen periodically whether or
+    // not the CPU touches the component
+    fn end_frame(&mut self) {}
+}
+
+// A plain block of RAM mapped at a fixed start address
+pub struct Ram {
+    start: u16,
+    data: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(start: u16, size: usize) -> Self {
+        Ram {
+            start,
+            data: vec![0; size],
+        }
+    }
+}
+
+impl MemoryMapped for Ram {
+    fn read(&self, address: u16) -> u8 {
+        self.data[(address - self.start) as usize]
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        self.data[(address - self.start) as usize] = value;
+    }
+}
+
+// Value seen when reading an address nothing drives
+const OPEN_BUS: u8 = 0xFF;
+
+// Writing a non-zero value here unmaps the boot ROM until the next reset
+const BOOT_ROM_DISABLE: u16 = 0xFF50;
+
+// Written by the CGB boot ROM to switch to DMG compatibility mode when running a DMG game
+const KEY0: u16 = 0xFF4C;
+
+// Arms a switch between normal and double speed in CGB mode, which the next STOP carries out.
+// Reads back the current speed in bit 7.
+const KEY1: u16 = 0xFF4D;
+
+// T-cycles the CPU is halted for while switching speed
+const SPEED_SWITCH_CYCLES: u32 = 2050 * 4;
+
+// T-cycles at normal speed taken to copy a block with VRAM DMA. It takes the same time at double
+// speed, which is twice as many CPU cycles.
+const HDMA_BLOCK_CYCLES: u32 = 8 * 4;
+
+// Selects the work RAM bank at 0xD000-0xDFFF in CGB mode
+const SVBK: u16 = 0xFF70;
+
+#[derive(Debug, PartialEq)]
+pub struct BootRomSizeError {
+    pub model: Model,
+    pub expected: usize,
+    pub actual: usize,
+}
+
+impl fmt::Display for BootRomSizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} boot ROM should be {} bytes but is {}",
+            self.model, self.expected, self.actual
+        )
+    }
+}
+
+impl Error for BootRomSizeError {}
+
+// Routes the 16-bit address space to the components mapped into it:
+//
+// 0x0000-0x7FFF  Cartridge ROM
+// 0x8000-0x9FFF  Video RAM
+// 0xA000-0xBFFF  Cartridge RAM
+// 0xC000-0xCFFF  Work RAM bank 0
+// 0xD000-0xDFFF  Work RAM bank 1, or 1-7 in CGB mode
+// 0xE000-0xFDFF  Echo of 0xC000-0xDDFF
+// 0xFE00-0xFE9F  Object attribute memory
+// 0xFEA0-0xFEFF  Unusable
+// 0xFF00-0xFF7F  I/O registers
+// 0xFF80-0xFFFE  High RAM
+// 0xFFFF         Interrupt enable
+//
+// While the boot ROM is mapped it covers 0x0000-0x00FF, and a CGB boot ROM also covers
+// 0x0200-0x08FF. The cartridge header at 0x0100-0x01FF stays visible so the boot ROM can check
+// it.
+pub struct Bus {
+    model: Model,
+    // Whether CGB features are switched on. CGB hardware turns them off for DMG games.
+    cgb_mode: bool,
+    boot_rom: Option<Vec<u8>>,
+    cartridge: Option<Box<dyn MemoryMapped>>,
+    ppu: Ppu,
+    // 8 banks of 4KB, of which the DMG only has the first 2
+    work_ram: Vec<u8>,
+    work_ram_bank: u8,
+    io_registers: Ram,
+    high_ram: Ram,
+    interrupts: InterruptController,
+    timer: Timer,
+    apu: Apu,
+    oam_dma: OamDma,
+    hdma: Hdma,
+    // In double speed the CPU, OAM DMA and timer run twice as fast, while the PPU and APU keep
+    // going at the same rate
+    double_speed: bool,
+    speed_switch_armed: bool,
+    // CPU T-cycles the CPU has to sit out because DMA or a speed switch has halted it
+    stall_cycles: u32,
+    // CPU T-cycles from the last tick that didn't make up a whole M-cycle
+    partial_cycles: u32,
+}
+
+impl Bus {
+    pub fn new(model: Model) -> Self {
+        Self::with_renderer(model, Renderer::Scanline)
+    }
+
+    pub fn with_renderer(model: Model, renderer: Renderer) -> Self {
+        Bus {
+            model,
+            cgb_mode: model.is_cgb(),
+            boot_rom: None,
+            cartridge: None,
+            ppu: Ppu::new(model, renderer),
+            work_ram: vec![0; 0x8000],
+            work_ram_bank: 1,
+            io_registers: Ram::new(0xFF00, 0x80),
+            high_ram: Ram::new(0xFF80, 0x7F),
+            interrupts: InterruptController::new(),
+            timer: Timer::new(),
+            apu: Apu::new(),
+            oam_dma: OamDma::new(),
+            hdma: Hdma::new(),
+            double_speed: false,
+            speed_switch_armed: false,
+            stall_cycles: 0,
+            partial_cycles: 0,
+        }
+    }
+
+    // The cartridge sees both the ROM (0x0000-0x7FFF) and external RAM (0xA000-0xBFFF) ranges
+    pub fn insert_cartridge(&mut self, cartridge: Box<dyn MemoryMapped>) {
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn end_frame(&mut self) {
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.end_frame();
+        }
+    }
+
+    pub fn model(&self) -> Model {
+        self.model
+    }
+
+    pub fn cgb_mode(&self) -> bool {
+        self.cgb_mode
+    }
+
+    pub fn double_speed(&self) -> bool {
+        self.double_speed
+    }
+
+    // Called on STOP. Switches speed if KEY1 has asked for it, halting the CPU for a while, and
+    // returns whether it did.
+    pub fn switch_speed(&mut self) -> bool {
+        if !(self.cgb_mode && self.speed_switch_armed) {
+            return false;
+        }
+
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+        self.stall_cycles += SPEED_SWITCH_CYCLES;
+        true
+    }
+
+    // CPU T-cycles the CPU has been halted for since this was last called
+    pub fn take_stall_cycles(&mut self) -> u32 {
+        std::mem::take(&mut self.stall_cycles)
+    }
+
+    // Maps a boot ROM over the start of the cartridge. It has to be the right size for the model:
+    // 256 bytes, or 2304 bytes for CGB hardware.
+    pub fn insert_boot_rom(&mut self, boot_rom: Vec<u8>) -> Result<(), BootRomSizeError> {
+        let expected = self.model.boot_rom_size();
+        if boot_rom.len() != expected {
+            return Err(BootRomSizeError {
+                model: self.model,
+                expected,
+                actual: boot_rom.len(),
+            });
+        }
+
+        self.boot_rom = Some(boot_rom);
+        Ok(())
+    }
+
+    // Does the parts of the boot ROM's job that the rest of the hardware can see: setting up the
+    // I/O registers, and on CGB hardware deciding whether to run in DMG compatibility mode
+    pub fn skip_boot_rom(&mut self) {
+        self.set_cgb_mode(self.model.is_cgb() && self.read(0x0143) & 0x80 != 0);
+
+        // Sound has to be switched on before its other registers can be written, so NR52 goes
+        // first. Setting up the PPU registers can raise a STAT interrupt the boot ROM wouldn't
+        // have left behind, so IF goes last.
+        let mut registers = self.model.post_boot_io_registers();
+        registers.sort_by_key(|&(address, _)| match address {
+            NR52 => 0,
+            INTERRUPT_FLAG => 2,
+            _ => 1,
+        });
+
+        let mut playing = 0;
+        for (address, value) in registers {
+            match address {
+                // Writing DIV would reset it
+                DIV => self.timer.set_divider((value as u16) << 8),
+                NR52 => {
+                    self.write(address, value);
+                    playing = value & 0x0F;
+                }
+                // Channels are only started once everything else is set up, and only the ones
+                // NR52 says the boot ROM left playing
+                NR14 | NR24 | NR34 | NR44 => self.write(address, value & 0x7F),
+                _ => self.write(address, value),
+            }
+        }
+        for (channel, &address) in [NR14, NR24, NR34, NR44].iter().enumerate() {
+            if playing & (1 << channel) != 0 {
+                self.write(address, self.read(address) | 0x80);
+            }
+        }
+    }
+
+    fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+        self.ppu.set_cgb_mode(cgb_mode);
+        if !cgb_mode {
+            self.work_ram_bank = 1;
+        }
+    }
+
+    // Offset into work RAM of an address in 0xC000-0xDFFF
+    fn work_ram_offset(&self, address: u16) -> usize {
+        match address {
+            0xC000..=0xCFFF => (address - 0xC000) as usize,
+            _ => self.work_ram_bank as usize * 0x1000 + (address - 0xD000) as usize,
+        }
+    }
+
+    pub fn boot_rom_mapped(&self) -> bool {
+        self.boot_rom.is_some()
+    }
+
+    fn read_boot_rom(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0100..=0x01FF => None,
+            _ => self.boot_rom.as_ref()?.get(address as usize).copied(),
+        }
+    }
+
+    // Moves the rest of the hardware on by the given number of CPU T-cycles, which in double
+    // speed are half as long
+    pub fn tick(&mut self, cycles: u32) {
+        // OAM DMA and the timer move on a whole M-cycle at a time, so any cycles short of one are
+        // kept for the next tick
+        let cpu_cycles = self.partial_cycles + cycles;
+        self.partial_cycles = cpu_cycles % 4;
+        for _ in 0..cpu_cycles / 4 {
+            if let Some((source, index)) = self.oam_dma.tick() {
+                let value = self.read_direct(source);
+                self.oam_dma.set_last_byte(value);
+                self.ppu.write_oam(index, value);
+            }
+
+            let divider = self.timer.divider();
+            self.timer.tick(4);
+            self.clock_frame_sequencer(divider);
+        }
+
+        if let Some(interrupt) = self.timer.take_interrupt() {
+            self.interrupts.request(interrupt);
+        }
+
+        let dots = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        // A long tick, like a speed switch, can cover several lines, each of which gets a block
+        for _ in 0..dots {
+            self.ppu.tick(1);
+            if self.ppu.take_hblank() && self.hdma.hblank_active() {
+                self.copy_hdma_block();
+            }
+        }
+        self.request_ppu_interrupts();
+        self.apu.tick(dots);
+    }
+
+    // The APU's frame sequencer moves on when bit 4 of DIV falls, or bit 5 in double speed so it
+    // keeps to 512Hz. Resetting DIV can make the bit fall early.
+    fn clock_frame_sequencer(&mut self, old_divider: u16) {
+        let bit = if self.double_speed { 13 } else { 12 };
+        if old_divider & (1 << bit) != 0 && self.timer.divider() & (1 << bit) == 0 {
+            self.apu.clock_frame_sequencer();
+        }
+    }
+
+    fn copy_hdma_block(&mut self) {
+        let (source, destination) = self.hdma.next_block();
+        for offset in 0..BLOCK_LENGTH {
+            let value = self.read_direct(source.wrapping_add(offset));
+            self.ppu
+                .write_video_ram(0x8000 + destination + offset, value);
+        }
+
+        self.stall_cycles += if self.double_speed {
+            HDMA_BLOCK_CYCLES * 2
+        } else {
+            HDMA_BLOCK_CYCLES
+        };
+    }
+
+    fn request_ppu_interrupts(&mut self) {
+        for interrupt in self.ppu.take_interrupts() {
+            self.interrupts.request(interrupt);
+        }
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupts.request(interrupt);
+    }
+
+    pub fn pending_interrupt(&self) -> Option<Interrupt> {
+        self.interrupts.pending()
+    }
+
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupts.acknowledge(interrupt);
+    }
+
+    // Reads without going through OAM DMA's lock on the bus
+    fn read_direct(&self, address: u16) -> u8 {
+        if let Some(byte) = self.read_boot_rom(address) {
+            return byte;
+        }
+
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => match &self.cartridge {
+                Some(cartridge) => cartridge.read(address),
+                None => OPEN_BUS,
+            },
+            0x8000..=0x9FFF => self.ppu.read(address),
+            0xC000..=0xDFFF => self.work_ram[self.work_ram_offset(address)],
+            0xE000..=0xFDFF => self.work_ram[self.work_ram_offset(address - 0x2000)],
+            0xFE00..=0xFE9F => self.ppu.read(address),
+            0xFEA0..=0xFEFF => 0x00,
+            DIV..=TAC => self.timer.read(address),
+            NR10..=0xFF3F => self.apu.read(address),
+            INTERRUPT_FLAG => self.interrupts.read_requested(),
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B | VBK | 0xFF68..=0xFF6C => self.ppu.read(address),
+            DMA => self.oam_dma.read(),
+            // Bank 0 can't be selected, and reads back as 1
+            SVBK if self.cgb_mode => 0xF8 | self.work_ram_bank,
+            KEY1 if self.cgb_mode => {
+                let speed = if self.double_speed { 0x80 } else { 0x00 };
+                speed | 0x7E | self.speed_switch_armed as u8
+            }
+            HDMA1..=HDMA5 if self.cgb_mode => self.hdma.read(address),
+            KEY1 | HDMA1..=HDMA5 | SVBK => OPEN_BUS,
+            BOOT_ROM_DISABLE | KEY0 => OPEN_BUS,
+            0xFF00..=0xFF7F => self.io_registers.read(address),
+            0xFF80..=0xFFFE => self.high_ram.read(address),
+            INTERRUPT_ENABLE => self.interrupts.read_enabled(),
+        }
+    }
+}
+
+impl MemoryMapped for Bus {
+    fn read(&self, address: u16) -> u8 {
+        if self.oam_dma.blocks(address) {
+            return self.oam_dma.blocked_read(address);
+        }
+
+        self.read_direct(address)
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if self.oam_dma.blocks(address) {
+            return;
+        }
+
+        match address {
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => {
+                if let Some(cartridge) = &mut self.cartridge {
+                    cartridge.write(address, value);
+                }
+            }
+            0x8000..=0x9FFF => self.ppu.write(address, value),
+            0xC000..=0xDFFF => {
+                let offset = self.work_ram_offset(address);
+                self.work_ram[offset] = value;
+            }
+            0xE000..=0xFDFF => {
+                let offset = self.work_ram_offset(address - 0x2000);
+                self.work_ram[offset] = value;
+            }
+            0xFE00..=0xFE9F => self.ppu.write(address, value),
+            0xFEA0..=0xFEFF => (),
+            DIV..=TAC => {
+                let divider = self.timer.divider();
+                self.timer.write(address, value);
+                self.clock_frame_sequencer(divider);
+            }
+            NR10..=0xFF3F => self.apu.write(address, value),
+            INTERRUPT_FLAG => self.interrupts.write_requested(value),
+            // Writing to STAT, LYC or LCDC can raise a STAT interrupt straight away
+            0xFF40..=0xFF45 | 0xFF47..=0xFF4B => {
+                self.ppu.write(address, value);
+                self.request_ppu_interrupts();
+            }
+            VBK | 0xFF68..=0xFF6C => self.ppu.write(address, value),
+            DMA => self.oam_dma.write(value),
+            SVBK if self.cgb_mode => self.work_ram_bank = (value & 0x07).max(1),
+            KEY1 if self.cgb_mode => self.speed_switch_armed = value & 0x01 != 0,
+            HDMA1..=HDMA5 if self.cgb_mode => {
+                self.hdma.write(address, value);
+                if let Some(blocks) = self.hdma.take_general() {
+                    for _ in 0..blocks {
+                        self.copy_hdma_block();
+                    }
+                }
+            }
+            KEY1 | HDMA1..=HDMA5 | SVBK => (),
+            BOOT_ROM_DISABLE => {
+                if value != 0 {
+                    self.boot_rom = None;
+                }
+            }
+            // Locked once the boot ROM has finished
+            KEY0 => {
+                if self.model.is_cgb() && self.boot_rom_mapped() {
+                    self.set_cgb_mode(value & 0x04 == 0);
+                }
+            }
+            0xFF00..=0xFF7F => self.io_registers.write(address, value),
+            0xFF80..=0xFFFE => self.high_ram.write(address, value),
+            INTERRUPT_ENABLE => self.interrupts.write_enabled(value),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{
+        hdma::{HDMA2, HDMA3, HDMA4},
+        timer::TIMA,
+    };
+
+    #[test]
+    fn empty_cartridge_slot_reads_open_bus() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(0x0100, 0x12);
+
+        assert_eq!(bus.read(0x0100), 0xFF);
+        assert_eq!(bus.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn cartridge_sees_rom_and_ram_ranges() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.insert_cartridge(Box::new(Ram::new(0x0000, 0xC000)));
+
+        bus.write(0x0150, 0x12);
+        bus.write(0xBFFF, 0x34);
+        assert_eq!(bus.read(0x0150), 0x12);
+        assert_eq!(bus.read(0xBFFF), 0x34);
+    }
+
+    #[test]
+    fn echo_ram_mirrors_work_ram() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(0xC123, 0x42);
+        assert_eq!(bus.read(0xE123), 0x42);
+
+        bus.write(0xFDFF, 0x24);
+        assert_eq!(bus.read(0xDDFF), 0x24);
+    }
+
+    #[test]
+    fn unusable_region_ignores_writes() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(0xFEA0, 0x42);
+
+        assert_eq!(bus.read(0xFEA0), 0x00);
+    }
+
+    #[test]
+    fn regions_are_independent() {
+        let mut bus = Bus::new(Model::Dmg);
+        let addresses = [0x8000, 0x9FFF, 0xC000, 0xFE00, 0xFE9F, 0xFF80, 0xFFFE];
+
+        for (i, &address) in addresses.iter().enumerate() {
+            bus.write(address, i as u8 + 1);
+        }
+        for (i, &address) in addresses.iter().enumerate() {
+            assert_eq!(bus.read(address), i as u8 + 1, "{:#06X}", address);
+        }
+    }
+
+    #[test]
+    fn boot_rom_overlays_cartridge_until_disabled() {
+        let mut bus = Bus::new(Model::Cgb);
+        bus.insert_cartridge(Box::new(Ram::new(0x0000, 0xC000)));
+        bus.write(0x0000, 0x11);
+        bus.write(0x0100, 0x22);
+        bus.write(0x0200, 0x33);
+        bus.insert_boot_rom(vec![0xAA; 0x0900]).unwrap();
+
+        assert_eq!(bus.read(0x0000), 0xAA);
+        assert_eq!(bus.read(0x0100), 0x22);
+        assert_eq!(bus.read(0x0200), 0xAA);
+        assert_eq!(bus.read(0x0900), 0x00);
+
+        bus.write(BOOT_ROM_DISABLE, 0x00);
+        assert!(bus.boot_rom_mapped());
+
+        bus.write(BOOT_ROM_DISABLE, 0x01);
+        assert!(!bus.boot_rom_mapped());
+        assert_eq!(bus.read(0x0000), 0x11);
+        assert_eq!(bus.read(0x0200), 0x33);
+    }
+
+    #[test]
+    fn boot_rom_size_depends_on_model() {
+        let mut bus = Bus::new(Model::Dmg);
+        assert_eq!(
+            bus.insert_boot_rom(vec![0; 0x0900]),
+            Err(BootRomSizeError {
+                model: Model::Dmg,
+                expected: 0x0100,
+                actual: 0x0900
+            })
+        );
+        assert!(!bus.boot_rom_mapped());
+
+        assert!(bus.insert_boot_rom(vec![0; 0x0100]).is_ok());
+    }
+
+    #[test]
+    fn cgb_boot_rom_can_switch_to_dmg_mode() {
+        let mut bus = Bus::new(Model::Cgb);
+        assert!(bus.cgb_mode());
+
+        bus.insert_boot_rom(vec![0; 0x0900]).unwrap();
+        bus.write(KEY0, 0x04);
+        assert!(!bus.cgb_mode());
+
+        // Locked once the boot ROM is unmapped
+        bus.write(BOOT_ROM_DISABLE, 0x01);
+        bus.write(KEY0, 0x00);
+        assert!(!bus.cgb_mode());
+    }
+
+    #[test]
+    fn skipping_boot_rom_picks_mode_from_header() {
+        let mut bus = Bus::new(Model::Agb);
+        bus.insert_cartridge(Box::new(Ram::new(0x0000, 0xC000)));
+        bus.skip_boot_rom();
+        assert!(!bus.cgb_mode());
+
+        bus.write(0x0143, 0x80);
+        bus.skip_boot_rom();
+        assert!(bus.cgb_mode());
+
+        // Never on for DMG hardware
+        let mut bus = Bus::new(Model::Dmg);
+        bus.insert_cartridge(Box::new(Ram::new(0x0000, 0xC000)));
+        bus.write(0x0143, 0xC0);
+        bus.skip_boot_rom();
+        assert!(!bus.cgb_mode());
+    }
+
+    #[test]
+    fn oam_dma_copies_into_oam() {
+        let mut bus = Bus::new(Model::Dmg);
+        for index in 0..0xA0 {
+            bus.write(0xC100 + index, index as u8);
+        }
+
+        bus.write(DMA, 0xC1);
+        assert_eq!(bus.read(DMA), 0xC1);
+        bus.tick(4 + 160 * 4);
+
+        for index in 0..0xA0 {
+            assert_eq!(bus.read(0xFE00 + index), index as u8);
+        }
+    }
+
+    #[test]
+    fn oam_dma_locks_cpu_out_of_the_bus() {
+        let mut bus = Bus::new(Model::Dmg);
+        for index in 0..0xA0 {
+            bus.write(0xC100 + index, index as u8 + 1);
+        }
+        bus.write(0xFF80, 0x12);
+
+        bus.write(DMA, 0xC1);
+        bus.tick(4 + 5 * 4);
+
+        // Reads pick up the byte being copied, and OAM reads as 0xFF
+        assert_eq!(bus.read(0x0000), 0x05);
+        assert_eq!(bus.read(0xD000), 0x05);
+        assert_eq!(bus.read(0xFE00), 0xFF);
+        bus.write(0xC000, 0x34);
+
+        // HRAM and the registers are still reachable
+        assert_eq!(bus.read(0xFF80), 0x12);
+        bus.write(0xFF81, 0x56);
+        assert_eq!(bus.read(0xFF81), 0x56);
+
+        bus.tick(155 * 4);
+        assert_eq!(bus.read(0xC000), 0x00);
+        assert_eq!(bus.read(0xFE9F), 0xA0);
+    }
+
+    #[test]
+    fn interrupt_registers() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(INTERRUPT_ENABLE, 0x05);
+        bus.request_interrupt(Interrupt::Timer);
+
+        assert_eq!(bus.read(INTERRUPT_FLAG), 0xE4);
+        assert_eq!(bus.pending_interrupt(), Some(Interrupt::Timer));
+    }
+
+    #[test]
+    fn work_ram_banks_switch_in_cgb_mode() {
+        let mut bus = Bus::new(Model::Cgb);
+        assert_eq!(bus.read(SVBK), 0xF9);
+
+        for bank in 1..8 {
+            bus.write(SVBK, bank);
+            bus.write(0xD000, bank);
+        }
+        bus.write(0xC000, 0x42);
+
+        // Bank 0 selects bank 1, and bank 0 itself is always at 0xC000
+        bus.write(SVBK, 0x00);
+        assert_eq!(bus.read(SVBK), 0xF9);
+        assert_eq!(bus.read(0xD000), 1);
+        bus.write(SVBK, 0xFB);
+        assert_eq!(bus.read(0xD000), 3);
+        assert_eq!(bus.read(0xF000), 3);
+        assert_eq!(bus.read(0xC000), 0x42);
+    }
+
+    #[test]
+    fn banks_are_fixed_outside_cgb_mode() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(0xD000, 0x11);
+        bus.write(0x8000, 0x22);
+        bus.write(SVBK, 0x02);
+        bus.write(VBK, 0x01);
+
+        assert_eq!(bus.read(SVBK), 0xFF);
+        assert_eq!(bus.read(VBK), 0xFF);
+        assert_eq!(bus.read(0xD000), 0x11);
+        assert_eq!(bus.read(0x8000), 0x22);
+    }
+
+    #[test]
+    fn video_ram_banks_switch_in_cgb_mode() {
+        let mut bus = Bus::new(Model::Cgb);
+        bus.write(0x8000, 0x11);
+        bus.write(VBK, 0x01);
+        assert_eq!(bus.read(VBK), 0xFF);
+        assert_eq!(bus.read(0x8000), 0x00);
+        bus.write(0x8000, 0x22);
+
+        bus.write(VBK, 0x00);
+        assert_eq!(bus.read(VBK), 0xFE);
+        assert_eq!(bus.read(0x8000), 0x11);
+
+        // Switching to DMG mode goes back to the first banks
+        bus.write(VBK, 0x01);
+        bus.write(SVBK, 0x05);
+        bus.insert_boot_rom(vec![0; 0x0900]).unwrap();
+        bus.write(KEY0, 0x04);
+        assert_eq!(bus.read(0x8000), 0x11);
+        assert_eq!(bus.work_ram_offset(0xD000), 0x1000);
+    }
+
+    #[test]
+    fn key1_arms_a_speed_switch_in_cgb_mode() {
+        let mut bus = Bus::new(Model::Cgb);
+        assert_eq!(bus.read(KEY1), 0x7E);
+        assert!(!bus.switch_speed());
+
+        bus.write(KEY1, 0x01);
+        assert_eq!(bus.read(KEY1), 0x7F);
+        assert!(bus.switch_speed());
+        assert_eq!(bus.read(KEY1), 0xFE);
+        assert_eq!(bus.take_stall_cycles(), SPEED_SWITCH_CYCLES);
+
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(KEY1, 0x01);
+        assert_eq!(bus.read(KEY1), 0xFF);
+        assert!(!bus.switch_speed());
+    }
+
+    #[test]
+    fn ppu_runs_at_the_same_rate_in_double_speed() {
+        let mut bus = Bus::new(Model::Cgb);
+        bus.write(0xFF40, 0x80);
+        bus.write(KEY1, 0x01);
+        bus.switch_speed();
+
+        bus.tick(456);
+        assert_eq!(bus.read(0xFF44), 0);
+        bus.tick(456);
+        assert_eq!(bus.read(0xFF44), 1);
+    }
+
+    fn bus_with_hdma_source() -> Bus {
+        let mut bus = Bus::new(Model::Cgb);
+        for offset in 0..0x40 {
+            bus.write(0xC000 + offset, offset as u8 + 1);
+        }
+        bus.write(HDMA1, 0xC0);
+        bus.write(HDMA2, 0x00);
+        bus.write(HDMA3, 0x01);
+        bus.write(HDMA4, 0x00);
+        bus
+    }
+
+    #[test]
+    fn general_purpose_hdma_copies_everything_at_once() {
+        let mut bus = bus_with_hdma_source();
+        bus.write(VBK, 0x01);
+        bus.write(HDMA5, 0x01);
+
+        for offset in 0..0x20 {
+            assert_eq!(bus.read(0x8100 + offset), offset as u8 + 1);
+        }
+        assert_eq!(bus.read(0x8120), 0x00);
+        assert_eq!(bus.read(HDMA5), 0xFF);
+        assert_eq!(bus.take_stall_cycles(), 2 * HDMA_BLOCK_CYCLES);
+
+        // Only into the selected bank
+        bus.write(VBK, 0x00);
+        assert_eq!(bus.read(0x8100), 0x00);
+    }
+
+    #[test]
+    fn hblank_hdma_copies_a_block_each_hblank() {
+        let mut bus = bus_with_hdma_source();
+        bus.write(0xFF40, 0x80);
+        bus.write(HDMA5, 0x81);
+        assert_eq!(bus.take_stall_cycles(), 0);
+
+        // Into HBlank on line 0
+        bus.tick(80 + 172);
+        assert_eq!(bus.read(0x810F), 0x10);
+        assert_eq!(bus.read(0x8110), 0x00);
+        assert_eq!(bus.read(HDMA5), 0x00);
+        assert_eq!(bus.take_stall_cycles(), HDMA_BLOCK_CYCLES);
+
+        bus.tick(456);
+        assert_eq!(bus.read(0x8110), 0x11);
+        assert_eq!(bus.read(HDMA5), 0xFF);
+
+        bus.tick(456);
+        assert_eq!(bus.read(0x8120), 0x00);
+    }
+
+    #[test]
+    fn hblank_hdma_copies_a_block_for_each_hblank_in_a_long_tick() {
+        let mut bus = bus_with_hdma_source();
+        bus.write(0xFF40, 0x80);
+        bus.write(HDMA5, 0x82);
+
+        // Through the HBlanks of lines 0, 1 and 2 at once
+        bus.tick(80 + 172 + 456 * 2);
+        assert_eq!(bus.read(0x812F), 0x30);
+        assert_eq!(bus.read(HDMA5), 0xFF);
+        assert_eq!(bus.take_stall_cycles(), 3 * HDMA_BLOCK_CYCLES);
+    }
+
+    #[test]
+    fn timer_runs_at_cpu_speed_and_requests_interrupts() {
+        // The timer counts CPU cycles, so takes as many at either speed
+        for double_speed in [false, true] {
+            let mut bus = Bus::new(Model::Cgb);
+            if double_speed {
+                bus.write(KEY1, 0x01);
+                bus.switch_speed();
+            }
+            bus.write(TIMA, 0xFF);
+            bus.write(TAC, 0x05);
+
+            bus.tick(16);
+            assert_eq!(bus.read(INTERRUPT_FLAG) & 0x04, 0x00);
+            bus.tick(4);
+            assert_eq!(bus.read(INTERRUPT_FLAG) & 0x04, 0x04);
+        }
+    }
+
+    #[test]
+    fn cycles_that_arent_whole_m_cycles_carry_over() {
+        let mut bus = Bus::new(Model::Dmg);
+        for index in 0..0xA0 {
+            bus.write(0xC100 + index, index as u8 + 1);
+        }
+        bus.write(TAC, 0x05);
+        bus.write(DMA, 0xC1);
+
+        // The timer and OAM DMA stay in step when ticked half an M-cycle at a time
+        for _ in 0..8 {
+            bus.tick(2);
+        }
+        assert_eq!(bus.read(TIMA), 1);
+        assert_eq!(bus.read(0x0000), 0x03);
+
+        bus.tick(157 * 4);
+        assert_eq!(bus.read(0xFE9F), 0xA0);
+    }
+
+    #[test]
+    fn frame_sequencer_is_clocked_from_div() {
+        let mut bus = Bus::new(Model::Dmg);
+        bus.write(NR52, 0x80);
+        // Channel 1 with 2 clocks of length left
+        bus.write(0xFF12, 0xF0);
+        bus.write(0xFF11, 0x3E);
+        bus.write(NR14, 0xC0);
+
+        // Step 0 clocks length when bit 4 of DIV first falls
+        bus.tick(0x2000 - 4);
+        bus.tick(4);
+        assert_eq!(bus.read(NR52) & 0x01, 0x01);
+
+        // Resetting DIV while the bit is set moves on to step 1, which doesn't clock length
+        bus.tick(0x1000);
+        bus.write(DIV, 0);
+        assert_eq!(bus.read(NR52) & 0x01, 0x01);
+
+        // Step 2 does
+        bus.tick(0x2000 - 4);
+        assert_eq!(bus.read(NR52) & 0x01, 0x01);
+        bus.tick(4);
+        assert_eq!(bus.read(NR52) & 0x01, 0x00);
+    }
+}
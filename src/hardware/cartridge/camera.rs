@@ -0,0 +1,329 @@
+use std::{fs, io, path::Path};
+
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+// Size of the image captured by the sensor
+pub const CAMERA_WIDTH: usize = 128;
+pub const CAMERA_HEIGHT: usize = 112;
+
+// Captured images are written to RAM bank 0 as 16x14 tiles, starting here
+const IMAGE_ADDRESS: usize = 0x0100;
+
+const REGISTER_COUNT: usize = 0x36;
+// 4x4 matrix of threshold triples used to turn the sensor output into four shades
+const DITHER_MATRIX: usize = 0x06;
+
+// Where the camera gets its pictures from. Images are CAMERA_WIDTH x CAMERA_HEIGHT greyscale, one
+// byte per pixel from 0 (black) to 255 (white), in rows from the top left.
+pub trait ImageSource {
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+// The same picture every time, such as one loaded from a file
+pub struct StaticImage {
+    pixels: Vec<u8>,
+}
+
+impl StaticImage {
+    // Scales the image to the size of the sensor. The pixels are one byte each, in rows from the
+    // top left.
+    pub fn new(width: usize, height: usize, pixels: &[u8]) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidInput, message);
+        if width == 0 || height == 0 {
+            return Err(invalid("image is empty"));
+        }
+        match width.checked_mul(height) {
+            Some(size) if pixels.len() >= size => (),
+            _ => return Err(invalid("image is smaller than its dimensions")),
+        }
+
+        let mut scaled = Vec::with_capacity(CAMERA_WIDTH * CAMERA_HEIGHT);
+        for y in 0..CAMERA_HEIGHT {
+            for x in 0..CAMERA_WIDTH {
+                scaled
+                    .push(pixels[(y * height / CAMERA_HEIGHT) * width + x * width / CAMERA_WIDTH]);
+            }
+        }
+
+        Ok(StaticImage { pixels: scaled })
+    }
+
+    // Loads a binary (P5) or plain (P2) PGM file
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        Self::from_pgm(&data)
+    }
+
+    pub fn from_pgm(data: &[u8]) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let binary = match data.get(0..2) {
+            Some(b"P5") => true,
+            Some(b"P2") => false,
+            _ => return Err(invalid("not a PGM file")),
+        };
+
+        let mut tokens = PgmTokens { data, position: 2 };
+        let (width, height, maximum) = match (tokens.number(), tokens.number(), tokens.number()) {
+            (Some(width), Some(height), Some(maximum))
+                if width > 0 && height > 0 && (1..=0xFFFF).contains(&maximum) =>
+            {
+                (width, height, maximum)
+            }
+            _ => return Err(invalid("bad PGM header")),
+        };
+
+        let mut pixels = Vec::with_capacity(width * height);
+        if binary {
+            // A single whitespace character separates the header from the pixels, which take two
+            // bytes each when the maximum doesn't fit in one
+            let bytes_per_pixel = if maximum > 0xFF { 2 } else { 1 };
+            let start = tokens.position + 1;
+            let end = start + width * height * bytes_per_pixel;
+            let raster = data
+                .get(start..end)
+                .ok_or_else(|| invalid("PGM file is truncated"))?;
+
+            for pixel in raster.chunks_exact(bytes_per_pixel) {
+                let value = pixel
+                    .iter()
+                    .fold(0, |value, &byte| (value << 8) | byte as usize);
+                pixels.push((value.min(maximum) * 0xFF / maximum) as u8);
+            }
+        } else {
+            for _ in 0..width * height {
+                let value = tokens
+                    .number()
+                    .ok_or_else(|| invalid("PGM file is truncated"))?;
+                pixels.push((value.min(maximum) * 0xFF / maximum) as u8);
+            }
+        }
+
+        StaticImage::new(width, height, &pixels)
+    }
+}
+
+// Reads the whitespace-separated numbers in a PGM file, skipping comments
+struct PgmTokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl PgmTokens<'_> {
+    fn number(&mut self) -> Option<usize> {
+        loop {
+            match self.data.get(self.position)? {
+                byte if byte.is_ascii_whitespace() => self.position += 1,
+                // Comments run to the end of the line
+                b'#' => {
+                    while *self.data.get(self.position)? != b'\n' {
+                        self.position += 1;
+                    }
+                }
+                _ => break,
+            }
+        }
+
+        let start = self.position;
+        while self
+            .data
+            .get(self.position)
+            .is_some_and(|byte| !byte.is_ascii_whitespace())
+        {
+            self.position += 1;
+        }
+
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()?
+            .parse()
+            .ok()
+    }
+}
+
+impl ImageSource for StaticImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+// The Game Boy Camera's mapper, which also drives the image sensor. Setting bit 4 of the RAM bank
+// register swaps RAM for the sensor's registers.
+pub struct PocketCamera {
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers_selected: bool,
+    registers: [u8; REGISTER_COUNT],
+    source: Option<Box<dyn ImageSource>>,
+}
+
+impl PocketCamera {
+    pub fn new() -> Self {
+        PocketCamera {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers_selected: false,
+            registers: [0; REGISTER_COUNT],
+            source: None,
+        }
+    }
+
+    // Takes a picture and writes it to RAM as tiles. Real captures take a while and set a busy
+    // bit until they finish, but here the picture is ready straight away. Exposure, gain and edge
+    // enhancement aren't emulated, so only the dither matrix affects the result.
+    fn capture(&mut self, ram: &mut [u8]) {
+        let image = match &mut self.source {
+            Some(source) => source.capture(),
+            // Without a source the sensor sees nothing but white
+            None => vec![0xFF; CAMERA_WIDTH * CAMERA_HEIGHT],
+        };
+
+        if ram.len() < IMAGE_ADDRESS + CAMERA_WIDTH * CAMERA_HEIGHT / 4 {
+            return;
+        }
+
+        for (index, &pixel) in image.iter().enumerate().take(CAMERA_WIDTH * CAMERA_HEIGHT) {
+            let (x, y) = (index % CAMERA_WIDTH, index / CAMERA_WIDTH);
+
+            let matrix = DITHER_MATRIX + ((y % 4) * 4 + x % 4) * 3;
+            let thresholds = &self.registers[matrix..matrix + 3];
+            let shade = thresholds
+                .iter()
+                .filter(|&&threshold| pixel < threshold)
+                .count() as u8;
+
+            let tile = (y / 8) * (CAMERA_WIDTH / 8) + x / 8;
+            let row = IMAGE_ADDRESS + tile * 16 + (y % 8) * 2;
+            let bit = 7 - (x % 8);
+
+            ram[row] = (ram[row] & !(1 << bit)) | ((shade & 0x01) << bit);
+            ram[row + 1] = (ram[row + 1] & !(1 << bit)) | (((shade >> 1) & 0x01) << bit);
+        }
+    }
+}
+
+impl MemoryBankController for PocketCamera {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x3F,
+            0x4000..=0x5FFF => {
+                self.registers_selected = value & 0x10 != 0;
+                self.ram_bank = value & 0x0F;
+            }
+            _ => (),
+        }
+    }
+
+    // Only the control register can be read back, the rest of the sensor registers read as 0
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if self.registers_selected {
+            match address & 0x7F {
+                0x00 => self.registers[0],
+                _ => 0x00,
+            }
+        } else if self.ram_enabled {
+            read_ram_bank(ram, self.ram_bank as usize, address)
+        } else {
+            0xFF
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if self.registers_selected {
+            match (address & 0x7F) as usize {
+                0x00 => {
+                    self.registers[0] = value & 0x07;
+                    if value & 0x01 != 0 {
+                        self.capture(ram);
+                        self.registers[0] &= !0x01;
+                        // The picture goes into RAM
+                        return true;
+                    }
+                }
+                register if register < REGISTER_COUNT => self.registers[register] = value,
+                _ => (),
+            }
+            false
+        } else {
+            self.ram_enabled && write_ram_bank(ram, self.ram_bank as usize, address, value)
+        }
+    }
+
+    fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.source = Some(source);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, Cartridge};
+    use super::*;
+    use crate::hardware::bus::MemoryMapped;
+
+    #[test]
+    fn parses_binary_and_plain_pgm() {
+        let binary = StaticImage::from_pgm(b"P5\n# two pixels\n2 1\n255\n\x00\xFF").unwrap();
+        assert_eq!(binary.pixels.len(), CAMERA_WIDTH * CAMERA_HEIGHT);
+        assert_eq!(binary.pixels[0], 0x00);
+        assert_eq!(binary.pixels[CAMERA_WIDTH - 1], 0xFF);
+
+        let plain = StaticImage::from_pgm(b"P2 2 1 15 0 15").unwrap();
+        assert_eq!(plain.pixels, binary.pixels);
+
+        assert!(StaticImage::from_pgm(b"P6 1 1 255 abc").is_err());
+        assert!(StaticImage::from_pgm(b"P5 2 2 255 \x00").is_err());
+    }
+
+    #[test]
+    fn checks_image_dimensions() {
+        assert!(StaticImage::new(2, 2, &[0x00, 0x40, 0x80, 0xFF]).is_ok());
+        assert!(StaticImage::new(2, 2, &[0x00, 0x40, 0x80]).is_err());
+        assert!(StaticImage::new(0, 2, &[]).is_err());
+        assert!(StaticImage::new(usize::MAX, 2, &[0x00]).is_err());
+    }
+
+    #[test]
+    fn captures_image_file_into_ram() {
+        // Black on the left and white on the right
+        let path = std::env::temp_dir().join(format!("rustboy-{}-camera.pgm", std::process::id()));
+        fs::write(&path, b"P5 2 1 255 \x00\xFF").unwrap();
+        let image = StaticImage::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        // Pocket Camera with 1 MiB of ROM and 128 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0xFC, 0x05, 0x04)).unwrap();
+        cartridge.set_image_source(Box::new(image.unwrap()));
+
+        // Same thresholds everywhere in the dither matrix
+        cartridge.write(0x4000, 0x10);
+        for entry in 0..16 {
+            cartridge.write(0xA006 + entry * 3, 0x40);
+            cartridge.write(0xA007 + entry * 3, 0x80);
+            cartridge.write(0xA008 + entry * 3, 0xC0);
+        }
+        cartridge.write(0xA000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 0x00);
+        assert_eq!(cartridge.read(0xA006), 0x00);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x00);
+
+        // The first tile is black and the first tile of the right half is white
+        assert_eq!(cartridge.read(0xA100), 0xFF);
+        assert_eq!(cartridge.read(0xA101), 0xFF);
+        assert_eq!(cartridge.read(0xA100 + 8 * 16), 0x00);
+        assert_eq!(cartridge.read(0xA101 + 8 * 16), 0x00);
+    }
+}
@@ -0,0 +1,343 @@
+use std::fmt;
+
+use crate::utils::bytes_to_word;
+
+use super::CartridgeError;
+
+const HEADER_END: usize = 0x0150;
+
+// Checked by the boot ROM before it starts the cartridge
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xCE, 0xED, 0x66, 0x66, 0xCC, 0x0D, 0x00, 0x0B, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0C, 0x00, 0x0D,
+    0x00, 0x08, 0x11, 0x1F, 0x88, 0x89, 0x00, 0x0E, 0xDC, 0xCC, 0x6E, 0xE6, 0xDD, 0xDD, 0xD9, 0x99,
+    0xBB, 0xBB, 0x67, 0x63, 0x6E, 0x0E, 0xEC, 0xCC, 0xDD, 0xDC, 0x99, 0x9F, 0xBB, 0xB9, 0x33, 0x3E,
+];
+
+const TITLE: usize = 0x0134;
+const CGB_FLAG: usize = 0x0143;
+const NEW_LICENSEE_CODE: usize = 0x0144;
+const SGB_FLAG: usize = 0x0146;
+const CARTRIDGE_TYPE: usize = 0x0147;
+const ROM_SIZE: usize = 0x0148;
+const RAM_SIZE: usize = 0x0149;
+const DESTINATION_CODE: usize = 0x014A;
+const OLD_LICENSEE_CODE: usize = 0x014B;
+const VERSION: usize = 0x014C;
+const HEADER_CHECKSUM: usize = 0x014D;
+const GLOBAL_CHECKSUM: usize = 0x014E;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapper {
+    RomOnly,
+    Mbc1,
+    Mbc2,
+    Mmm01,
+    Mbc3,
+    Mbc5,
+    Mbc6,
+    Mbc7,
+    PocketCamera,
+    Tama5,
+    HuC3,
+    HuC1,
+}
+
+// The mapper chip along with whatever else is on the cartridge board
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CartridgeType {
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+}
+
+impl CartridgeType {
+    fn decode(code: u8) -> Result<Self, CartridgeError> {
+        let (mapper, ram, battery, timer, rumble) = match code {
+            0x00 => (Mapper::RomOnly, false, false, false, false),
+            0x01 => (Mapper::Mbc1, false, false, false, false),
+            0x02 => (Mapper::Mbc1, true, false, false, false),
+            0x03 => (Mapper::Mbc1, true, true, false, false),
+            0x05 => (Mapper::Mbc2, false, false, false, false),
+            0x06 => (Mapper::Mbc2, false, true, false, false),
+            0x08 => (Mapper::RomOnly, true, false, false, false),
+            0x09 => (Mapper::RomOnly, true, true, false, false),
+            0x0B => (Mapper::Mmm01, false, false, false, false),
+            0x0C => (Mapper::Mmm01, true, false, false, false),
+            0x0D => (Mapper::Mmm01, true, true, false, false),
+            0x0F => (Mapper::Mbc3, false, true, true, false),
+            0x10 => (Mapper::Mbc3, true, true, true, false),
+            0x11 => (Mapper::Mbc3, false, false, false, false),
+            0x12 => (Mapper::Mbc3, true, false, false, false),
+            0x13 => (Mapper::Mbc3, true, true, false, false),
+            0x19 => (Mapper::Mbc5, false, false, false, false),
+            0x1A => (Mapper::Mbc5, true, false, false, false),
+            0x1B => (Mapper::Mbc5, true, true, false, false),
+            0x1C => (Mapper::Mbc5, false, false, false, true),
+            0x1D => (Mapper::Mbc5, true, false, false, true),
+            0x1E => (Mapper::Mbc5, true, true, false, true),
+            0x20 => (Mapper::Mbc6, true, true, false, false),
+            0x22 => (Mapper::Mbc7, true, true, false, true),
+            0xFC => (Mapper::PocketCamera, true, true, false, false),
+            0xFD => (Mapper::Tama5, true, true, true, false),
+            0xFE => (Mapper::HuC3, true, true, true, false),
+            0xFF => (Mapper::HuC1, true, true, false, false),
+            _ => return Err(CartridgeError::UnknownCartridgeType(code)),
+        };
+
+        Ok(CartridgeType {
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CgbSupport {
+    None,
+    // Uses CGB features but still runs on older models
+    Enhanced,
+    Only,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    // Two ASCII characters, used when the old code is 0x33
+    New(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct CartridgeHeader {
+    pub title: String,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub japanese: bool,
+    pub licensee: Licensee,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+    // Nothing on the hardware checks the global checksum, and plenty of homebrew, patched and
+    // translated ROMs get it wrong, so a mismatch is only a warning
+    pub global_checksum_valid: bool,
+}
+
+impl CartridgeHeader {
+    pub fn parse(rom: &[u8]) -> Result<Self, CartridgeError> {
+        if rom.len() < HEADER_END {
+            return Err(CartridgeError::Truncated {
+                expected: HEADER_END,
+                actual: rom.len(),
+            });
+        }
+
+        let header_checksum = rom[HEADER_CHECKSUM];
+        let computed = compute_header_checksum(rom);
+        if computed != header_checksum {
+            return Err(CartridgeError::HeaderChecksum {
+                expected: header_checksum,
+                actual: computed,
+            });
+        }
+
+        let cgb_support = match rom[CGB_FLAG] {
+            0xC0 => CgbSupport::Only,
+            flag if flag & 0x80 != 0 => CgbSupport::Enhanced,
+            _ => CgbSupport::None,
+        };
+
+        // Newer cartridges shortened the title to make room for the manufacturer code and CGB
+        // flag, but the length isn't marked anywhere so stop at the first NUL instead
+        let title_end = if cgb_support == CgbSupport::None {
+            CGB_FLAG + 1
+        } else {
+            CGB_FLAG
+        };
+        let title = rom[TITLE..title_end]
+            .iter()
+            .take_while(|&&byte| byte != 0)
+            .map(|&byte| byte as char)
+            .collect();
+
+        let licensee = match rom[OLD_LICENSEE_CODE] {
+            0x33 => Licensee::New(
+                rom[NEW_LICENSEE_CODE..NEW_LICENSEE_CODE + 2]
+                    .iter()
+                    .map(|&byte| byte as char)
+                    .collect(),
+            ),
+            code => Licensee::Old(code),
+        };
+
+        let rom_size = match rom[ROM_SIZE] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            code => return Err(CartridgeError::UnsupportedRomSize(code)),
+        };
+
+        let ram_size = match rom[RAM_SIZE] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(CartridgeError::UnsupportedRamSize(code)),
+        };
+
+        let global_checksum = bytes_to_word(rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]);
+        let global_checksum_valid =
+            compute_global_checksum(&rom[..rom.len().min(rom_size)]) == global_checksum;
+
+        Ok(CartridgeHeader {
+            title,
+            cgb_support,
+            sgb_support: rom[SGB_FLAG] == 0x03,
+            cartridge_type: CartridgeType::decode(rom[CARTRIDGE_TYPE])?,
+            rom_size,
+            ram_size,
+            japanese: rom[DESTINATION_CODE] == 0x00,
+            licensee,
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum,
+            global_checksum_valid,
+        })
+    }
+}
+
+impl fmt::Display for CartridgeHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} (v{}) {:?}, {} KiB ROM, {} KiB RAM",
+            self.title,
+            self.version,
+            self.cartridge_type.mapper,
+            self.rom_size / 1024,
+            self.ram_size / 1024
+        )
+    }
+}
+
+// The boot ROM refuses to start a cartridge whose header fails this check
+pub fn compute_header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |checksum, &byte| {
+            checksum.wrapping_sub(byte).wrapping_sub(1)
+        })
+}
+
+// Sum of every byte in the ROM apart from the checksum itself. Nothing on the hardware checks
+// this, but a mismatch is a good sign of a bad dump.
+pub fn compute_global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|&(address, _)| address != GLOBAL_CHECKSUM && address != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |checksum, (_, &byte)| {
+            checksum.wrapping_add(byte as u16)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::cartridge::{fix_checksums, test_rom};
+
+    #[test]
+    fn parses_fields() {
+        let mut rom = test_rom(0x13, 0x02, 0x03);
+        rom[TITLE..TITLE + 8].copy_from_slice(b"POKEMON\0");
+        rom[CGB_FLAG] = 0x80;
+        rom[SGB_FLAG] = 0x03;
+        rom[DESTINATION_CODE] = 0x01;
+        rom[OLD_LICENSEE_CODE] = 0x33;
+        rom[NEW_LICENSEE_CODE..NEW_LICENSEE_CODE + 2].copy_from_slice(b"01");
+        rom[VERSION] = 0x02;
+        fix_checksums(&mut rom);
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON");
+        assert_eq!(header.cgb_support, CgbSupport::Enhanced);
+        assert!(header.sgb_support);
+        assert_eq!(header.cartridge_type.mapper, Mapper::Mbc3);
+        assert!(header.cartridge_type.ram);
+        assert!(header.cartridge_type.battery);
+        assert!(!header.cartridge_type.timer);
+        assert_eq!(header.rom_size, 128 * 1024);
+        assert_eq!(header.ram_size, 32 * 1024);
+        assert!(!header.japanese);
+        assert_eq!(header.licensee, Licensee::New(String::from("01")));
+        assert_eq!(header.version, 2);
+        assert!(header.global_checksum_valid);
+    }
+
+    #[test]
+    fn flags_bad_global_checksum() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[0x4000] = 0x01;
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(!header.global_checksum_valid);
+    }
+
+    #[test]
+    fn full_length_title_without_cgb_flag() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[TITLE..CGB_FLAG + 1].copy_from_slice(b"ABCDEFGHIJKLMNOP");
+        fix_checksums(&mut rom);
+
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "ABCDEFGHIJKLMNOP");
+        assert_eq!(header.cgb_support, CgbSupport::None);
+        assert_eq!(header.licensee, Licensee::Old(0x00));
+    }
+
+    #[test]
+    fn rejects_bad_header_checksum() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[HEADER_CHECKSUM] ^= 0xFF;
+
+        assert!(matches!(
+            CartridgeHeader::parse(&rom),
+            Err(CartridgeError::HeaderChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn rejects_short_header() {
+        let rom = vec![0; 0x0140];
+
+        assert!(matches!(
+            CartridgeHeader::parse(&rom),
+            Err(CartridgeError::Truncated {
+                expected: 0x0150,
+                actual: 0x0140
+            })
+        ));
+    }
+
+    #[test]
+    fn rejects_unknown_codes() {
+        let mut rom = test_rom(0x04, 0x00, 0x00);
+        assert!(matches!(
+            CartridgeHeader::parse(&rom),
+            Err(CartridgeError::UnknownCartridgeType(0x04))
+        ));
+
+        rom[CARTRIDGE_TYPE] = 0x00;
+        rom[ROM_SIZE] = 0x52;
+        fix_checksums(&mut rom);
+        assert!(matches!(
+            CartridgeHeader::parse(&rom),
+            Err(CartridgeError::UnsupportedRomSize(0x52))
+        ));
+    }
+}
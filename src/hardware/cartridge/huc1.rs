@@ -0,0 +1,108 @@
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+// Hudson's mapper with an infrared port. The RAM area is shared between RAM and the IR register.
+pub struct HuC1 {
+    infrared_mode: bool,
+    // 6-bit register, so up to 64 banks of ROM
+    rom_bank: u8,
+    ram_bank: u8,
+    infrared_led: bool,
+    // Whether the sensor is currently seeing light from another device
+    infrared_signal: bool,
+}
+
+impl HuC1 {
+    pub fn new() -> Self {
+        HuC1 {
+            infrared_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            infrared_led: false,
+            infrared_signal: false,
+        }
+    }
+}
+
+impl MemoryBankController for HuC1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            // There's no RAM enable, any value other than 0x0E switches back to RAM
+            0x0000..=0x1FFF => self.infrared_mode = value == 0x0E,
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x3F {
+                    0 => 1,
+                    bank => bank,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => (),
+        }
+    }
+
+    // Bit 0 of the IR register is set while light is being received, and the unused bits read as
+    // 0xC0
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if self.infrared_mode {
+            0xC0 | self.infrared_signal as u8
+        } else {
+            read_ram_bank(ram, self.ram_bank as usize, address)
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if self.infrared_mode {
+            self.infrared_led = value & 0x01 != 0;
+            false
+        } else {
+            write_ram_bank(ram, self.ram_bank as usize, address, value)
+        }
+    }
+
+    fn infrared_led(&self) -> bool {
+        self.infrared_led
+    }
+
+    fn set_infrared_signal(&mut self, signal: bool) {
+        self.infrared_signal = signal;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, Cartridge};
+    use crate::hardware::bus::MemoryMapped;
+
+    #[test]
+    fn switches_between_ram_and_infrared() {
+        // HuC1 with 256 KiB of ROM and 32 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0xFF, 0x03, 0x03)).unwrap();
+        cartridge.write(0x2000, 0x05);
+        assert_eq!(cartridge.read(0x4000), 5);
+
+        // RAM needs no enabling
+        cartridge.write(0x4000, 0x02);
+        cartridge.write(0xA000, 0x42);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+
+        cartridge.write(0x0000, 0x0E);
+        assert_eq!(cartridge.read(0xA000), 0xC0);
+        cartridge.set_infrared_signal(true);
+        assert_eq!(cartridge.read(0xA000), 0xC1);
+
+        cartridge.write(0xA000, 0x01);
+        assert!(cartridge.infrared_led());
+
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+    }
+}
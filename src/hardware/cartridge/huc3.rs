@@ -0,0 +1,303 @@
+use std::rc::Rc;
+
+use super::{read_ram_bank, read_rom_bank, rtc::Clock, write_ram_bank, MemoryBankController};
+
+// Size of the clock state stored after the RAM in a .sav file: the minute counter as a
+// little-endian u64 followed by the Unix time it was saved at
+pub const HUC3_CLOCK_SAVE_SIZE: usize = 16;
+
+const MINUTES_PER_DAY: u64 = 24 * 60;
+
+// The day counter is 12 bits wide
+const DAYS_WRAP: u64 = 0x1000;
+
+// Commands written in mode 0xB, in the upper nibble of the value
+const READ: u8 = 0x1;
+const WRITE: u8 = 0x3;
+const ADDRESS_LOW: u8 = 0x4;
+const ADDRESS_HIGH: u8 = 0x5;
+const EXTENDED: u8 = 0x6;
+
+// Hudson's mapper with a clock and an infrared port. The clock lives on a separate chip that is
+// driven by writing commands through the RAM area, and keeps its registers in a small block of
+// 4-bit memory.
+pub struct HuC3 {
+    // Selects what the RAM area is connected to
+    mode: u8,
+    rom_bank: u8,
+    ram_bank: u8,
+    clock: Rc<dyn Clock>,
+    // Value of the minute counter when it was last set, and the time it was set at
+    minutes: u64,
+    set_at: u64,
+    memory: [u8; 0x100],
+    address: u8,
+    command: u8,
+    result: u8,
+    infrared_led: bool,
+    infrared_signal: bool,
+}
+
+impl HuC3 {
+    pub fn new(clock: Rc<dyn Clock>) -> Self {
+        let set_at = clock.now();
+
+        HuC3 {
+            mode: 0,
+            rom_bank: 1,
+            ram_bank: 0,
+            clock,
+            minutes: 0,
+            set_at,
+            memory: [0; 0x100],
+            address: 0,
+            command: 0,
+            result: 0,
+            infrared_led: false,
+            infrared_signal: false,
+        }
+    }
+
+    fn minutes(&self) -> u64 {
+        self.minutes + self.clock.now().saturating_sub(self.set_at) / 60
+    }
+
+    fn set_minutes(&mut self, minutes: u64) {
+        self.minutes = minutes;
+        self.set_at = self.clock.now();
+    }
+
+    // The minute of the day goes in 0x00-0x02 and the day counter in 0x03-0x05, a nibble at a time
+    // starting with the lowest
+    fn copy_clock_to_memory(&mut self) {
+        let minutes = self.minutes();
+        let minute_of_day = minutes % MINUTES_PER_DAY;
+        let days = (minutes / MINUTES_PER_DAY) % DAYS_WRAP;
+
+        for nibble in 0..3 {
+            self.memory[nibble] = ((minute_of_day >> (nibble * 4)) & 0x0F) as u8;
+            self.memory[nibble + 3] = ((days >> (nibble * 4)) & 0x0F) as u8;
+        }
+    }
+
+    fn copy_memory_to_clock(&mut self) {
+        let mut minute_of_day = 0;
+        let mut days = 0;
+        for nibble in 0..3 {
+            minute_of_day |= (self.memory[nibble] as u64) << (nibble * 4);
+            days |= (self.memory[nibble + 3] as u64) << (nibble * 4);
+        }
+
+        self.set_minutes(days * MINUTES_PER_DAY + minute_of_day);
+    }
+
+    // Returns whether the clock was set
+    fn execute_command(&mut self, value: u8) -> bool {
+        self.command = (value >> 4) & 0x07;
+        let argument = value & 0x0F;
+
+        match self.command {
+            READ => {
+                self.result = self.memory[self.address as usize];
+                self.address = self.address.wrapping_add(1);
+            }
+            WRITE => {
+                self.memory[self.address as usize] = argument;
+                self.address = self.address.wrapping_add(1);
+            }
+            ADDRESS_LOW => self.address = (self.address & 0xF0) | argument,
+            ADDRESS_HIGH => self.address = (self.address & 0x0F) | (argument << 4),
+            EXTENDED => match argument {
+                0x0 => self.copy_clock_to_memory(),
+                0x1 => {
+                    self.copy_memory_to_clock();
+                    return true;
+                }
+                // Status check, which always reports the clock as ready
+                0x2 => self.result = 0x01,
+                // The rest control the alarm and the tone generator, which aren't emulated
+                _ => (),
+            },
+            _ => (),
+        }
+
+        false
+    }
+}
+
+impl MemoryBankController for HuC3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.mode = value & 0x0F,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.ram_bank = value & 0x03,
+            _ => (),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        match self.mode {
+            // Read-only and read-write RAM
+            0x0 | 0xA => read_ram_bank(ram, self.ram_bank as usize, address),
+            0xC => (self.command << 4) | self.result,
+            // The clock is always ready for another command
+            0xD => 0x01,
+            0xE => 0xC0 | self.infrared_signal as u8,
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        match self.mode {
+            0xA => write_ram_bank(ram, self.ram_bank as usize, address, value),
+            0xB => self.execute_command(value),
+            0xE => {
+                self.infrared_led = value & 0x01 != 0;
+                false
+            }
+            _ => false,
+        }
+    }
+
+    fn infrared_led(&self) -> bool {
+        self.infrared_led
+    }
+
+    fn set_infrared_signal(&mut self, signal: bool) {
+        self.infrared_signal = signal;
+    }
+
+    fn save_clock(&mut self) -> Option<Vec<u8>> {
+        let mut data = Vec::with_capacity(HUC3_CLOCK_SAVE_SIZE);
+        data.extend_from_slice(&self.minutes().to_le_bytes());
+        data.extend_from_slice(&self.clock.now().to_le_bytes());
+
+        Some(data)
+    }
+
+    // The clock keeps counting while the emulator is closed
+    fn load_clock(&mut self, data: &[u8]) {
+        if data.len() < HUC3_CLOCK_SAVE_SIZE {
+            return;
+        }
+
+        let mut minutes = [0; 8];
+        let mut saved_at = [0; 8];
+        minutes.copy_from_slice(&data[0..8]);
+        saved_at.copy_from_slice(&data[8..16]);
+
+        self.minutes = u64::from_le_bytes(minutes);
+        self.set_at = u64::from_le_bytes(saved_at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, rtc::TestClock, Cartridge};
+    use super::*;
+    use crate::hardware::bus::MemoryMapped;
+
+    fn huc3_cartridge(clock: &TestClock) -> Cartridge {
+        // HuC3 with 256 KiB of ROM and 32 KiB of RAM
+        let rom = banked_test_rom(0xFE, 0x03, 0x03);
+        Cartridge::from_bytes_with_clock(rom, Rc::new(clock.clone())).unwrap()
+    }
+
+    // Reads a nibble from the clock's memory
+    fn read_nibble(cartridge: &mut Cartridge, address: u8) -> u8 {
+        cartridge.write(0x0000, 0x0B);
+        cartridge.write(0xA000, 0x40 | (address & 0x0F));
+        cartridge.write(0xA000, 0x50 | (address >> 4));
+        cartridge.write(0xA000, 0x10);
+
+        cartridge.write(0x0000, 0x0C);
+        cartridge.read(0xA000) & 0x0F
+    }
+
+    #[test]
+    fn reads_clock_through_commands() {
+        let clock = TestClock::default();
+        let mut cartridge = huc3_cartridge(&clock);
+
+        // Two days, one hour and three minutes
+        clock.advance((2 * MINUTES_PER_DAY + 63) * 60);
+        cartridge.write(0x0000, 0x0B);
+        cartridge.write(0xA000, 0x60);
+
+        let minutes = read_nibble(&mut cartridge, 0x00) as u64
+            | (read_nibble(&mut cartridge, 0x01) as u64) << 4
+            | (read_nibble(&mut cartridge, 0x02) as u64) << 8;
+        assert_eq!(minutes, 63);
+        assert_eq!(read_nibble(&mut cartridge, 0x03), 2);
+    }
+
+    #[test]
+    fn sets_clock_from_memory() {
+        let clock = TestClock::default();
+        let mut cartridge = huc3_cartridge(&clock);
+
+        // Write 0x123 minutes and 5 days, then copy them to the clock
+        cartridge.write(0x0000, 0x0B);
+        cartridge.write(0xA000, 0x40);
+        cartridge.write(0xA000, 0x50);
+        for nibble in [0x3, 0x2, 0x1, 0x5, 0x0, 0x0] {
+            cartridge.write(0xA000, 0x30 | nibble);
+        }
+        cartridge.write(0xA000, 0x61);
+
+        // A minute passes before the clock is read back
+        clock.advance(60);
+        cartridge.write(0xA000, 0x60);
+        assert_eq!(read_nibble(&mut cartridge, 0x00), 0x4);
+        assert_eq!(read_nibble(&mut cartridge, 0x01), 0x2);
+        assert_eq!(read_nibble(&mut cartridge, 0x02), 0x1);
+        assert_eq!(read_nibble(&mut cartridge, 0x03), 0x5);
+    }
+
+    #[test]
+    fn ram_modes() {
+        let clock = TestClock::default();
+        let mut cartridge = huc3_cartridge(&clock);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+
+        // Mode 0 can only read
+        cartridge.write(0x0000, 0x00);
+        cartridge.write(0xA000, 0x24);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+
+        cartridge.write(0x0000, 0x0D);
+        assert_eq!(cartridge.read(0xA000), 0x01);
+    }
+
+    #[test]
+    fn clock_save_round_trip() {
+        let clock = TestClock::default();
+        let mut cartridge = huc3_cartridge(&clock);
+        clock.advance(90 * 60);
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), 0x8000 + HUC3_CLOCK_SAVE_SIZE);
+
+        clock.advance(30 * 60);
+        let mut restored = huc3_cartridge(&clock);
+        restored.load_save_data(&data);
+        restored.write(0x0000, 0x0B);
+        restored.write(0xA000, 0x60);
+
+        // 120 minutes is 0x078
+        assert_eq!(read_nibble(&mut restored, 0x00), 0x8);
+        assert_eq!(read_nibble(&mut restored, 0x01), 0x7);
+    }
+}
@@ -0,0 +1,205 @@
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+pub struct Mbc1 {
+    ram_enabled: bool,
+    // 5-bit register at 0x2000-0x3FFF
+    rom_bank: u8,
+    // 2-bit register at 0x4000-0x5FFF, used as either the RAM bank or the upper ROM bank bits
+    upper_bank: u8,
+    // When set, the upper bank register also applies to 0x0000-0x3FFF and to RAM
+    advanced_banking: bool,
+    // MBC1M multicarts only wire up 4 bits of the ROM bank register, so the upper bits start at
+    // bit 4 instead of bit 5
+    multicart: bool,
+}
+
+impl Mbc1 {
+    pub fn new(multicart: bool) -> Self {
+        Mbc1 {
+            ram_enabled: false,
+            rom_bank: 1,
+            upper_bank: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    fn upper_bank_shift(&self) -> u8 {
+        if self.multicart {
+            4
+        } else {
+            5
+        }
+    }
+
+    fn lower_bank(&self) -> u8 {
+        if self.multicart {
+            self.rom_bank & 0x0F
+        } else {
+            self.rom_bank
+        }
+    }
+}
+
+impl MemoryBankController for Mbc1 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let upper_bits = (self.upper_bank << self.upper_bank_shift()) as usize;
+
+        let bank = if address < 0x4000 {
+            if self.advanced_banking {
+                upper_bits
+            } else {
+                0
+            }
+        } else {
+            upper_bits | self.lower_bank() as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                // Bank 0 can't be selected here, writing it selects bank 1. Only the 5 bits in
+                // the register are checked, which is why banks 0x20, 0x40 and 0x60 are
+                // unreachable on large cartridges.
+                self.rom_bank = match value & 0x1F {
+                    0 => 1,
+                    bank => bank,
+                };
+            }
+            0x4000..=0x5FFF => self.upper_bank = value & 0x03,
+            _ => self.advanced_banking = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        let bank = if self.advanced_banking {
+            self.upper_bank as usize
+        } else {
+            0
+        };
+        read_ram_bank(ram, bank, address)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        let bank = if self.advanced_banking {
+            self.upper_bank as usize
+        } else {
+            0
+        };
+        write_ram_bank(ram, bank, address, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, fix_checksums, Cartridge};
+    use crate::hardware::bus::MemoryMapped;
+
+    #[test]
+    fn switches_rom_banks() {
+        // MBC1 with 256 KiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x01, 0x03, 0x00)).unwrap();
+        assert_eq!(cartridge.read(0x4000), 1);
+
+        cartridge.write(0x2000, 0x05);
+        assert_eq!(cartridge.read(0x4000), 5);
+        assert_eq!(cartridge.read(0x0000), 0);
+
+        // Bank numbers past the end of the ROM wrap around
+        cartridge.write(0x2000, 0x13);
+        assert_eq!(cartridge.read(0x4000), 3);
+    }
+
+    #[test]
+    fn bank_zero_selects_bank_one() {
+        // MBC1 with 2 MiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x01, 0x06, 0x00)).unwrap();
+
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 1);
+
+        // Only the lower 5 bits are checked, so 0x20 becomes 0x21
+        cartridge.write(0x4000, 0x01);
+        assert_eq!(cartridge.read(0x4000), 0x21);
+
+        cartridge.write(0x2000, 0x02);
+        assert_eq!(cartridge.read(0x4000), 0x22);
+    }
+
+    #[test]
+    fn large_rom_mode_maps_upper_bits_into_bank_zero_area() {
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x01, 0x06, 0x00)).unwrap();
+        cartridge.write(0x4000, 0x02);
+        assert_eq!(cartridge.read(0x0000), 0x00);
+
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0x0000), 0x40);
+        assert_eq!(cartridge.read(0x4000), 0x41);
+    }
+
+    #[test]
+    fn ram_needs_enabling() {
+        // MBC1 with RAM and battery, 32 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x03, 0x02, 0x03)).unwrap();
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x12);
+        assert_eq!(cartridge.read(0xA000), 0x12);
+
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+    }
+
+    #[test]
+    fn ram_banking_only_in_advanced_mode() {
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x03, 0x02, 0x03)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x01);
+
+        cartridge.write(0x4000, 0x02);
+        assert_eq!(cartridge.read(0xA000), 0x01);
+
+        cartridge.write(0x6000, 0x01);
+        cartridge.write(0xA000, 0x03);
+        assert_eq!(cartridge.read(0xA000), 0x03);
+
+        cartridge.write(0x6000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x01);
+    }
+
+    #[test]
+    fn multicart_uses_four_bit_bank_register() {
+        let mut rom = banked_test_rom(0x01, 0x05, 0x00);
+        // A second game's header in bank 0x10 marks the cartridge as an MBC1M multicart
+        let logo = rom[0x0104..0x0134].to_vec();
+        rom[0x40104..0x40134].copy_from_slice(&logo);
+        fix_checksums(&mut rom);
+
+        let mut cartridge = Cartridge::from_bytes(rom).unwrap();
+
+        cartridge.write(0x4000, 0x01);
+        cartridge.write(0x2000, 0x12);
+        assert_eq!(cartridge.read(0x4000), 0x12);
+
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0x0000), 0x10);
+
+        cartridge.write(0x4000, 0x03);
+        assert_eq!(cartridge.read(0x0000), 0x30);
+        assert_eq!(cartridge.read(0x4000), 0x32);
+    }
+}
@@ -0,0 +1,103 @@
+use super::{read_rom_bank, MemoryBankController};
+
+// Size of the RAM built into the MBC2 chip, in 4-bit cells
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+pub struct Mbc2 {
+    ram_enabled: bool,
+    // 4-bit register, so at most 16 banks of ROM
+    rom_bank: u8,
+}
+
+impl Mbc2 {
+    pub fn new() -> Self {
+        Mbc2 {
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl MemoryBankController for Mbc2 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        // Both registers live in 0x0000-0x3FFF and bit 8 of the address picks between them
+        match address {
+            0x0000..=0x3FFF if address & 0x0100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => {
+                self.rom_bank = match value & 0x0F {
+                    0 => 1,
+                    bank => bank,
+                };
+            }
+            _ => (),
+        }
+    }
+
+    // Only the lower nibble of each cell exists, the upper one reads as set. The 512 cells are
+    // mirrored across the whole of 0xA000-0xBFFF.
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        ram[address as usize % MBC2_RAM_SIZE] | 0xF0
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        let cell = &mut ram[address as usize % MBC2_RAM_SIZE];
+        let changed = *cell != value & 0x0F;
+        *cell = value & 0x0F;
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, Cartridge};
+    use crate::hardware::bus::MemoryMapped;
+
+    #[test]
+    fn address_bit_eight_selects_register() {
+        // MBC2 with 256 KiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x05, 0x03, 0x00)).unwrap();
+
+        // Bit 8 clear is the RAM enable, which leaves the ROM bank alone
+        cartridge.write(0x0000, 0x05);
+        assert_eq!(cartridge.read(0x4000), 1);
+
+        cartridge.write(0x0100, 0x05);
+        assert_eq!(cartridge.read(0x4000), 5);
+
+        cartridge.write(0x3FFF, 0x00);
+        assert_eq!(cartridge.read(0x4000), 1);
+    }
+
+    #[test]
+    fn built_in_ram_is_four_bits_wide() {
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x06, 0x01, 0x00)).unwrap();
+        cartridge.write(0xA000, 0x0A);
+        assert_eq!(cartridge.read(0xA000), 0xFF);
+
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x35);
+        assert_eq!(cartridge.read(0xA000), 0xF5);
+
+        // 512 cells mirrored through the whole RAM area
+        assert_eq!(cartridge.read(0xA200), 0xF5);
+        assert_eq!(cartridge.read(0xBE00), 0xF5);
+    }
+}
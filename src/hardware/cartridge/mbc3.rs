@@ -0,0 +1,228 @@
+use std::rc::Rc;
+
+use super::{
+    read_ram_bank, read_rom_bank,
+    rtc::{Clock, RealTimeClock},
+    write_ram_bank, MemoryBankController,
+};
+
+pub struct Mbc3 {
+    // Also enables access to the clock registers
+    ram_enabled: bool,
+    // 7-bit register, so up to 128 banks of ROM
+    rom_bank: u8,
+    // Values 0x00-0x03 select a RAM bank and 0x08-0x0C select a clock register
+    ram_bank: u8,
+    rtc: Option<RealTimeClock>,
+    // The clock is latched by writing 0x00 and then 0x01
+    latch_armed: bool,
+}
+
+impl Mbc3 {
+    // The clock is only there on cartridges with a timer
+    pub fn new(clock: Option<Rc<dyn Clock>>) -> Self {
+        Mbc3 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rtc: clock.map(RealTimeClock::new),
+            latch_armed: false,
+        }
+    }
+}
+
+impl MemoryBankController for Mbc3 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            // Unlike MBC1 the whole bank number is checked, so only bank 0 itself is remapped
+            0x2000..=0x3FFF => {
+                self.rom_bank = match value & 0x7F {
+                    0 => 1,
+                    bank => bank,
+                };
+            }
+            0x4000..=0x5FFF => self.ram_bank = value,
+            _ => {
+                if self.latch_armed && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch_armed = value == 0x00;
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        match (self.ram_bank, &self.rtc) {
+            (bank @ 0x00..=0x03, _) => read_ram_bank(ram, bank as usize, address),
+            (register @ 0x08..=0x0C, Some(rtc)) => rtc.read(register),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled {
+            return false;
+        }
+
+        match (self.ram_bank, &mut self.rtc) {
+            (bank @ 0x00..=0x03, _) => write_ram_bank(ram, bank as usize, address, value),
+            // Setting the clock changes the state saved after the RAM
+            (register @ 0x08..=0x0C, Some(rtc)) => {
+                rtc.write(register, value);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn save_clock(&mut self) -> Option<Vec<u8>> {
+        self.rtc.as_mut().map(RealTimeClock::save)
+    }
+
+    fn load_clock(&mut self, data: &[u8]) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load(data);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        banked_test_rom,
+        rtc::{TestClock, RTC_SAVE_SIZE},
+        Cartridge,
+    };
+    use crate::hardware::bus::MemoryMapped;
+    use std::rc::Rc;
+
+    fn timer_cartridge(clock: &TestClock) -> Cartridge {
+        // MBC3 with timer, RAM and battery, 32 KiB of RAM
+        let rom = banked_test_rom(0x10, 0x02, 0x03);
+        let mut cartridge = Cartridge::from_bytes_with_clock(rom, Rc::new(clock.clone())).unwrap();
+        cartridge.write(0x0000, 0x0A);
+
+        cartridge
+    }
+
+    fn latch(cartridge: &mut Cartridge) {
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x01);
+    }
+
+    #[test]
+    fn seven_bit_rom_bank() {
+        // MBC3 with 2 MiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x11, 0x06, 0x00)).unwrap();
+
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 1);
+
+        // Banks 0x20, 0x40 and 0x60 are reachable, unlike on MBC1
+        cartridge.write(0x2000, 0x20);
+        assert_eq!(cartridge.read(0x4000), 0x20);
+
+        cartridge.write(0x2000, 0xFF);
+        assert_eq!(cartridge.read(0x4000), 0x7F);
+        assert_eq!(cartridge.read(0x0000), 0x00);
+    }
+
+    #[test]
+    fn switches_ram_banks() {
+        // MBC3 with RAM and battery, 32 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x13, 0x02, 0x03)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+
+        for bank in 0..4 {
+            cartridge.write(0x4000, bank);
+            cartridge.write(0xA123, bank + 0x10);
+        }
+        for bank in 0..4 {
+            cartridge.write(0x4000, bank);
+            assert_eq!(cartridge.read(0xA123), bank + 0x10);
+        }
+
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(cartridge.read(0xA123), 0xFF);
+    }
+
+    #[test]
+    fn clock_registers_are_mapped_over_ram() {
+        let clock = TestClock::default();
+        let mut cartridge = timer_cartridge(&clock);
+        cartridge.write(0xA000, 0x42);
+
+        cartridge.write(0x4000, 0x09);
+        cartridge.write(0xA000, 0x2A);
+        latch(&mut cartridge);
+        assert_eq!(cartridge.read(0xA000), 0x2A);
+
+        cartridge.write(0x4000, 0x00);
+        assert_eq!(cartridge.read(0xA000), 0x42);
+    }
+
+    #[test]
+    fn latch_needs_zero_then_one() {
+        let clock = TestClock::default();
+        let mut cartridge = timer_cartridge(&clock);
+        cartridge.write(0x4000, 0x08);
+
+        clock.advance(5);
+        latch(&mut cartridge);
+        assert_eq!(cartridge.read(0xA000), 5);
+
+        clock.advance(5);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 5);
+
+        cartridge.write(0x6000, 0x00);
+        cartridge.write(0x6000, 0x02);
+        cartridge.write(0x6000, 0x01);
+        assert_eq!(cartridge.read(0xA000), 5);
+
+        latch(&mut cartridge);
+        assert_eq!(cartridge.read(0xA000), 10);
+    }
+
+    #[test]
+    fn clock_is_saved_after_ram() {
+        let clock = TestClock::default();
+        let mut cartridge = timer_cartridge(&clock);
+        cartridge.write(0xA000, 0x42);
+        cartridge.write(0x4000, 0x0A);
+        cartridge.write(0xA000, 0x05);
+
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), 0x8000 + RTC_SAVE_SIZE);
+        assert_eq!(data[0], 0x42);
+
+        // Load into a fresh cartridge a day later
+        clock.advance(24 * 60 * 60);
+        let mut restored = timer_cartridge(&clock);
+        restored.load_save_data(&data);
+        assert_eq!(restored.read(0xA000), 0x42);
+
+        restored.write(0x4000, 0x0A);
+        latch(&mut restored);
+        assert_eq!(restored.read(0xA000), 0x05);
+        restored.write(0x4000, 0x0B);
+        assert_eq!(restored.read(0xA000), 0x01);
+    }
+}
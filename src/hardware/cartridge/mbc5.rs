@@ -0,0 +1,135 @@
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+pub struct Mbc5 {
+    ram_enabled: bool,
+    // 9-bit bank number split over two registers. Bank 0 can be mapped into 0x4000-0x7FFF.
+    rom_bank: u16,
+    // 4-bit register, so up to 16 banks of RAM
+    ram_bank: u8,
+    // On rumble cartridges bit 3 of the RAM bank register drives the motor instead
+    has_rumble: bool,
+    rumble: bool,
+}
+
+impl Mbc5 {
+    pub fn new(has_rumble: bool) -> Self {
+        Mbc5 {
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            rumble: false,
+        }
+    }
+}
+
+impl MemoryBankController for Mbc5 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            // The whole value is checked, not just the lower nibble
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | ((value as u16 & 0x01) << 8)
+            }
+            0x4000..=0x5FFF if self.has_rumble => {
+                self.rumble = value & 0x08 != 0;
+                self.ram_bank = value & 0x07;
+            }
+            0x4000..=0x5FFF => self.ram_bank = value & 0x0F,
+            _ => (),
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        read_ram_bank(ram, self.ram_bank as usize, address)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        self.ram_enabled && write_ram_bank(ram, self.ram_bank as usize, address, value)
+    }
+
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, fix_checksums, Cartridge};
+    use crate::hardware::bus::MemoryMapped;
+
+    #[test]
+    fn nine_bit_rom_bank() {
+        // MBC5 with 8 MiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x19, 0x08, 0x00)).unwrap();
+
+        cartridge.write(0x2000, 0x00);
+        assert_eq!(cartridge.read(0x4000), 0x00);
+
+        cartridge.write(0x2000, 0x42);
+        assert_eq!(cartridge.read(0x4000), 0x42);
+    }
+
+    #[test]
+    fn upper_rom_bank_bit() {
+        // The test ROM only has room for the low byte of the bank number, so mark bank 0x142
+        // separately
+        let mut rom = banked_test_rom(0x19, 0x08, 0x00);
+        rom[0x142 * 0x4000 + 1] = 0x99;
+        fix_checksums(&mut rom);
+        let mut cartridge = Cartridge::from_bytes(rom).unwrap();
+
+        cartridge.write(0x2000, 0x42);
+        assert_eq!(cartridge.read(0x4001), 0x00);
+
+        cartridge.write(0x3000, 0x01);
+        assert_eq!(cartridge.read(0x4001), 0x99);
+    }
+
+    #[test]
+    fn sixteen_ram_banks() {
+        // MBC5 with RAM and battery, 128 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x1B, 0x02, 0x04)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+
+        cartridge.write(0x4000, 0x0F);
+        cartridge.write(0xA000, 0x0F);
+        cartridge.write(0x4000, 0x00);
+        cartridge.write(0xA000, 0x00);
+
+        cartridge.write(0x4000, 0x0F);
+        assert_eq!(cartridge.read(0xA000), 0x0F);
+        assert!(!cartridge.rumble());
+    }
+
+    #[test]
+    fn rumble_uses_ram_bank_bit_three() {
+        // MBC5 with rumble, RAM and battery, 32 KiB of RAM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x1E, 0x02, 0x03)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x01);
+        cartridge.write(0xA000, 0x11);
+
+        cartridge.write(0x4000, 0x09);
+        assert!(cartridge.rumble());
+        assert_eq!(cartridge.read(0xA000), 0x11);
+
+        cartridge.write(0x4000, 0x01);
+        assert!(!cartridge.rumble());
+    }
+}
@@ -0,0 +1,203 @@
+use super::MemoryBankController;
+
+// MBC6 banks everything in halves: 8 KiB of ROM at a time and 4 KiB of RAM at a time
+const HALF_ROM_BANK_SIZE: usize = 0x2000;
+const HALF_RAM_BANK_SIZE: usize = 0x1000;
+
+// Size of the flash chip on the cartridge, which can be mapped in place of either ROM bank
+const FLASH_SIZE: usize = 0x100000;
+
+pub struct Mbc6 {
+    ram_enabled: bool,
+    // Banks for 0x4000-0x5FFF and 0x6000-0x7FFF
+    rom_banks: [u8; 2],
+    // Whether each ROM area shows flash instead of ROM
+    flash_selected: [bool; 2],
+    // Banks for 0xA000-0xAFFF and 0xB000-0xBFFF
+    ram_banks: [u8; 2],
+    flash_enabled: bool,
+    flash_write_enabled: bool,
+    // The flash chip's command interface isn't emulated, so writes program bytes directly. As on
+    // the real chip, programming can only clear bits until the flash is erased.
+    flash: Vec<u8>,
+    flash_written: bool,
+}
+
+impl Mbc6 {
+    pub fn new() -> Self {
+        Mbc6 {
+            ram_enabled: false,
+            rom_banks: [0; 2],
+            flash_selected: [false; 2],
+            ram_banks: [0; 2],
+            flash_enabled: false,
+            flash_write_enabled: false,
+            flash: vec![0xFF; FLASH_SIZE],
+            flash_written: false,
+        }
+    }
+
+    // Offset of an address in 0x4000-0x7FFF into ROM or flash, and whether it is in flash
+    fn rom_offset(&self, address: u16) -> (usize, bool) {
+        let area = (address as usize - 0x4000) / HALF_ROM_BANK_SIZE;
+        let bank = self.rom_banks[area] as usize;
+        let offset = bank * HALF_ROM_BANK_SIZE + (address as usize % HALF_ROM_BANK_SIZE);
+
+        (offset, self.flash_enabled && self.flash_selected[area])
+    }
+
+    fn ram_offset(&self, ram: &[u8], address: u16) -> usize {
+        let area = (address as usize - 0xA000) / HALF_RAM_BANK_SIZE;
+        let bank = self.ram_banks[area] as usize;
+
+        (bank * HALF_RAM_BANK_SIZE + (address as usize % HALF_RAM_BANK_SIZE)) % ram.len()
+    }
+}
+
+impl MemoryBankController for Mbc6 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        if address < 0x4000 {
+            return rom[address as usize];
+        }
+
+        match self.rom_offset(address) {
+            (offset, true) => self.flash[offset % FLASH_SIZE],
+            (offset, false) => rom[offset % rom.len()],
+        }
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x03FF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0400..=0x07FF => self.ram_banks[0] = value & 0x07,
+            0x0800..=0x0BFF => self.ram_banks[1] = value & 0x07,
+            0x0C00..=0x0FFF => self.flash_enabled = value & 0x01 != 0,
+            0x1000..=0x1FFF => self.flash_write_enabled = value & 0x01 != 0,
+            0x2000..=0x27FF => self.rom_banks[0] = value & 0x7F,
+            0x2800..=0x2FFF => self.flash_selected[0] = value == 0x08,
+            0x3000..=0x37FF => self.rom_banks[1] = value & 0x7F,
+            0x3800..=0x3FFF => self.flash_selected[1] = value == 0x08,
+            _ => {
+                if let (offset, true) = self.rom_offset(address) {
+                    if self.flash_write_enabled {
+                        let byte = &mut self.flash[offset % FLASH_SIZE];
+                        self.flash_written |= *byte & value != *byte;
+                        *byte &= value;
+                    }
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled || ram.is_empty() {
+            return 0xFF;
+        }
+
+        ram[self.ram_offset(ram, address)]
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled || ram.is_empty() {
+            return false;
+        }
+
+        let offset = self.ram_offset(ram, address);
+        let changed = ram[offset] != value;
+        ram[offset] = value;
+        changed
+    }
+
+    fn flash(&self) -> Option<&[u8]> {
+        Some(&self.flash)
+    }
+
+    fn load_flash(&mut self, data: &[u8]) {
+        self.flash[..data.len()].copy_from_slice(data);
+    }
+
+    fn take_flash_written(&mut self) -> bool {
+        std::mem::take(&mut self.flash_written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, Cartridge};
+    use super::FLASH_SIZE;
+    use crate::hardware::bus::MemoryMapped;
+
+    fn mbc6_cartridge() -> Cartridge {
+        // MBC6 with 1 MiB of ROM and 32 KiB of RAM
+        Cartridge::from_bytes(banked_test_rom(0x20, 0x05, 0x03)).unwrap()
+    }
+
+    #[test]
+    fn switches_rom_in_halves() {
+        let mut cartridge = mbc6_cartridge();
+
+        // 8 KiB banks 4 and 6 are the first halves of 16 KiB banks 2 and 3
+        cartridge.write(0x2000, 0x04);
+        cartridge.write(0x3000, 0x06);
+        assert_eq!(cartridge.read(0x4000), 2);
+        assert_eq!(cartridge.read(0x6000), 3);
+    }
+
+    #[test]
+    fn switches_ram_in_halves() {
+        let mut cartridge = mbc6_cartridge();
+        cartridge.write(0x0000, 0x0A);
+
+        cartridge.write(0x0400, 0x01);
+        cartridge.write(0xA000, 0x11);
+        cartridge.write(0x0800, 0x01);
+        assert_eq!(cartridge.read(0xB000), 0x11);
+
+        cartridge.write(0x0800, 0x02);
+        assert_eq!(cartridge.read(0xB000), 0x00);
+    }
+
+    #[test]
+    fn flash_is_mapped_over_rom() {
+        let mut cartridge = mbc6_cartridge();
+        cartridge.write(0x0C00, 0x01);
+        cartridge.write(0x2800, 0x08);
+        assert_eq!(cartridge.read(0x4000), 0xFF);
+
+        // Writes are ignored until flash writing is enabled
+        cartridge.write(0x4000, 0x12);
+        assert_eq!(cartridge.read(0x4000), 0xFF);
+
+        cartridge.write(0x1000, 0x01);
+        cartridge.write(0x4000, 0x12);
+        assert_eq!(cartridge.read(0x4000), 0x12);
+
+        // The other half still shows ROM
+        cartridge.write(0x3000, 0x02);
+        assert_eq!(cartridge.read(0x6000), 1);
+    }
+
+    #[test]
+    fn flash_is_saved_after_ram() {
+        let mut cartridge = mbc6_cartridge();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x11);
+        cartridge.write(0x0C00, 0x01);
+        cartridge.write(0x1000, 0x01);
+        cartridge.write(0x2800, 0x08);
+        cartridge.dirty = false;
+        cartridge.write(0x4000, 0x12);
+        assert!(cartridge.dirty);
+
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), 0x8000 + FLASH_SIZE);
+        assert_eq!(data[0x0000], 0x11);
+        assert_eq!(data[0x8000], 0x12);
+
+        let mut restored = mbc6_cartridge();
+        restored.load_save_data(&data);
+        restored.write(0x0C00, 0x01);
+        restored.write(0x2800, 0x08);
+        assert_eq!(restored.read(0x4000), 0x12);
+    }
+}
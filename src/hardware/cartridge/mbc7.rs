@@ -0,0 +1,381 @@
+use super::{read_rom_bank, MemoryBankController};
+
+// Size of the 93LC56 EEPROM, organised as 128 16-bit words
+pub const MBC7_EEPROM_SIZE: usize = 0x100;
+
+// Latched accelerometer value when the cartridge is held flat, and how far it moves for 1g
+const ACCELEROMETER_CENTRE: f32 = 0x81D0 as f32;
+const ACCELEROMETER_RANGE: f32 = 0x70 as f32;
+
+// Opcodes, which follow the start bit
+const READ: u32 = 0b10;
+const WRITE: u32 = 0b01;
+const ERASE: u32 = 0b11;
+// These share opcode 0b00 and are told apart by the top two address bits
+const EXTENDED: u32 = 0b00;
+const WRITE_ALL: u32 = 0b01;
+const ERASE_ALL: u32 = 0b10;
+const ENABLE_WRITES: u32 = 0b11;
+const DISABLE_WRITES: u32 = 0b00;
+
+// Start bit, 2 opcode bits and 8 address bits, followed by 16 data bits for writes
+const COMMAND_BITS: u8 = 11;
+const WRITE_COMMAND_BITS: u8 = COMMAND_BITS + 16;
+
+// The EEPROM is a serial chip, driven a bit at a time by toggling its pins through a register.
+// Its contents are kept in the cartridge RAM so they are saved along with everything else.
+struct Eeprom {
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    write_enabled: bool,
+    // Bits received since the start bit
+    command: u32,
+    command_bits: u8,
+    // Word being shifted out by a read
+    output: u16,
+    output_bits: u8,
+}
+
+impl Eeprom {
+    fn new() -> Self {
+        Eeprom {
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            write_enabled: false,
+            command: 0,
+            command_bits: 0,
+            output: 0,
+            output_bits: 0,
+        }
+    }
+
+    fn read_pins(&self) -> u8 {
+        (self.chip_select as u8) << 7
+            | (self.clock as u8) << 6
+            | (self.data_in as u8) << 1
+            | self.data_out as u8
+    }
+
+    // Returns whether the storage was written to
+    fn write_pins(&mut self, storage: &mut [u8], value: u8) -> bool {
+        let chip_select = value & 0x80 != 0;
+        let clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+
+        let mut written = false;
+        if !chip_select {
+            // Deselecting the chip abandons whatever it was doing
+            self.command = 0;
+            self.command_bits = 0;
+            self.output_bits = 0;
+        } else if clock && !self.clock {
+            written = self.clock_in(storage);
+        }
+
+        self.chip_select = chip_select;
+        self.clock = clock;
+        written
+    }
+
+    // Runs on the rising edge of the clock
+    fn clock_in(&mut self, storage: &mut [u8]) -> bool {
+        if self.output_bits > 0 {
+            self.data_out = self.output & 0x8000 != 0;
+            self.output <<= 1;
+            self.output_bits -= 1;
+            return false;
+        }
+
+        // Zeros before the start bit are ignored
+        if self.command_bits == 0 && !self.data_in {
+            return false;
+        }
+
+        self.command = (self.command << 1) | self.data_in as u32;
+        self.command_bits += 1;
+
+        if self.command_bits < COMMAND_BITS {
+            return false;
+        }
+
+        // The start bit, opcode and address, without any data after them
+        let header = self.command >> (self.command_bits - COMMAND_BITS);
+        let opcode = (header >> 8) & 0x03;
+        let extended = (header >> 6) & 0x03;
+        let needs_data = opcode == WRITE || (opcode == EXTENDED && extended == WRITE_ALL);
+
+        (!needs_data || self.command_bits == WRITE_COMMAND_BITS)
+            && self.execute(storage, opcode, extended)
+    }
+
+    fn execute(&mut self, storage: &mut [u8], opcode: u32, extended: u32) -> bool {
+        let (address, data) = if self.command_bits == WRITE_COMMAND_BITS {
+            ((self.command >> 16) & 0x7F, self.command as u16)
+        } else {
+            (self.command & 0x7F, 0xFFFF)
+        };
+        let address = address as usize;
+
+        let mut written = false;
+        match (opcode, extended) {
+            (READ, _) => {
+                // A dummy zero comes out before the data
+                self.output = read_word(storage, address);
+                self.output_bits = 16;
+                self.data_out = false;
+            }
+            (WRITE, _) | (ERASE, _) if self.write_enabled => {
+                write_word(storage, address, data);
+                self.data_out = true;
+                written = true;
+            }
+            (EXTENDED, WRITE_ALL) | (EXTENDED, ERASE_ALL) if self.write_enabled => {
+                for address in 0..MBC7_EEPROM_SIZE / 2 {
+                    write_word(storage, address, data);
+                }
+                self.data_out = true;
+                written = true;
+            }
+            (EXTENDED, ENABLE_WRITES) => self.write_enabled = true,
+            (EXTENDED, DISABLE_WRITES) => self.write_enabled = false,
+            _ => (),
+        }
+
+        self.command = 0;
+        self.command_bits = 0;
+        written
+    }
+}
+
+fn read_word(storage: &[u8], address: usize) -> u16 {
+    u16::from_le_bytes([storage[address * 2], storage[address * 2 + 1]])
+}
+
+fn write_word(storage: &mut [u8], address: usize, value: u16) {
+    storage[address * 2..address * 2 + 2].copy_from_slice(&value.to_le_bytes());
+}
+
+// MBC7 replaces RAM with an accelerometer and an EEPROM, both accessed through registers in
+// 0xA000-0xAFFF
+pub struct Mbc7 {
+    // Both enables have to be set before the registers can be used
+    ram_enabled: bool,
+    registers_enabled: bool,
+    rom_bank: u8,
+    // Current tilt from the host, from -1.0 to 1.0 on each axis
+    tilt: (f32, f32),
+    latched: (u16, u16),
+    // Latching is done by writing 0x55 and then 0xAA
+    latch_armed: bool,
+    eeprom: Eeprom,
+}
+
+impl Mbc7 {
+    pub fn new() -> Self {
+        Mbc7 {
+            ram_enabled: false,
+            registers_enabled: false,
+            rom_bank: 1,
+            tilt: (0.0, 0.0),
+            latched: (0x8000, 0x8000),
+            latch_armed: false,
+            eeprom: Eeprom::new(),
+        }
+    }
+}
+
+fn accelerometer_value(tilt: f32) -> u16 {
+    (ACCELEROMETER_CENTRE + tilt.clamp(-1.0, 1.0) * ACCELEROMETER_RANGE) as u16
+}
+
+impl MemoryBankController for Mbc7 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 {
+            0
+        } else {
+            self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            0x2000..=0x3FFF => self.rom_bank = value & 0x7F,
+            0x4000..=0x5FFF => self.registers_enabled = value == 0x40,
+            _ => (),
+        }
+    }
+
+    fn read_ram(&self, _ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled || !self.registers_enabled || address >= 0xB000 {
+            return 0xFF;
+        }
+
+        match (address >> 4) & 0x0F {
+            0x2 => self.latched.0 as u8,
+            0x3 => (self.latched.0 >> 8) as u8,
+            0x4 => self.latched.1 as u8,
+            0x5 => (self.latched.1 >> 8) as u8,
+            0x6 => 0x00,
+            0x8 => self.eeprom.read_pins(),
+            _ => 0xFF,
+        }
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        if !self.ram_enabled || !self.registers_enabled || address >= 0xB000 {
+            return false;
+        }
+
+        match (address >> 4) & 0x0F {
+            0x0 if value == 0x55 => {
+                self.latched = (0x8000, 0x8000);
+                self.latch_armed = true;
+            }
+            0x1 if value == 0xAA && self.latch_armed => {
+                self.latched = (
+                    accelerometer_value(self.tilt.0),
+                    accelerometer_value(self.tilt.1),
+                );
+                self.latch_armed = false;
+            }
+            0x8 => return self.eeprom.write_pins(ram, value),
+            _ => (),
+        }
+
+        false
+    }
+
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt = (x, y);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{banked_test_rom, Cartridge};
+    use super::*;
+    use crate::hardware::bus::MemoryMapped;
+
+    const EEPROM: u16 = 0xA080;
+
+    fn mbc7_cartridge() -> Cartridge {
+        // MBC7 with 1 MiB of ROM
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x22, 0x05, 0x00)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0x4000, 0x40);
+
+        cartridge
+    }
+
+    // Clocks bits into the EEPROM, most significant first, with chip select held high
+    fn send_bits(cartridge: &mut Cartridge, bits: u32, count: u8) {
+        for bit in (0..count).rev() {
+            let data_in = ((bits >> bit) & 0x01) as u8 * 0x02;
+            cartridge.write(EEPROM, 0x80 | data_in);
+            cartridge.write(EEPROM, 0xC0 | data_in);
+        }
+    }
+
+    // Start bit, opcode and address
+    fn send_command(cartridge: &mut Cartridge, opcode: u32, address: u32) {
+        send_bits(cartridge, 0x400 | (opcode << 8) | address, COMMAND_BITS);
+    }
+
+    fn read_bits(cartridge: &mut Cartridge, count: u8) -> u32 {
+        let mut value = 0;
+        for _ in 0..count {
+            cartridge.write(EEPROM, 0x80);
+            cartridge.write(EEPROM, 0xC0);
+            value = (value << 1) | (cartridge.read(EEPROM) & 0x01) as u32;
+        }
+
+        value
+    }
+
+    fn deselect(cartridge: &mut Cartridge) {
+        cartridge.write(EEPROM, 0x00);
+    }
+
+    #[test]
+    fn accelerometer_latches_tilt() {
+        let mut cartridge = mbc7_cartridge();
+        cartridge.set_tilt(0.0, -1.0);
+
+        cartridge.write(0xA000, 0x55);
+        assert_eq!(cartridge.read(0xA020), 0x00);
+        assert_eq!(cartridge.read(0xA030), 0x80);
+
+        cartridge.write(0xA010, 0xAA);
+        assert_eq!(cartridge.read(0xA020), 0xD0);
+        assert_eq!(cartridge.read(0xA030), 0x81);
+        assert_eq!(cartridge.read(0xA040), 0x60);
+        assert_eq!(cartridge.read(0xA050), 0x81);
+
+        // Tilting again doesn't change the latched value
+        cartridge.set_tilt(1.0, 1.0);
+        assert_eq!(cartridge.read(0xA020), 0xD0);
+    }
+
+    #[test]
+    fn registers_need_both_enables() {
+        let mut cartridge = Cartridge::from_bytes(banked_test_rom(0x22, 0x05, 0x00)).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        assert_eq!(cartridge.read(0xA060), 0xFF);
+
+        cartridge.write(0x4000, 0x40);
+        assert_eq!(cartridge.read(0xA060), 0x00);
+    }
+
+    #[test]
+    fn eeprom_write_then_read() {
+        let mut cartridge = mbc7_cartridge();
+
+        // Erased EEPROM reads as all ones
+        send_command(&mut cartridge, READ, 0x05);
+        assert_eq!(cartridge.read(EEPROM) & 0x01, 0);
+        assert_eq!(read_bits(&mut cartridge, 16), 0xFFFF);
+        deselect(&mut cartridge);
+
+        // Writes are ignored until enabled
+        send_command(&mut cartridge, WRITE, 0x05);
+        send_bits(&mut cartridge, 0x1234, 16);
+        deselect(&mut cartridge);
+
+        send_command(&mut cartridge, EXTENDED, 0xC0);
+        deselect(&mut cartridge);
+        send_command(&mut cartridge, WRITE, 0x05);
+        send_bits(&mut cartridge, 0xBEEF, 16);
+        deselect(&mut cartridge);
+
+        send_command(&mut cartridge, READ, 0x05);
+        assert_eq!(read_bits(&mut cartridge, 16), 0xBEEF);
+        deselect(&mut cartridge);
+
+        // The contents are saved with the cartridge
+        let data = cartridge.save_data();
+        assert_eq!(data.len(), MBC7_EEPROM_SIZE);
+        assert_eq!(&data[10..12], &[0xEF, 0xBE]);
+    }
+
+    #[test]
+    fn eeprom_erase_all() {
+        let mut cartridge = mbc7_cartridge();
+        let mut data = vec![0; MBC7_EEPROM_SIZE];
+        data[0] = 0x12;
+        cartridge.load_save_data(&data);
+
+        send_command(&mut cartridge, EXTENDED, 0xC0);
+        deselect(&mut cartridge);
+        send_command(&mut cartridge, EXTENDED, 0x80);
+        deselect(&mut cartridge);
+
+        assert!(cartridge.save_data().iter().all(|&byte| byte == 0xFF));
+    }
+}
@@ -0,0 +1,176 @@
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+// MMM01 multicarts start in a menu stored at the end of the ROM. The menu sets up which part of
+// the ROM the chosen game lives in and then locks the mapping, after which the game sees an
+// MBC1-like mapper confined to its own banks.
+pub struct Mmm01 {
+    mapped: bool,
+    ram_enabled: bool,
+    // 5-bit register, as on MBC1
+    rom_bank: u8,
+    // Bits of the ROM bank number set by the menu, which the game can't change
+    rom_bank_base: usize,
+    // Bits of rom_bank that were fixed by the menu, for games smaller than 512 KiB
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    ram_bank_base: usize,
+    advanced_banking: bool,
+}
+
+impl Mmm01 {
+    pub fn new() -> Self {
+        Mmm01 {
+            mapped: false,
+            ram_enabled: false,
+            rom_bank: 1,
+            rom_bank_base: 0,
+            rom_bank_mask: 0,
+            ram_bank: 0,
+            ram_bank_base: 0,
+            advanced_banking: false,
+        }
+    }
+
+    // As on MBC1, the RAM bank register is only used in advanced banking mode
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            self.ram_bank as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl MemoryBankController for Mmm01 {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        // Until a game is mapped the last 32 KiB of ROM, where the menu is, appears at 0x0000
+        if !self.mapped {
+            let last_bank = rom.len() / 0x4000 - 1;
+            let bank = if address < 0x4000 {
+                last_bank - 1
+            } else {
+                last_bank
+            };
+            return read_rom_bank(rom, bank, address);
+        }
+
+        let bank = if address < 0x4000 {
+            self.rom_bank_base | (self.rom_bank & self.rom_bank_mask) as usize
+        } else {
+            self.rom_bank_base | self.rom_bank as usize
+        };
+
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => {
+                self.ram_enabled = value & 0x0F == 0x0A;
+                if value & 0x40 != 0 {
+                    self.mapped = true;
+                }
+            }
+            0x2000..=0x3FFF => {
+                let bank =
+                    (self.rom_bank & self.rom_bank_mask) | (value & 0x1F & !self.rom_bank_mask);
+                self.rom_bank = if bank == 0 { 1 } else { bank };
+
+                if !self.mapped {
+                    self.rom_bank_base = ((value as usize >> 5) & 0x03) << 5;
+                }
+            }
+            0x4000..=0x5FFF => {
+                self.ram_bank = value & 0x03;
+
+                if !self.mapped {
+                    self.ram_bank_base = ((value as usize >> 2) & 0x03) << 2;
+                    self.rom_bank_base |= ((value as usize >> 4) & 0x03) << 7;
+                }
+            }
+            _ => {
+                self.advanced_banking = value & 0x01 != 0;
+
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 1) & 0x1E;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xFF;
+        }
+
+        read_ram_bank(ram, self.ram_bank_base | self.ram_bank(), address)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        self.ram_enabled
+            && write_ram_bank(ram, self.ram_bank_base | self.ram_bank(), address, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        banked_test_rom, fix_checksums, header::compute_header_checksum, Cartridge, Mapper,
+    };
+    use crate::hardware::bus::MemoryMapped;
+
+    // 1 MiB multicart whose menu, in the last 32 KiB, has its own MMM01 header
+    fn mmm01_cartridge() -> Cartridge {
+        let mut rom = banked_test_rom(0x01, 0x05, 0x00);
+        let menu = rom.len() - 0x8000;
+        rom.copy_within(0x0100..0x0150, menu + 0x0100);
+        rom[menu + 0x0147] = 0x0B;
+        rom[menu + 0x014D] = compute_header_checksum(&rom[menu..]);
+
+        Cartridge::from_bytes(rom).unwrap()
+    }
+
+    #[test]
+    fn starts_in_menu_at_end_of_rom() {
+        let cartridge = mmm01_cartridge();
+        assert_eq!(cartridge.header().cartridge_type.mapper, Mapper::Mmm01);
+
+        assert_eq!(cartridge.read(0x0000), 62);
+        assert_eq!(cartridge.read(0x4000), 63);
+    }
+
+    #[test]
+    fn ignores_mmm01_type_without_a_valid_menu_header() {
+        // An MBC1 ROM that happens to have 0x0B where the menu's cartridge type would be
+        let mut rom = banked_test_rom(0x01, 0x05, 0x00);
+        let menu = rom.len() - 0x8000;
+        rom[menu + 0x0147] = 0x0B;
+        fix_checksums(&mut rom);
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert_eq!(cartridge.header().cartridge_type.mapper, Mapper::Mbc1);
+        assert!(cartridge.header().global_checksum_valid);
+        assert_eq!(cartridge.read(0x0000), 0);
+    }
+
+    #[test]
+    fn menu_maps_game_and_locks() {
+        let mut cartridge = mmm01_cartridge();
+
+        // Game starting at bank 0x20 (mid bits 0b01), using 4 banks
+        cartridge.write(0x2000, 0x20);
+        cartridge.write(0x6000, 0x38);
+        cartridge.write(0x0000, 0x40);
+
+        assert_eq!(cartridge.read(0x0000), 0x20);
+        assert_eq!(cartridge.read(0x4000), 0x21);
+
+        cartridge.write(0x2000, 0x03);
+        assert_eq!(cartridge.read(0x4000), 0x23);
+
+        // Only the low 2 bits belong to the game now
+        cartridge.write(0x2000, 0x07);
+        assert_eq!(cartridge.read(0x4000), 0x23);
+        assert_eq!(cartridge.read(0x0000), 0x20);
+    }
+}
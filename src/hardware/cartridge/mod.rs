@@ -0,0 +1,666 @@
+mod camera;
+mod header;
+mod huc1;
+mod huc3;
+mod mbc1;
+mod mbc2;
+mod mbc3;
+mod mbc5;
+mod mbc6;
+mod mbc7;
+mod mmm01;
+mod rom_only;
+mod rtc;
+
+use std::{
+    error::Error,
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::bus::MemoryMapped;
+
+use self::{
+    camera::PocketCamera,
+    header::NINTENDO_LOGO,
+    huc1::HuC1,
+    huc3::HuC3,
+    mbc1::Mbc1,
+    mbc2::{Mbc2, MBC2_RAM_SIZE},
+    mbc3::Mbc3,
+    mbc5::Mbc5,
+    mbc6::Mbc6,
+    mbc7::{Mbc7, MBC7_EEPROM_SIZE},
+    mmm01::Mmm01,
+    rom_only::RomOnly,
+};
+
+pub use camera::{ImageSource, StaticImage, CAMERA_HEIGHT, CAMERA_WIDTH};
+pub use header::{CartridgeHeader, CartridgeType, CgbSupport, Licensee, Mapper};
+pub use rtc::{Clock, SystemClock};
+
+const ROM_BANK_SIZE: usize = 0x4000;
+const RAM_BANK_SIZE: usize = 0x2000;
+
+// Minimum time between automatic saves, in seconds
+const AUTOSAVE_INTERVAL: u64 = 5;
+
+#[derive(Debug)]
+pub enum CartridgeError {
+    Io(io::Error),
+    // The file is shorter than the header or the ROM size it declares
+    Truncated { expected: usize, actual: usize },
+    HeaderChecksum { expected: u8, actual: u8 },
+    UnknownCartridgeType(u8),
+    UnsupportedRomSize(u8),
+    UnsupportedRamSize(u8),
+    UnsupportedMapper(Mapper),
+}
+
+impl fmt::Display for CartridgeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CartridgeError::Io(error) => write!(f, "could not read cartridge: {}", error),
+            CartridgeError::Truncated { expected, actual } => write!(
+                f,
+                "cartridge is truncated: expected {} bytes but found {}",
+                expected, actual
+            ),
+            CartridgeError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "header checksum mismatch: header says {:#04X} but computed {:#04X}",
+                expected, actual
+            ),
+            CartridgeError::UnknownCartridgeType(code) => {
+                write!(f, "unknown cartridge type {:#04X}", code)
+            }
+            CartridgeError::UnsupportedRomSize(code) => {
+                write!(f, "unsupported ROM size code {:#04X}", code)
+            }
+            CartridgeError::UnsupportedRamSize(code) => {
+                write!(f, "unsupported RAM size code {:#04X}", code)
+            }
+            CartridgeError::UnsupportedMapper(mapper) => {
+                write!(f, "{:?} cartridges are not supported", mapper)
+            }
+        }
+    }
+}
+
+impl Error for CartridgeError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CartridgeError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for CartridgeError {
+    fn from(error: io::Error) -> Self {
+        CartridgeError::Io(error)
+    }
+}
+
+// The mapper chip on the cartridge, which decides which parts of ROM and RAM are visible. Writes
+// to the ROM area go to the controller's registers. The cartridge owns the actual ROM and RAM, so
+// the controller only keeps track of its banking state.
+pub trait MemoryBankController {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8;
+    fn write_register(&mut self, address: u16, value: u8);
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8;
+    // Returns whether anything that goes in the .sav file changed
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool;
+
+    // Whether the rumble motor is currently on, for cartridges that have one
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    // State of the cartridge's real-time clock, if it has one, in the format stored after the RAM
+    // in a .sav file
+    fn save_clock(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_clock(&mut self, _data: &[u8]) {}
+
+    // Contents of the cartridge's flash memory, if it has any, which is stored between the RAM
+    // and the clock state in a .sav file
+    fn flash(&self) -> Option<&[u8]> {
+        None
+    }
+
+    fn load_flash(&mut self, _data: &[u8]) {}
+
+    // Whether the flash has been written to since this was last called
+    fn take_flash_written(&mut self) -> bool {
+        false
+    }
+
+    // Whether the infrared LED is lit, on cartridges with an IR port
+    fn infrared_led(&self) -> bool {
+        false
+    }
+
+    // Whether the IR sensor is receiving light from another device
+    fn set_infrared_signal(&mut self, _signal: bool) {}
+
+    // Tilt for cartridges with an accelerometer, from -1.0 to 1.0 on each axis
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    fn set_image_source(&mut self, _source: Box<dyn ImageSource>) {}
+}
+
+// Bank numbers past the end of the ROM wrap around, as the unused upper bits of the bank number
+// aren't wired up to anything
+fn read_rom_bank(rom: &[u8], bank: usize, address: u16) -> u8 {
+    let bank_count = rom.len() / ROM_BANK_SIZE;
+    let offset = (bank % bank_count) * ROM_BANK_SIZE + (address as usize % ROM_BANK_SIZE);
+
+    rom[offset]
+}
+
+// Missing RAM reads as open bus, and RAM smaller than a bank is mirrored across it
+fn read_ram_bank(ram: &[u8], bank: usize, address: u16) -> u8 {
+    if ram.is_empty() {
+        return 0xFF;
+    }
+
+    let offset = bank * RAM_BANK_SIZE + (address as usize % RAM_BANK_SIZE);
+    ram[offset % ram.len()]
+}
+
+// Returns whether the value in RAM changed
+fn write_ram_bank(ram: &mut [u8], bank: usize, address: u16, value: u8) -> bool {
+    if ram.is_empty() {
+        return false;
+    }
+
+    let offset = bank * RAM_BANK_SIZE + (address as usize % RAM_BANK_SIZE);
+    let length = ram.len();
+    let changed = ram[offset % length] != value;
+    ram[offset % length] = value;
+    changed
+}
+
+// MBC1M multicarts are wired differently from normal MBC1 cartridges but use the same cartridge
+// type, so they're spotted by a second copy of the Nintendo logo at the start of bank 0x10
+fn is_mbc1_multicart(rom: &[u8]) -> bool {
+    const LOGO_ADDRESS: usize = 0x10 * ROM_BANK_SIZE + 0x0104;
+
+    rom.len() == 0x100000 && rom[LOGO_ADDRESS..LOGO_ADDRESS + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+}
+
+// MMM01 multicarts start in a menu at the end of the ROM, and the header describing the cartridge
+// is the menu's. The header at the start belongs to the first game. Any ROM can have an MMM01
+// cartridge type at that spot by chance, so the rest of the menu's header has to check out too.
+fn mmm01_menu(rom: &[u8]) -> Option<&[u8]> {
+    if rom.len() <= 0x8000 {
+        return None;
+    }
+
+    let menu = &rom[rom.len() - 0x8000..];
+    let valid = menu[0x0104..0x0104 + NINTENDO_LOGO.len()] == NINTENDO_LOGO
+        && menu[0x014D] == header::compute_header_checksum(menu);
+    match menu[0x0147] {
+        0x0B..=0x0D if valid => Some(menu),
+        _ => None,
+    }
+}
+
+pub struct Cartridge {
+    header: CartridgeHeader,
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    controller: Box<dyn MemoryBankController>,
+    clock: Rc<dyn Clock>,
+    // Where battery-backed RAM is persisted, if the cartridge has a battery and came from a file
+    save_path: Option<PathBuf>,
+    // Set when RAM has changed since the last save
+    dirty: bool,
+    last_saved: u64,
+}
+
+impl Cartridge {
+    // Loads a .gb or .gbc file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, CartridgeError> {
+        Self::load_with_clock(path, Rc::new(SystemClock))
+    }
+
+    // Battery-backed cartridges also load their RAM from a .sav file next to the ROM, if there is
+    // one. The file is a raw dump of the RAM, optionally followed by the clock state, which is
+    // the same format other emulators use.
+    pub fn load_with_clock<P: AsRef<Path>>(
+        path: P,
+        clock: Rc<dyn Clock>,
+    ) -> Result<Self, CartridgeError> {
+        let path = path.as_ref();
+        let rom = fs::read(path)?;
+        let mut cartridge = Self::from_bytes_with_clock(rom, clock)?;
+
+        if cartridge.header.cartridge_type.battery {
+            let save_path = path.with_extension("sav");
+            match fs::read(&save_path) {
+                Ok(data) => cartridge.load_save_data(&data),
+                Err(error) if error.kind() == io::ErrorKind::NotFound => (),
+                Err(error) => return Err(error.into()),
+            }
+            cartridge.save_path = Some(save_path);
+        }
+
+        Ok(cartridge)
+    }
+
+    pub fn from_bytes(rom: Vec<u8>) -> Result<Self, CartridgeError> {
+        Self::from_bytes_with_clock(rom, Rc::new(SystemClock))
+    }
+
+    // The clock drives autosaving and cartridges with a real-time clock
+    pub fn from_bytes_with_clock(
+        mut rom: Vec<u8>,
+        clock: Rc<dyn Clock>,
+    ) -> Result<Self, CartridgeError> {
+        let menu = mmm01_menu(&rom);
+        let mut header = CartridgeHeader::parse(menu.unwrap_or(&rom))?;
+
+        if rom.len() < header.rom_size {
+            return Err(CartridgeError::Truncated {
+                expected: header.rom_size,
+                actual: rom.len(),
+            });
+        }
+
+        // The menu's header was parsed from its own 32 KiB, but the checksum covers the whole ROM
+        if menu.is_some() {
+            header.global_checksum_valid =
+                header::compute_global_checksum(&rom[..header.rom_size]) == header.global_checksum;
+        }
+
+        // Anything past the declared size can't be reached by the mapper
+        rom.truncate(header.rom_size);
+
+        let controller: Box<dyn MemoryBankController> = match header.cartridge_type.mapper {
+            Mapper::RomOnly => Box::new(RomOnly),
+            Mapper::Mbc1 => Box::new(Mbc1::new(is_mbc1_multicart(&rom))),
+            Mapper::Mbc2 => Box::new(Mbc2::new()),
+            Mapper::Mbc3 if header.cartridge_type.timer => Box::new(Mbc3::new(Some(clock.clone()))),
+            Mapper::Mbc3 => Box::new(Mbc3::new(None)),
+            Mapper::Mbc5 => Box::new(Mbc5::new(header.cartridge_type.rumble)),
+            Mapper::Mbc6 => Box::new(Mbc6::new()),
+            Mapper::Mbc7 => Box::new(Mbc7::new()),
+            Mapper::Mmm01 => Box::new(Mmm01::new()),
+            Mapper::PocketCamera => Box::new(PocketCamera::new()),
+            Mapper::HuC1 => Box::new(HuC1::new()),
+            Mapper::HuC3 => Box::new(HuC3::new(clock.clone())),
+            mapper => return Err(CartridgeError::UnsupportedMapper(mapper)),
+        };
+
+        let ram = match header.cartridge_type.mapper {
+            // MBC2 has its RAM built into the mapper, so the header declares none
+            Mapper::Mbc2 => vec![0; MBC2_RAM_SIZE],
+            // MBC7 saves to an EEPROM instead, which starts out erased
+            Mapper::Mbc7 => vec![0xFF; MBC7_EEPROM_SIZE],
+            _ => vec![0; header.ram_size],
+        };
+        let last_saved = clock.now();
+
+        Ok(Cartridge {
+            header,
+            rom,
+            ram,
+            controller,
+            clock,
+            save_path: None,
+            dirty: false,
+            last_saved,
+        })
+    }
+
+    pub fn header(&self) -> &CartridgeHeader {
+        &self.header
+    }
+
+    pub fn rumble(&self) -> bool {
+        self.controller.rumble()
+    }
+
+    pub fn infrared_led(&self) -> bool {
+        self.controller.infrared_led()
+    }
+
+    pub fn set_infrared_signal(&mut self, signal: bool) {
+        self.controller.set_infrared_signal(signal);
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.controller.set_tilt(x, y);
+    }
+
+    // Where the Game Boy Camera gets its pictures from
+    pub fn set_image_source(&mut self, source: Box<dyn ImageSource>) {
+        self.controller.set_image_source(source);
+    }
+
+    // Contents of the cartridge RAM followed by the flash and clock state, for cartridges that
+    // have them
+    pub fn save_data(&mut self) -> Vec<u8> {
+        let mut data = self.ram.clone();
+        if let Some(flash) = self.controller.flash() {
+            data.extend_from_slice(flash);
+        }
+        if let Some(clock) = self.controller.save_clock() {
+            data.extend_from_slice(&clock);
+        }
+
+        data
+    }
+
+    // Restores data from save_data. Missing RAM or clock state is left as it is, so a save from
+    // an emulator that doesn't store the clock can still be used.
+    pub fn load_save_data(&mut self, data: &[u8]) {
+        let (ram, rest) = data.split_at(self.ram.len().min(data.len()));
+        self.ram[..ram.len()].copy_from_slice(ram);
+
+        let rest = match self.controller.flash().map(<[u8]>::len) {
+            Some(flash_length) => {
+                let (flash, rest) = rest.split_at(flash_length.min(rest.len()));
+                self.controller.load_flash(flash);
+                rest
+            }
+            None => rest,
+        };
+
+        if !rest.is_empty() {
+            self.controller.load_clock(rest);
+        }
+    }
+
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    // Writes the RAM to the .sav file. Does nothing for cartridges without a battery.
+    pub fn save(&mut self) -> io::Result<()> {
+        if let Some(path) = self.save_path.clone() {
+            fs::write(path, self.save_data())?;
+        }
+
+        self.dirty = false;
+        self.last_saved = self.clock.now();
+        Ok(())
+    }
+
+    // Games usually disable RAM once they've finished saving, so checking on every write to the
+    // cartridge catches the end of a save soon after it happens. It's also checked every frame, so
+    // a game that leaves the cartridge alone afterwards still gets saved.
+    fn autosave(&mut self) {
+        if !self.dirty || self.clock.now() < self.last_saved + AUTOSAVE_INTERVAL {
+            return;
+        }
+
+        if let Err(error) = self.save() {
+            eprintln!("Failed to save cartridge RAM: {}", error);
+        }
+    }
+}
+
+// Save on exit, so nothing written since the last autosave is lost
+impl Drop for Cartridge {
+    fn drop(&mut self) {
+        if !self.dirty {
+            return;
+        }
+
+        if let Err(error) = self.save() {
+            eprintln!("Failed to save cartridge RAM: {}", error);
+        }
+    }
+}
+
+impl MemoryMapped for Cartridge {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.controller.read_rom(&self.rom, address),
+            0xA000..=0xBFFF => self.controller.read_ram(&self.ram, address),
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x7FFF => {
+                self.controller.write_register(address, value);
+                if self.controller.take_flash_written() {
+                    self.dirty = true;
+                }
+            }
+            0xA000..=0xBFFF => {
+                if self.controller.write_ram(&mut self.ram, address, value) {
+                    self.dirty = true;
+                }
+            }
+            _ => return,
+        }
+
+        self.autosave();
+    }
+
+    fn end_frame(&mut self) {
+        self.autosave();
+    }
+}
+
+// Builds an otherwise empty ROM of the declared size with valid checksums
+#[cfg(test)]
+pub fn test_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; 0x8000 << rom_size];
+    rom[0x0104..0x0134].copy_from_slice(&NINTENDO_LOGO);
+    rom[0x0147] = cartridge_type;
+    rom[0x0148] = rom_size;
+    rom[0x0149] = ram_size;
+    fix_checksums(&mut rom);
+
+    rom
+}
+
+// Like test_rom, but with the number of each bank written to its first byte
+#[cfg(test)]
+pub fn banked_test_rom(cartridge_type: u8, rom_size: u8, ram_size: u8) -> Vec<u8> {
+    let mut rom = test_rom(cartridge_type, rom_size, ram_size);
+    for bank in 1..rom.len() / ROM_BANK_SIZE {
+        rom[bank * ROM_BANK_SIZE] = bank as u8;
+    }
+    fix_checksums(&mut rom);
+
+    rom
+}
+
+#[cfg(test)]
+pub fn fix_checksums(rom: &mut [u8]) {
+    rom[0x014D] = header::compute_header_checksum(rom);
+
+    let global_checksum = header::compute_global_checksum(rom);
+    rom[0x014E] = (global_checksum >> 8) as u8;
+    rom[0x014F] = global_checksum as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rtc::TestClock;
+
+    #[test]
+    fn loads_valid_rom() {
+        let mut rom = test_rom(0x08, 0x00, 0x02);
+        rom[0x0150] = 0x42;
+        fix_checksums(&mut rom);
+
+        let mut cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert_eq!(cartridge.header().rom_size, 0x8000);
+        assert_eq!(cartridge.read(0x0150), 0x42);
+
+        cartridge.write(0xA010, 0x99);
+        assert_eq!(cartridge.read(0xA010), 0x99);
+
+        // ROM can't be written to
+        cartridge.write(0x0150, 0x00);
+        assert_eq!(cartridge.read(0x0150), 0x42);
+    }
+
+    #[test]
+    fn rejects_truncated_rom() {
+        let mut rom = test_rom(0x01, 0x01, 0x00);
+        rom.truncate(0x8000);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::Truncated {
+                expected: 0x10000,
+                actual: 0x8000
+            })
+        ));
+    }
+
+    #[test]
+    fn loads_rom_with_bad_global_checksum() {
+        let mut rom = test_rom(0x00, 0x00, 0x00);
+        rom[0x4000] = 0x01;
+
+        let cartridge = Cartridge::from_bytes(rom).unwrap();
+        assert!(!cartridge.header().global_checksum_valid);
+        assert_eq!(cartridge.read(0x4000), 0x01);
+    }
+
+    #[test]
+    fn rejects_unsupported_mapper() {
+        let rom = test_rom(0xFD, 0x00, 0x00);
+
+        assert!(matches!(
+            Cartridge::from_bytes(rom),
+            Err(CartridgeError::UnsupportedMapper(Mapper::Tama5))
+        ));
+    }
+
+    // A ROM file in the temp directory, removed along with its save when the test finishes
+    struct TempRom(PathBuf);
+
+    impl TempRom {
+        fn new(name: &str, rom: &[u8]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("rustboy-{}-{}.gb", std::process::id(), name));
+            fs::write(&path, rom).unwrap();
+            TempRom(path)
+        }
+
+        fn save_path(&self) -> PathBuf {
+            self.0.with_extension("sav")
+        }
+    }
+
+    impl Drop for TempRom {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+            let _ = fs::remove_file(self.save_path());
+        }
+    }
+
+    #[test]
+    fn battery_ram_is_saved_on_drop_and_reloaded() {
+        // MBC1 with RAM and battery, 8 KiB of RAM
+        let rom = TempRom::new("battery", &test_rom(0x03, 0x00, 0x02));
+
+        let mut cartridge = Cartridge::load(&rom.0).unwrap();
+        assert_eq!(cartridge.save_path(), Some(rom.save_path().as_path()));
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA123, 0x42);
+        drop(cartridge);
+
+        // Raw dump of the RAM, as other emulators expect
+        let data = fs::read(rom.save_path()).unwrap();
+        assert_eq!(data.len(), 0x2000);
+        assert_eq!(data[0x0123], 0x42);
+
+        let mut cartridge = Cartridge::load(&rom.0).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        assert_eq!(cartridge.read(0xA123), 0x42);
+    }
+
+    #[test]
+    fn ram_without_battery_is_not_saved() {
+        // MBC1 with RAM, 8 KiB of RAM
+        let rom = TempRom::new("no-battery", &test_rom(0x02, 0x00, 0x02));
+
+        let mut cartridge = Cartridge::load(&rom.0).unwrap();
+        assert_eq!(cartridge.save_path(), None);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        drop(cartridge);
+
+        assert!(!rom.save_path().exists());
+    }
+
+    #[test]
+    fn autosaves_once_interval_has_passed() {
+        let rom = TempRom::new("autosave", &test_rom(0x03, 0x00, 0x02));
+        let clock = TestClock::default();
+
+        let mut cartridge = Cartridge::load_with_clock(&rom.0, Rc::new(clock.clone())).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        assert!(!rom.save_path().exists());
+
+        // Disabling RAM after the interval triggers the save
+        clock.advance(AUTOSAVE_INTERVAL);
+        cartridge.write(0x0000, 0x00);
+        assert_eq!(fs::read(rom.save_path()).unwrap()[0], 0x42);
+    }
+
+    #[test]
+    fn autosaves_from_the_frame_loop() {
+        let rom = TempRom::new("autosave-frame", &test_rom(0x03, 0x00, 0x02));
+        let clock = TestClock::default();
+
+        let mut cartridge = Cartridge::load_with_clock(&rom.0, Rc::new(clock.clone())).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        cartridge.end_frame();
+        assert!(!rom.save_path().exists());
+
+        // The game never touches the cartridge again
+        clock.advance(AUTOSAVE_INTERVAL);
+        cartridge.end_frame();
+        assert_eq!(fs::read(rom.save_path()).unwrap()[0], 0x42);
+    }
+
+    #[test]
+    fn writes_that_change_nothing_are_not_saved() {
+        // MBC1 with RAM and battery, 8 KiB of RAM
+        let rom = TempRom::new("unchanged", &test_rom(0x03, 0x00, 0x02));
+
+        // RAM is disabled, and then the value is already there
+        let mut cartridge = Cartridge::load(&rom.0).unwrap();
+        cartridge.write(0xA000, 0x42);
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x00);
+        drop(cartridge);
+        assert!(!rom.save_path().exists());
+
+        // MBC1 with battery but no RAM
+        let rom = TempRom::new("no-ram", &test_rom(0x03, 0x00, 0x00));
+        let mut cartridge = Cartridge::load(&rom.0).unwrap();
+        cartridge.write(0x0000, 0x0A);
+        cartridge.write(0xA000, 0x42);
+        drop(cartridge);
+        assert!(!rom.save_path().exists());
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        assert!(matches!(
+            Cartridge::load("does/not/exist.gb"),
+            Err(CartridgeError::Io(_))
+        ));
+    }
+}
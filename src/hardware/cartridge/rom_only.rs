@@ -0,0 +1,21 @@
+use super::{read_ram_bank, read_rom_bank, write_ram_bank, MemoryBankController};
+
+// 32 KiB of ROM mapped directly, optionally with up to 8 KiB of RAM that is always enabled
+pub struct RomOnly;
+
+impl MemoryBankController for RomOnly {
+    fn read_rom(&self, rom: &[u8], address: u16) -> u8 {
+        let bank = if address < 0x4000 { 0 } else { 1 };
+        read_rom_bank(rom, bank, address)
+    }
+
+    fn write_register(&mut self, _address: u16, _value: u8) {}
+
+    fn read_ram(&self, ram: &[u8], address: u16) -> u8 {
+        read_ram_bank(ram, 0, address)
+    }
+
+    fn write_ram(&mut self, ram: &mut [u8], address: u16, value: u8) -> bool {
+        write_ram_bank(ram, 0, address, value)
+    }
+}
@@ -0,0 +1,375 @@
+use std::{
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Size of the clock state appended to the end of a .sav file. This is the layout used by BGB and
+// VBA-M: the live registers and the latched registers as 5 little-endian u32s each, followed by
+// the Unix time the file was saved as a little-endian u64.
+pub const RTC_SAVE_SIZE: usize = 48;
+
+// Older versions of VBA store the timestamp as a u32 instead
+pub const RTC_SAVE_SIZE_32_BIT: usize = 44;
+
+const SECONDS: u8 = 0x08;
+const MINUTES: u8 = 0x09;
+const HOURS: u8 = 0x0A;
+const DAY_LOW: u8 = 0x0B;
+const DAY_HIGH: u8 = 0x0C;
+
+// Source of the current wall clock time, in seconds since the Unix epoch. The clock keeps running
+// while the emulator is closed, so it follows real time rather than emulated cycles. Shared
+// between the cartridge and its clock chip, so both see the same time.
+pub trait Clock {
+    fn now(&self) -> u64;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Registers {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    // 9-bit day counter
+    days: u16,
+    halted: bool,
+    // Set when the day counter overflows, and stays set until cleared by the game
+    day_carry: bool,
+}
+
+impl Registers {
+    fn read(&self, register: u8) -> u8 {
+        match register {
+            SECONDS => self.seconds,
+            MINUTES => self.minutes,
+            HOURS => self.hours,
+            DAY_LOW => self.days as u8,
+            DAY_HIGH => {
+                let mut value = (self.days >> 8) as u8;
+                if self.halted {
+                    value |= 0x40;
+                }
+                if self.day_carry {
+                    value |= 0x80;
+                }
+                value
+            }
+            _ => 0xFF,
+        }
+    }
+
+    // Unused bits aren't stored
+    fn write(&mut self, register: u8, value: u8) {
+        match register {
+            SECONDS => self.seconds = value & 0x3F,
+            MINUTES => self.minutes = value & 0x3F,
+            HOURS => self.hours = value & 0x1F,
+            DAY_LOW => self.days = (self.days & 0x100) | value as u16,
+            DAY_HIGH => {
+                self.days = (self.days & 0xFF) | ((value as u16 & 0x01) << 8);
+                self.halted = value & 0x40 != 0;
+                self.day_carry = value & 0x80 != 0;
+            }
+            _ => (),
+        }
+    }
+
+    fn advance(&mut self, seconds: u64) {
+        let minutes = count_up(&mut self.seconds, seconds, 60, 0x40);
+        let hours = count_up(&mut self.minutes, minutes, 60, 0x40);
+        let days = count_up(&mut self.hours, hours, 24, 0x20);
+
+        let days = self.days as u64 + days;
+        self.days = (days % 512) as u16;
+        if days >= 512 {
+            self.day_carry = true;
+        }
+    }
+
+    fn save(&self, data: &mut Vec<u8>) {
+        for register in SECONDS..=DAY_HIGH {
+            data.extend_from_slice(&(self.read(register) as u32).to_le_bytes());
+        }
+    }
+
+    fn load(data: &[u8]) -> Self {
+        let mut registers = Registers::default();
+        for (register, value) in (SECONDS..=DAY_HIGH).zip(data.chunks_exact(4)) {
+            registers.write(register, value[0]);
+        }
+        registers
+    }
+}
+
+// Adds to a counter that carries into the next unit when it passes `limit - 1`, returning the
+// number of carries. Games can write values past that, which count on until the register's bits
+// run out at `overflow` and wrap to 0 without carrying.
+fn count_up(value: &mut u8, mut amount: u64, limit: u64, overflow: u64) -> u64 {
+    let mut current = *value as u64;
+    if current >= limit {
+        let until_overflow = overflow - current;
+        if amount < until_overflow {
+            *value = (current + amount) as u8;
+            return 0;
+        }
+        amount -= until_overflow;
+        current = 0;
+    }
+
+    let total = current + amount;
+    *value = (total % limit) as u8;
+    total / limit
+}
+
+// The clock on MBC3 cartridges. Games read a latched copy of the registers, so the time can't
+// roll over halfway through being read.
+pub struct RealTimeClock {
+    clock: Rc<dyn Clock>,
+    registers: Registers,
+    latched: Registers,
+    // Time the registers were last brought up to date
+    updated_at: u64,
+}
+
+impl RealTimeClock {
+    pub fn new(clock: Rc<dyn Clock>) -> Self {
+        let updated_at = clock.now();
+
+        RealTimeClock {
+            clock,
+            registers: Registers::default(),
+            latched: Registers::default(),
+            updated_at,
+        }
+    }
+
+    // Catches the registers up with the time that has passed since they were last touched
+    fn update(&mut self) {
+        let now = self.clock.now();
+        if !self.registers.halted {
+            self.registers.advance(now.saturating_sub(self.updated_at));
+        }
+        self.updated_at = now;
+    }
+
+    pub fn latch(&mut self) {
+        self.update();
+        self.latched = self.registers;
+    }
+
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched.read(register)
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.update();
+        self.registers.write(register, value);
+    }
+
+    pub fn save(&mut self) -> Vec<u8> {
+        self.update();
+
+        let mut data = Vec::with_capacity(RTC_SAVE_SIZE);
+        self.registers.save(&mut data);
+        self.latched.save(&mut data);
+        data.extend_from_slice(&self.updated_at.to_le_bytes());
+
+        data
+    }
+
+    // Restores the clock from a save and then adds on the time that has passed since it was made
+    pub fn load(&mut self, data: &[u8]) {
+        let mut timestamp = [0; 8];
+        match data.len() {
+            RTC_SAVE_SIZE_32_BIT => timestamp[..4].copy_from_slice(&data[40..44]),
+            length if length >= RTC_SAVE_SIZE => timestamp.copy_from_slice(&data[40..48]),
+            _ => return,
+        }
+
+        self.registers = Registers::load(&data[0..20]);
+        self.latched = Registers::load(&data[20..40]);
+        self.updated_at = u64::from_le_bytes(timestamp);
+
+        self.update();
+    }
+}
+
+// A clock that only moves when told to. Clones share the same time, so a test can keep one and
+// hand the other to the cartridge.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub struct TestClock(std::rc::Rc<std::cell::Cell<u64>>);
+
+#[cfg(test)]
+impl TestClock {
+    pub fn advance(&self, seconds: u64) {
+        self.0.set(self.0.get() + seconds);
+    }
+}
+
+#[cfg(test)]
+impl Clock for TestClock {
+    fn now(&self) -> u64 {
+        self.0.get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_with_carries() {
+        let clock = TestClock::default();
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.write(SECONDS, 59);
+        rtc.write(MINUTES, 59);
+        rtc.write(HOURS, 23);
+        rtc.write(DAY_LOW, 0xFF);
+
+        clock.advance(1);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(MINUTES), 0);
+        assert_eq!(rtc.read(HOURS), 0);
+        assert_eq!(rtc.read(DAY_LOW), 0x00);
+        assert_eq!(rtc.read(DAY_HIGH), 0x01);
+    }
+
+    #[test]
+    fn out_of_range_values_wrap_without_carrying() {
+        let clock = TestClock::default();
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.write(SECONDS, 60);
+        rtc.write(MINUTES, 62);
+        rtc.write(HOURS, 30);
+
+        // Counts on to 63 before wrapping to 0, and only then does it count normally
+        clock.advance(3);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 63);
+        assert_eq!(rtc.read(MINUTES), 62);
+
+        clock.advance(1);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(MINUTES), 62);
+
+        clock.advance(60);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(MINUTES), 63);
+        assert_eq!(rtc.read(HOURS), 30);
+
+        clock.advance(60);
+        rtc.latch();
+        assert_eq!(rtc.read(MINUTES), 0);
+        assert_eq!(rtc.read(HOURS), 30);
+        assert_eq!(rtc.read(DAY_LOW), 0);
+
+        // Hours run out of bits at 31
+        clock.advance(2 * 60 * 60);
+        rtc.latch();
+        assert_eq!(rtc.read(HOURS), 0);
+        assert_eq!(rtc.read(DAY_LOW), 0);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_carry() {
+        let clock = TestClock::default();
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.write(DAY_LOW, 0xFF);
+        rtc.write(DAY_HIGH, 0x01);
+
+        clock.advance(24 * 60 * 60);
+        rtc.latch();
+        assert_eq!(rtc.read(DAY_LOW), 0x00);
+        assert_eq!(rtc.read(DAY_HIGH), 0x80);
+
+        // The carry stays set until it's cleared
+        clock.advance(24 * 60 * 60);
+        rtc.latch();
+        assert_eq!(rtc.read(DAY_HIGH), 0x80);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let clock = TestClock::default();
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.write(DAY_HIGH, 0x40);
+
+        clock.advance(100);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 0);
+        assert_eq!(rtc.read(DAY_HIGH), 0x40);
+
+        rtc.write(DAY_HIGH, 0x00);
+        clock.advance(5);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 5);
+    }
+
+    #[test]
+    fn reads_come_from_latched_registers() {
+        let clock = TestClock::default();
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+
+        clock.advance(10);
+        assert_eq!(rtc.read(SECONDS), 0);
+
+        rtc.latch();
+        clock.advance(10);
+        assert_eq!(rtc.read(SECONDS), 10);
+    }
+
+    #[test]
+    fn save_round_trip_includes_time_away() {
+        let clock = TestClock::default();
+        clock.advance(1_000_000);
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.write(MINUTES, 30);
+        rtc.latch();
+
+        let data = rtc.save();
+        assert_eq!(data.len(), RTC_SAVE_SIZE);
+        assert_eq!(&data[4..8], &[30, 0, 0, 0]);
+        assert_eq!(&data[40..48], &1_000_000u64.to_le_bytes());
+
+        clock.advance(2 * 60 * 60);
+        let mut restored = RealTimeClock::new(Rc::new(clock.clone()));
+        restored.load(&data);
+
+        // The latched registers come back as they were saved
+        assert_eq!(restored.read(MINUTES), 30);
+        assert_eq!(restored.read(HOURS), 0);
+
+        restored.latch();
+        assert_eq!(restored.read(MINUTES), 30);
+        assert_eq!(restored.read(HOURS), 2);
+    }
+
+    #[test]
+    fn loads_saves_with_32_bit_timestamp() {
+        let clock = TestClock::default();
+        clock.advance(1_000_060);
+        let mut data = vec![0; RTC_SAVE_SIZE_32_BIT];
+        data[0] = 30;
+        data[40..44].copy_from_slice(&1_000_000u32.to_le_bytes());
+
+        let mut rtc = RealTimeClock::new(Rc::new(clock.clone()));
+        rtc.load(&data);
+        rtc.latch();
+        assert_eq!(rtc.read(SECONDS), 30);
+        assert_eq!(rtc.read(MINUTES), 1);
+    }
+}
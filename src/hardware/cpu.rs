@@ -1,45 +1,148 @@
 use crate::utils::{bytes_to_word, word_to_bytes};
 
 use super::{
+    alu,
+    bus::{Bus, MemoryMapped},
     instructions::{
-        Instruction, JumpCondition, LoadType, PrefixedInstruction, RegisterSideEffect, XORTarget,
+        ArithmeticTarget, IncDecTarget, Instruction, JumpCondition, LoadType, PrefixedInstruction,
+        PrefixedTarget, RegisterSideEffect,
     },
+    interrupts::Interrupt,
     registers::{Flag, Register, RegisterFile},
+    timer::DIV,
 };
 
+// The master clock of the DMG in T-cycles per second. One M-cycle is four T-cycles.
+pub const CLOCK_SPEED: u32 = 4_194_304;
+// 154 scanlines of 456 T-cycles each
+pub const CYCLES_PER_FRAME: u32 = 70_224;
+
+#[allow(clippy::upper_case_acronyms)]
 pub struct CPU {
-    program_counter: usize,
+    program_counter: u16,
     registers: RegisterFile,
-    memory: Box<[u8; 65536]>,
+    bus: Bus,
+    interrupt_master_enable: bool,
+    // EI only takes effect after the instruction following it has run
+    interrupt_enable_delay: u8,
+    halted: bool,
+    halt_bug: bool,
+    stopped: bool,
+    locked: bool,
+    total_cycles: u64,
+    // T-cycles the rest of the hardware has been moved on by during the current step
+    step_cycles: u32,
+    frame_overshoot: u32,
 }
 
 impl CPU {
-    pub fn new() -> Self {
+    pub fn new(bus: Bus) -> Self {
         let registers = RegisterFile::new();
-        let memory = Box::new([0; 65536]);
 
         CPU {
             program_counter: 0,
             registers,
-            memory,
+            bus,
+            interrupt_master_enable: false,
+            interrupt_enable_delay: 0,
+            halted: false,
+            halt_bug: false,
+            stopped: false,
+            locked: false,
+            total_cycles: 0,
+            step_cycles: 0,
+            frame_overshoot: 0,
         }
     }
 
+    // Puts the CPU and I/O registers in the state the model's boot ROM would leave them in, for
+    // when no boot ROM is run. Without this, execution starts at 0x0000 where a boot ROM is
+    // expected.
+    pub fn skip_boot_rom(&mut self) {
+        self.bus.skip_boot_rom();
+
+        let header_checksum = self.read_memory(0x014D);
+        let [af, bc, de, hl] = self
+            .bus
+            .model()
+            .post_boot_registers(header_checksum, self.bus.cgb_mode());
+
+        self.registers.write_register(Register::AF, af);
+        self.registers.write_register(Register::BC, bc);
+        self.registers.write_register(Register::DE, de);
+        self.registers.write_register(Register::HL, hl);
+        self.registers
+            .write_register(Register::StackPointer, 0xFFFE);
+        self.program_counter = 0x0100;
+    }
+
+    fn get_immediate_byte(&mut self) -> u8 {
+        let byte = self.read_cycle(self.program_counter);
+        self.program_counter = self.program_counter.wrapping_add(1);
+
+        byte
+    }
+
     fn get_immediate_word(&mut self) -> u16 {
-        self.program_counter += 1;
-        let lower_byte = self.read_memory(self.program_counter);
-        self.program_counter += 1;
-        let higher_byte = self.read_memory(self.program_counter);
+        let lower_byte = self.get_immediate_byte();
+        let higher_byte = self.get_immediate_byte();
 
         bytes_to_word(higher_byte, lower_byte)
     }
 
-    fn read_memory(&self, address: usize) -> u8 {
-        self.memory[address]
+    fn read_memory(&self, address: u16) -> u8 {
+        self.bus.read(address)
+    }
+
+    fn write_memory(&mut self, address: u16, value: u8) {
+        self.bus.write(address, value);
+    }
+
+    // Each memory access by an instruction takes an M-cycle, and the rest of the hardware is
+    // moved on before the access happens. That way an access sees the PPU and DMA as they are at
+    // that point in the instruction rather than at its start.
+    fn read_cycle(&mut self, address: u16) -> u8 {
+        self.tick_cycle();
+        self.read_memory(address)
+    }
+
+    fn write_cycle(&mut self, address: u16, value: u8) {
+        self.tick_cycle();
+        self.write_memory(address, value);
     }
 
-    fn write_memory(&mut self, address: usize, value: u8) {
-        self.memory[address] = value;
+    fn tick_cycle(&mut self) {
+        self.bus.tick(4);
+        self.step_cycles += 4;
+    }
+
+    fn push_word(&mut self, value: u16) {
+        let (high, low) = word_to_bytes(value);
+        let sp = self.registers.read_register(Register::StackPointer);
+
+        self.write_cycle(sp.wrapping_sub(1), high);
+        self.write_cycle(sp.wrapping_sub(2), low);
+        self.registers
+            .write_register(Register::StackPointer, sp.wrapping_sub(2));
+    }
+
+    fn pop_word(&mut self) -> u16 {
+        let sp = self.registers.read_register(Register::StackPointer);
+        let low = self.read_cycle(sp);
+        let high = self.read_cycle(sp.wrapping_add(1));
+        self.registers
+            .write_register(Register::StackPointer, sp.wrapping_add(2));
+
+        bytes_to_word(high, low)
+    }
+
+    fn apply_side_effect(&mut self, reg: Register, side_effect: RegisterSideEffect) {
+        let value = self.registers.read_register(reg);
+
+        match side_effect {
+            RegisterSideEffect::Inc => self.registers.write_register(reg, value.wrapping_add(1)),
+            RegisterSideEffect::Dec => self.registers.write_register(reg, value.wrapping_sub(1)),
+        }
     }
 
     fn execute_load_instruction(&mut self, load_type: LoadType) {
@@ -50,8 +153,7 @@ impl CPU {
             }
 
             LoadType::ImmediateByte(reg) => {
-                self.program_counter += 1;
-                let byte = self.read_memory(self.program_counter);
+                let byte = self.get_immediate_byte();
 
                 self.registers.write_register(reg, byte as u16);
             }
@@ -62,87 +164,226 @@ impl CPU {
             }
 
             LoadType::ImmediateByteToMemory(reg) => {
-                self.program_counter += 1;
-                let byte = self.read_memory(self.program_counter);
+                let byte = self.get_immediate_byte();
                 let address = self.registers.read_register(reg);
 
-                self.write_memory(address as usize, byte);
+                self.write_cycle(address, byte);
             }
             LoadType::StackPointerToMemory => {
-                let address = self.get_immediate_word() as usize;
+                let address = self.get_immediate_word();
                 let sp = self.registers.read_register(Register::StackPointer);
 
                 let (high, low) = word_to_bytes(sp);
 
-                self.write_memory(address as usize, low);
-                self.write_memory((address + 1) as usize, high);
+                self.write_cycle(address, low);
+                self.write_cycle(address.wrapping_add(1), high);
             }
 
             LoadType::FromMemory(destination, address_reg) => {
-                let address = self.registers.read_register(address_reg) as usize;
-                let value = self.read_memory(address) as u16;
+                let address = self.registers.read_register(address_reg);
+                let value = self.read_cycle(address) as u16;
 
                 self.registers.write_register(destination, value);
             }
 
             LoadType::FromMemoryWithSideEffect(reg, side_effect) => {
-                let address = self.registers.read_register(reg.clone()) as usize;
-                let value = self.read_memory(address) as u16;
+                let address = self.registers.read_register(reg);
+                let value = self.read_cycle(address) as u16;
 
                 self.registers.write_register(Register::A, value);
-
-                match side_effect {
-                    RegisterSideEffect::Inc => {
-                        self.registers.write_register(reg, address as u16 + 1)
-                    }
-                    RegisterSideEffect::Dec => {
-                        self.registers.write_register(reg, address as u16 - 1)
-                    }
-                }
+                self.apply_side_effect(reg, side_effect);
             }
 
             LoadType::ToMemory(address_reg, source) => {
-                let address = self.registers.read_register(address_reg) as usize;
+                let address = self.registers.read_register(address_reg);
                 let value = self.registers.read_register(source);
 
-                self.write_memory(address, value as u8);
+                self.write_cycle(address, value as u8);
             }
 
             LoadType::ToMemoryWithSideEffect(reg, side_effect) => {
-                let address = self.registers.read_register(reg.clone()) as usize;
+                let address = self.registers.read_register(reg);
                 let value = self.registers.read_register(Register::A);
 
-                self.write_memory(address, value as u8);
+                self.write_cycle(address, value as u8);
+                self.apply_side_effect(reg, side_effect);
+            }
 
-                match side_effect {
-                    RegisterSideEffect::Inc => {
-                        self.registers.write_register(reg, address as u16 + 1)
-                    }
-                    RegisterSideEffect::Dec => {
-                        self.registers.write_register(reg, address as u16 - 1)
-                    }
-                }
+            LoadType::ToImmediateAddress => {
+                let address = self.get_immediate_word();
+                let value = self.registers.read_register(Register::A);
+
+                self.write_cycle(address, value as u8);
+            }
+
+            LoadType::FromImmediateAddress => {
+                let address = self.get_immediate_word();
+                let value = self.read_cycle(address) as u16;
+
+                self.registers.write_register(Register::A, value);
+            }
+
+            LoadType::ToHighMemoryImmediate => {
+                let address = 0xFF00 | self.get_immediate_byte() as u16;
+                let value = self.registers.read_register(Register::A);
+
+                self.write_cycle(address, value as u8);
+            }
+
+            LoadType::FromHighMemoryImmediate => {
+                let address = 0xFF00 | self.get_immediate_byte() as u16;
+                let value = self.read_cycle(address) as u16;
+
+                self.registers.write_register(Register::A, value);
+            }
+
+            LoadType::ToHighMemory(offset_reg) => {
+                let address = 0xFF00 | self.registers.read_register(offset_reg);
+                let value = self.registers.read_register(Register::A);
+
+                self.write_cycle(address, value as u8);
+            }
+
+            LoadType::FromHighMemory(offset_reg) => {
+                let address = 0xFF00 | self.registers.read_register(offset_reg);
+                let value = self.read_cycle(address) as u16;
+
+                self.registers.write_register(Register::A, value);
+            }
+
+            LoadType::HLToStackPointer => {
+                let value = self.registers.read_register(Register::HL);
+                self.registers.write_register(Register::StackPointer, value);
+            }
+
+            LoadType::StackPointerOffsetToHL => {
+                let value = self.stack_pointer_with_offset();
+                self.registers.write_register(Register::HL, value);
             }
         }
     }
 
-    fn execute_xor_instruction(&mut self, target: XORTarget) {
+    fn read_arithmetic_target(&mut self, target: ArithmeticTarget) -> u8 {
         match target {
-            XORTarget::Register(reg) => {
-                let a_reg = self.registers.read_register(Register::A);
-                let val = self.registers.read_register(reg);
-                let xor_result = a_reg ^ val;
+            ArithmeticTarget::Register(reg) => self.registers.read_register(reg) as u8,
+            ArithmeticTarget::Memory(address_reg) => {
+                let address = self.registers.read_register(address_reg);
+                self.read_cycle(address)
+            }
+            ArithmeticTarget::Immediate => self.get_immediate_byte(),
+        }
+    }
 
-                self.registers.write_register(Register::A, xor_result);
-                // Clear flags register
-                self.registers.write_register(Register::F, 0);
-                self.registers.set_flag(Flag::Z, xor_result == 0);
+    fn execute_alu_instruction(
+        &mut self,
+        target: ArithmeticTarget,
+        operation: fn(&mut RegisterFile, u8),
+    ) {
+        let value = self.read_arithmetic_target(target);
+        operation(&mut self.registers, value);
+    }
+
+    fn execute_inc_dec_instruction(&mut self, target: IncDecTarget, increment: bool) {
+        let operation = if increment {
+            alu::increment
+        } else {
+            alu::decrement
+        };
+
+        match target {
+            IncDecTarget::Register(reg) => {
+                let value = self.registers.read_register(reg) as u8;
+                let result = operation(&mut self.registers, value);
+                self.registers.write_register(reg, result as u16);
+            }
+            IncDecTarget::Memory(address_reg) => {
+                let address = self.registers.read_register(address_reg);
+                let value = self.read_cycle(address);
+                let result = operation(&mut self.registers, value);
+                self.write_cycle(address, result);
+            }
+            IncDecTarget::Word(reg) => {
+                // 16-bit increments and decrements leave the flags untouched
+                let side_effect = if increment {
+                    RegisterSideEffect::Inc
+                } else {
+                    RegisterSideEffect::Dec
+                };
+                self.apply_side_effect(reg, side_effect);
             }
         }
     }
 
-    fn execute_bit_instruction(&mut self, index: u8, reg: Register) {
+    fn execute_add_word_instruction(&mut self, reg: Register) {
         let value = self.registers.read_register(reg);
+        alu::add_word(&mut self.registers, value);
+    }
+
+    // Shared by ADD SP, s8 and LD HL, SP+s8
+    fn stack_pointer_with_offset(&mut self) -> u16 {
+        let offset = self.get_immediate_byte();
+        let sp = self.registers.read_register(Register::StackPointer);
+
+        alu::add_signed_byte(&mut self.registers, sp, offset)
+    }
+
+    fn execute_rotate_a_instruction(&mut self, left: bool, through_carry: bool) {
+        let value = self.registers.read_register(Register::A) as u8;
+        let carry_in = self.registers.get_flag(Flag::C) as u8;
+
+        let (result, carry_out) = if left {
+            let fill = if through_carry { carry_in } else { value >> 7 };
+            ((value << 1) | fill, value >> 7 != 0)
+        } else {
+            let fill = if through_carry { carry_in } else { value & 1 };
+            ((value >> 1) | (fill << 7), value & 1 != 0)
+        };
+
+        self.registers.write_register(Register::A, result as u16);
+        // The accumulator rotates always clear Z, unlike their prefixed counterparts
+        self.registers.write_register(Register::F, 0);
+        self.registers.set_flag(Flag::C, carry_out);
+    }
+
+    fn read_prefixed_target(&mut self, target: PrefixedTarget) -> u8 {
+        match target {
+            PrefixedTarget::Register(reg) => self.registers.read_register(reg) as u8,
+            PrefixedTarget::Memory(address_reg) => {
+                let address = self.registers.read_register(address_reg);
+                self.read_cycle(address)
+            }
+        }
+    }
+
+    fn write_prefixed_target(&mut self, target: PrefixedTarget, value: u8) {
+        match target {
+            PrefixedTarget::Register(reg) => self.registers.write_register(reg, value as u16),
+            PrefixedTarget::Memory(address_reg) => {
+                let address = self.registers.read_register(address_reg);
+                self.write_cycle(address, value);
+            }
+        }
+    }
+
+    // Rotates, shifts and SWAP all share the same flag behaviour, so the operation itself is
+    // passed in as a function from (value, carry in) to (result, carry out)
+    fn execute_shift_instruction(
+        &mut self,
+        target: PrefixedTarget,
+        operation: fn(u8, bool) -> (u8, bool),
+    ) {
+        let value = self.read_prefixed_target(target);
+        let carry = self.registers.get_flag(Flag::C);
+        let (result, carry_out) = operation(value, carry);
+
+        self.write_prefixed_target(target, result);
+        self.registers.write_register(Register::F, 0);
+        self.registers.set_flag(Flag::Z, result == 0);
+        self.registers.set_flag(Flag::C, carry_out);
+    }
+
+    fn execute_bit_instruction(&mut self, index: u8, target: PrefixedTarget) {
+        let value = self.read_prefixed_target(target);
         let bit = (value >> index) & 1 != 0;
 
         self.registers.set_flag(Flag::Z, !bit);
@@ -150,51 +391,1047 @@ impl CPU {
         self.registers.set_flag(Flag::H, true);
     }
 
-    fn execute_jump_relative(&mut self, condition: JumpCondition) {
-        self.program_counter += 1;
-        let steps = self.read_memory(self.program_counter);
+    fn execute_set_bit_instruction(&mut self, index: u8, target: PrefixedTarget, bit: bool) {
+        let value = self.read_prefixed_target(target);
+        let result = if bit {
+            value | (1 << index)
+        } else {
+            value & !(1 << index)
+        };
 
+        self.write_prefixed_target(target, result);
+    }
+
+    fn condition_met(&self, condition: JumpCondition) -> bool {
         match condition {
-            JumpCondition::NegatedFlag(flag) => {
-                if !self.registers.get_flag(flag) {
-                    // We always increment the counter so ensure we go one back to land on the correct address
-                    self.program_counter += (steps - 1) as usize;
-                }
+            JumpCondition::Always => true,
+            JumpCondition::NotZero => !self.registers.get_flag(Flag::Z),
+            JumpCondition::Zero => self.registers.get_flag(Flag::Z),
+            JumpCondition::NotCarry => !self.registers.get_flag(Flag::C),
+            JumpCondition::Carry => self.registers.get_flag(Flag::C),
+        }
+    }
+
+    // Branches return the number of M-cycles they took, as a taken branch costs an extra cycle
+    // to load the new program counter
+    fn execute_jump(&mut self, condition: JumpCondition) -> u8 {
+        let address = self.get_immediate_word();
+
+        if self.condition_met(condition) {
+            self.program_counter = address;
+            4
+        } else {
+            3
+        }
+    }
+
+    fn execute_jump_to_hl(&mut self) -> u8 {
+        self.program_counter = self.registers.read_register(Register::HL);
+        1
+    }
+
+    fn execute_jump_relative(&mut self, condition: JumpCondition) -> u8 {
+        // The offset is a signed byte relative to the address of the following instruction
+        let steps = self.get_immediate_byte() as i8;
+
+        if self.condition_met(condition) {
+            self.program_counter = self.program_counter.wrapping_add(steps as u16);
+            3
+        } else {
+            2
+        }
+    }
+
+    fn execute_call(&mut self, condition: JumpCondition) -> u8 {
+        let address = self.get_immediate_word();
+
+        if self.condition_met(condition) {
+            self.push_word(self.program_counter);
+            self.program_counter = address;
+            6
+        } else {
+            3
+        }
+    }
+
+    fn execute_return(&mut self, condition: JumpCondition) -> u8 {
+        // An unconditional RET doesn't spend a cycle checking the flags
+        let unconditional = matches!(condition, JumpCondition::Always);
+
+        if self.condition_met(condition) {
+            self.program_counter = self.pop_word();
+            if unconditional {
+                4
+            } else {
+                5
             }
+        } else {
+            2
         }
     }
 
-    fn execute_prefixed_instruction(&mut self) {
-        // All prefixed instructions are 2 bytes long
-        self.program_counter += 1;
+    fn execute_restart(&mut self, vector: u8) {
+        self.push_word(self.program_counter);
+        self.program_counter = vector as u16;
+    }
 
-        let opcode = self.read_memory(self.program_counter);
+    // Returns the number of M-cycles the prefixed instruction took
+    fn execute_prefixed_instruction(&mut self) -> u8 {
+        let opcode = self.get_immediate_byte();
         let instruction = PrefixedInstruction::decode(opcode);
+        let cycles = instruction.cycles();
 
         match instruction {
-            PrefixedInstruction::Bit(index, reg) => self.execute_bit_instruction(index, reg),
-            PrefixedInstruction::NoOp => (),
+            PrefixedInstruction::RotateLeftCircular(target) => self
+                .execute_shift_instruction(target, |value, _| {
+                    (value.rotate_left(1), value >> 7 != 0)
+                }),
+            PrefixedInstruction::RotateRightCircular(target) => self
+                .execute_shift_instruction(target, |value, _| {
+                    (value.rotate_right(1), value & 1 != 0)
+                }),
+            PrefixedInstruction::RotateLeft(target) => self
+                .execute_shift_instruction(target, |value, carry| {
+                    ((value << 1) | carry as u8, value >> 7 != 0)
+                }),
+            PrefixedInstruction::RotateRight(target) => self
+                .execute_shift_instruction(target, |value, carry| {
+                    ((value >> 1) | ((carry as u8) << 7), value & 1 != 0)
+                }),
+            PrefixedInstruction::ShiftLeftArithmetic(target) => {
+                self.execute_shift_instruction(target, |value, _| (value << 1, value >> 7 != 0))
+            }
+            PrefixedInstruction::ShiftRightArithmetic(target) => {
+                // Bit 7 is preserved, keeping the sign of the value
+                self.execute_shift_instruction(target, |value, _| {
+                    (((value as i8) >> 1) as u8, value & 1 != 0)
+                })
+            }
+            PrefixedInstruction::Swap(target) => {
+                self.execute_shift_instruction(target, |value, _| (value.rotate_left(4), false))
+            }
+            PrefixedInstruction::ShiftRightLogical(target) => {
+                self.execute_shift_instruction(target, |value, _| (value >> 1, value & 1 != 0))
+            }
+            PrefixedInstruction::Bit(index, target) => self.execute_bit_instruction(index, target),
+            PrefixedInstruction::Reset(index, target) => {
+                self.execute_set_bit_instruction(index, target, false)
+            }
+            PrefixedInstruction::Set(index, target) => {
+                self.execute_set_bit_instruction(index, target, true)
+            }
         };
+
+        cycles
+    }
+
+    fn execute_halt(&mut self) {
+        if !self.interrupt_master_enable && self.bus.pending_interrupt().is_some() {
+            // The HALT bug: with interrupts disabled and one already pending, HALT exits straight
+            // away and the CPU fails to increment the program counter after the next fetch
+            self.halt_bug = true;
+        } else {
+            self.halted = true;
+        }
+    }
+
+    // Pushes the program counter and jumps to the interrupt vector, taking 5 M-cycles
+    fn dispatch_interrupt(&mut self, interrupt: Interrupt) -> u8 {
+        self.interrupt_master_enable = false;
+        self.bus.acknowledge_interrupt(interrupt);
+
+        self.push_word(self.program_counter);
+        self.program_counter = interrupt.vector();
+
+        5
     }
 
-    fn execute(&mut self, instruction: Instruction) {
+    // Returns the number of M-cycles the instruction took
+    fn execute(&mut self, instruction: Instruction) -> u8 {
+        let cycles = instruction.cycles();
+
         match instruction {
-            Instruction::XOR(target) => self.execute_xor_instruction(target),
+            // Branches and prefixed instructions work out their own timing
+            Instruction::Jump(condition) => return self.execute_jump(condition),
+            Instruction::JumpToHL => return self.execute_jump_to_hl(),
+            Instruction::JumpRelative(condition) => return self.execute_jump_relative(condition),
+            Instruction::Call(condition) => return self.execute_call(condition),
+            Instruction::Return(condition) => return self.execute_return(condition),
+            Instruction::Prefixed => return self.execute_prefixed_instruction(),
             Instruction::Load(load_type) => self.execute_load_instruction(load_type),
-            Instruction::Prefixed => self.execute_prefixed_instruction(),
-            Instruction::JumpRelative(condition) => self.execute_jump_relative(condition),
+            Instruction::Add(target) => self.execute_alu_instruction(target, alu::add),
+            Instruction::AddWithCarry(target) => {
+                self.execute_alu_instruction(target, alu::add_with_carry)
+            }
+            Instruction::Subtract(target) => self.execute_alu_instruction(target, alu::subtract),
+            Instruction::SubtractWithCarry(target) => {
+                self.execute_alu_instruction(target, alu::subtract_with_carry)
+            }
+            Instruction::And(target) => self.execute_alu_instruction(target, alu::and),
+            Instruction::Xor(target) => self.execute_alu_instruction(target, alu::xor),
+            Instruction::Or(target) => self.execute_alu_instruction(target, alu::or),
+            Instruction::Compare(target) => self.execute_alu_instruction(target, alu::compare),
+            Instruction::Increment(target) => self.execute_inc_dec_instruction(target, true),
+            Instruction::Decrement(target) => self.execute_inc_dec_instruction(target, false),
+            Instruction::AddWord(reg) => self.execute_add_word_instruction(reg),
+            Instruction::AddStackPointer => {
+                let value = self.stack_pointer_with_offset();
+                self.registers.write_register(Register::StackPointer, value);
+            }
+            Instruction::RotateLeftCircularA => self.execute_rotate_a_instruction(true, false),
+            Instruction::RotateRightCircularA => self.execute_rotate_a_instruction(false, false),
+            Instruction::RotateLeftA => self.execute_rotate_a_instruction(true, true),
+            Instruction::RotateRightA => self.execute_rotate_a_instruction(false, true),
+            Instruction::DecimalAdjust => alu::decimal_adjust(&mut self.registers),
+            Instruction::Complement => alu::complement(&mut self.registers),
+            Instruction::SetCarryFlag => alu::set_carry_flag(&mut self.registers),
+            Instruction::ComplementCarryFlag => alu::complement_carry_flag(&mut self.registers),
+            Instruction::Push(reg) => {
+                let value = self.registers.read_register(reg);
+                self.push_word(value);
+            }
+            Instruction::Pop(reg) => {
+                let value = self.pop_word();
+                self.registers.write_register(reg, value);
+            }
+            Instruction::ReturnFromInterrupt => {
+                self.program_counter = self.pop_word();
+                self.interrupt_master_enable = true;
+            }
+            Instruction::Restart(vector) => self.execute_restart(vector),
+            Instruction::DisableInterrupts => {
+                self.interrupt_master_enable = false;
+                self.interrupt_enable_delay = 0;
+            }
+            Instruction::EnableInterrupts => {
+                if !self.interrupt_master_enable {
+                    self.interrupt_enable_delay = 2;
+                }
+            }
+            Instruction::Halt => self.execute_halt(),
+            Instruction::Stop => {
+                // STOP is followed by a padding byte which is skipped, and resets the divider
+                self.get_immediate_byte();
+                self.write_memory(DIV, 0);
+                // With a speed switch armed in KEY1, STOP switches speed and carries on instead
+                if !self.bus.switch_speed() {
+                    self.stopped = true;
+                }
+            }
             Instruction::NoOp => (),
+            Instruction::Illegal => self.locked = true,
+        };
+
+        cycles
+    }
+
+    // Executes a single instruction or interrupt dispatch, returning the number of T-cycles it
+    // took. In double speed these are T-cycles of the normal speed clock, so a frame is always
+    // CYCLES_PER_FRAME long.
+    pub fn step(&mut self) -> u32 {
+        self.step_cycles = 0;
+
+        // DMA and speed switches halt the CPU between instructions while the rest of the hardware
+        // carries on
+        let stall_cycles = self.bus.take_stall_cycles();
+        if stall_cycles > 0 {
+            self.bus.tick(stall_cycles);
+            return self.elapsed(stall_cycles);
+        }
+
+        let pending_interrupt = self.bus.pending_interrupt();
+
+        // A pending interrupt wakes the CPU from HALT whether or not IME is set
+        if self.halted && pending_interrupt.is_some() {
+            self.halted = false;
+        }
+
+        let cycles = if self.halted || self.stopped || self.locked {
+            // Nothing executes, but time still passes
+            1
+        } else if let (true, Some(interrupt)) = (self.interrupt_master_enable, pending_interrupt) {
+            self.dispatch_interrupt(interrupt)
+        } else {
+            let opcode = self.get_immediate_byte();
+            if self.halt_bug {
+                self.halt_bug = false;
+                self.program_counter = self.program_counter.wrapping_sub(1);
+            }
+
+            let instruction = Instruction::decode(opcode);
+            let cycles = self.execute(instruction);
+
+            if self.interrupt_enable_delay > 0 {
+                self.interrupt_enable_delay -= 1;
+                self.interrupt_master_enable = self.interrupt_enable_delay == 0;
+            }
+
+            cycles
         };
 
-        // Increment program counter
-        self.program_counter += 1;
+        let cycles = cycles as u32 * 4;
+        // Cycles without a memory access still need to pass
+        self.bus.tick(cycles.saturating_sub(self.step_cycles));
+
+        self.elapsed(cycles)
+    }
+
+    // Adds CPU T-cycles to the total, converted to T-cycles of the normal speed clock
+    fn elapsed(&mut self, cycles: u32) -> u32 {
+        let cycles = if self.bus.double_speed() {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.total_cycles += cycles as u64;
+
+        cycles
+    }
+
+    // Lets the rest of the hardware raise an interrupt in IF
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.bus.request_interrupt(interrupt);
+    }
+
+    // Runs whole instructions until at least the given number of T-cycles have passed, returning
+    // the number of T-cycles actually run, which may overshoot by part of an instruction
+    pub fn run_cycles(&mut self, cycles: u32) -> u32 {
+        let mut elapsed = 0;
+
+        while elapsed < cycles {
+            elapsed += self.step();
+        }
+
+        elapsed
+    }
+
+    // Runs for the length of one frame. Any overshoot from the previous frame is taken off this
+    // one, so frames stay in step with the clock over time.
+    pub fn run_frame(&mut self) -> u32 {
+        let target = CYCLES_PER_FRAME - self.frame_overshoot;
+        let elapsed = self.run_cycles(target);
+        self.frame_overshoot = elapsed - target;
+        self.bus.end_frame();
+
+        elapsed
+    }
+
+    pub fn total_cycles(&self) -> u64 {
+        self.total_cycles
+    }
+
+    // Test ROMs signal that they've finished by running LD B, B and leave their result in the
+    // registers. Once the next instruction is that breakpoint, returns B, C, D, E, H and L.
+    #[cfg(test)]
+    pub fn breakpoint(&self) -> Option<[u8; 6]> {
+        if self.halted || self.read_memory(self.program_counter) != 0x40 {
+            return None;
+        }
+
+        let registers = [
+            Register::B,
+            Register::C,
+            Register::D,
+            Register::E,
+            Register::H,
+            Register::L,
+        ];
+        Some(registers.map(|register| self.registers.read_register(register) as u8))
+    }
+
+    // The last frame drawn, as 160x144 RGB555 colours
+    pub fn framebuffer(&self) -> &[u16] {
+        self.bus.ppu().framebuffer()
+    }
+
+    // Stereo sound produced since this was last called
+    pub fn take_samples(&mut self) -> Vec<(f32, f32)> {
+        self.bus.apu_mut().take_samples()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::{
+        bus::Ram,
+        interrupts::{INTERRUPT_ENABLE, INTERRUPT_FLAG},
+        model::Model,
+    };
+
+    // Uses writable memory in place of a cartridge so programs can be placed at 0x0000
+    fn test_cpu() -> CPU {
+        test_cpu_with_model(Model::Dmg)
+    }
+
+    fn test_cpu_with_model(model: Model) -> CPU {
+        let mut bus = Bus::new(model);
+        bus.insert_cartridge(Box::new(Ram::new(0x0000, 0xC000)));
+
+        CPU::new(bus)
+    }
+
+    fn cpu_with_program(program: &[u8]) -> CPU {
+        let mut cpu = test_cpu();
+        for (address, &byte) in program.iter().enumerate() {
+            cpu.write_memory(address as u16, byte);
+        }
+
+        cpu
+    }
+
+    #[test]
+    fn every_legal_opcode_decodes() {
+        let illegal = [
+            0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+        ];
+
+        for opcode in 0..=0xFF {
+            let is_illegal = matches!(Instruction::decode(opcode), Instruction::Illegal);
+            assert_eq!(
+                is_illegal,
+                illegal.contains(&opcode),
+                "opcode {:#04X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn add_sets_half_carry_and_carry() {
+        // LD A, 0x8F; ADD A, 0x81
+        let mut cpu = cpu_with_program(&[0x3E, 0x8F, 0xC6, 0x81]);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.registers.read_register(Register::A), 0x10);
+        assert!(!cpu.registers.get_flag(Flag::Z));
+        assert!(!cpu.registers.get_flag(Flag::N));
+        assert!(cpu.registers.get_flag(Flag::H));
+        assert!(cpu.registers.get_flag(Flag::C));
+    }
+
+    #[test]
+    fn compare_leaves_accumulator_untouched() {
+        // LD A, 0x3C; CP 0x3C
+        let mut cpu = cpu_with_program(&[0x3E, 0x3C, 0xFE, 0x3C]);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.registers.read_register(Register::A), 0x3C);
+        assert!(cpu.registers.get_flag(Flag::Z));
+        assert!(cpu.registers.get_flag(Flag::N));
+        assert!(!cpu.registers.get_flag(Flag::C));
+    }
+
+    #[test]
+    fn inc_preserves_carry() {
+        // SCF; LD B, 0xFF; INC B
+        let mut cpu = cpu_with_program(&[0x37, 0x06, 0xFF, 0x04]);
+        cpu.step();
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.registers.read_register(Register::B), 0);
+        assert!(cpu.registers.get_flag(Flag::Z));
+        assert!(cpu.registers.get_flag(Flag::H));
+        assert!(cpu.registers.get_flag(Flag::C));
+    }
+
+    #[test]
+    fn call_and_return() {
+        // LD SP, 0xFFFE; CALL 0x0010; ... 0x0010: RET
+        let mut program = [0; 0x11];
+        program[..6].copy_from_slice(&[0x31, 0xFE, 0xFF, 0xCD, 0x10, 0x00]);
+        program[0x10] = 0xC9;
+        let mut cpu = cpu_with_program(&program);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0010);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xFFFC);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0006);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xFFFE);
+    }
+
+    #[test]
+    fn push_pop_round_trips_every_pair() {
+        // LD SP, 0xD000; PUSH BC; PUSH DE; PUSH HL; POP AF; POP HL; POP DE
+        let mut cpu = cpu_with_program(&[0x31, 0x00, 0xD0, 0xC5, 0xD5, 0xE5, 0xF1, 0xE1, 0xD1]);
+        cpu.registers.write_register(Register::BC, 0x1234);
+        cpu.registers.write_register(Register::DE, 0x5678);
+        cpu.registers.write_register(Register::HL, 0x9ABF);
+
+        for _ in 0..4 {
+            cpu.step();
+        }
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xCFFA);
+        assert_eq!(cpu.read_memory(0xCFFF), 0x12);
+        assert_eq!(cpu.read_memory(0xCFFE), 0x34);
+
+        cpu.step();
+        // The unused bits of F can't be set by popping AF
+        assert_eq!(cpu.registers.read_register(Register::AF), 0x9AB0);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::HL), 0x5678);
+        assert_eq!(cpu.registers.read_register(Register::DE), 0x1234);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xD000);
     }
 
-    pub fn step(&mut self) {
-        let opcode = self.read_memory(self.program_counter);
+    #[test]
+    fn conditional_call_and_return() {
+        // LD SP, 0xD000; XOR A; CALL NZ, 0x0020; CALL Z, 0x0020; ... 0x0020: RET NZ; RET Z
+        let mut program = [0; 0x22];
+        program[..10]
+            .copy_from_slice(&[0x31, 0x00, 0xD0, 0xAF, 0xC4, 0x20, 0x00, 0xCC, 0x20, 0x00]);
+        program[0x20] = 0xC0;
+        program[0x21] = 0xC8;
+        let mut cpu = cpu_with_program(&program);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0007);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xD000);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0020);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0021);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x000A);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xD000);
+    }
+
+    #[test]
+    fn restart_and_return_from_interrupt() {
+        // LD SP, 0xD000; RST 38H; ... 0x0038: RETI
+        let mut program = [0; 0x39];
+        program[..4].copy_from_slice(&[0x31, 0x00, 0xD0, 0xFF]);
+        program[0x38] = 0xD9;
+        let mut cpu = cpu_with_program(&program);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0038);
+        assert_eq!(cpu.read_memory(0xCFFE), 0x04);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0004);
+        assert!(cpu.interrupt_master_enable);
+    }
+
+    #[test]
+    fn interrupt_dispatch() {
+        // LD SP, 0xD000; EI; NOP; NOP
+        let mut cpu = cpu_with_program(&[0x31, 0x00, 0xD0, 0xFB, 0x00, 0x00]);
+        cpu.write_memory(INTERRUPT_ENABLE, 0x1F);
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.request_interrupt(Interrupt::Joypad);
+
+        cpu.step();
+        cpu.step();
+        // EI takes effect after the following instruction
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0005);
+
+        assert_eq!(cpu.step(), 20);
+        assert_eq!(cpu.program_counter, 0x0050);
+        assert_eq!(cpu.read_memory(0xCFFE), 0x05);
+        assert!(!cpu.interrupt_master_enable);
+        // Only the serviced interrupt is acknowledged
+        assert_eq!(cpu.read_memory(INTERRUPT_FLAG), 0xF0);
+    }
+
+    #[test]
+    fn di_straight_after_ei_keeps_interrupts_disabled() {
+        // EI; DI; NOP
+        let mut cpu = cpu_with_program(&[0xFB, 0xF3, 0x00]);
+        cpu.write_memory(INTERRUPT_ENABLE, 0x01);
+        cpu.request_interrupt(Interrupt::VBlank);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0003);
+    }
+
+    #[test]
+    fn halt_waits_for_an_interrupt() {
+        // LD SP, 0xD000; EI; HALT; NOP
+        let mut cpu = cpu_with_program(&[0x31, 0x00, 0xD0, 0xFB, 0x76, 0x00]);
+        cpu.write_memory(INTERRUPT_ENABLE, 0x01);
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+        for _ in 0..10 {
+            assert_eq!(cpu.step(), 4);
+        }
+        assert_eq!(cpu.program_counter, 0x0005);
+
+        cpu.request_interrupt(Interrupt::VBlank);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0040);
+        assert_eq!(cpu.read_memory(0xCFFE), 0x05);
+    }
+
+    #[test]
+    fn halt_without_ime_resumes_without_dispatch() {
+        // HALT; INC A
+        let mut cpu = cpu_with_program(&[0x76, 0x3C]);
+        cpu.write_memory(INTERRUPT_ENABLE, 0x04);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        cpu.request_interrupt(Interrupt::Timer);
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0002);
+        assert_eq!(cpu.registers.read_register(Register::A), 1);
+    }
+
+    #[test]
+    fn halt_bug_repeats_the_next_byte() {
+        // HALT; INC A; NOP with an interrupt already pending and IME clear
+        let mut cpu = cpu_with_program(&[0x76, 0x3C, 0x00]);
+        cpu.write_memory(INTERRUPT_ENABLE, 0x04);
+        cpu.request_interrupt(Interrupt::Timer);
+
+        cpu.step();
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::A), 2);
+        assert_eq!(cpu.program_counter, 0x0002);
+    }
+
+    #[test]
+    fn decimal_adjust_after_addition() {
+        // LD A, 0x45; ADD A, 0x38; DAA
+        let mut cpu = cpu_with_program(&[0x3E, 0x45, 0xC6, 0x38, 0x27]);
+        cpu.step();
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.registers.read_register(Register::A), 0x83);
+        assert!(!cpu.registers.get_flag(Flag::C));
+    }
+
+    #[test]
+    fn illegal_opcode_locks_cpu() {
+        let mut cpu = cpu_with_program(&[0xD3, 0x00]);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.program_counter, 1);
+    }
+
+    #[test]
+    fn stack_pointer_relative_loads() {
+        // LD SP, 0xFFF8; LD HL, SP-2; ADD SP, 0x08
+        let mut cpu = cpu_with_program(&[0x31, 0xF8, 0xFF, 0xF8, 0xFE, 0xE8, 0x08]);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::HL), 0xFFF6);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xFFF8);
+        assert!(cpu.registers.get_flag(Flag::C));
+        assert!(cpu.registers.get_flag(Flag::H));
+
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0x0000);
+        assert!(!cpu.registers.get_flag(Flag::Z));
+    }
+
+    #[test]
+    fn word_inc_dec_wraps_without_flags() {
+        // DEC BC; INC SP
+        let mut cpu = cpu_with_program(&[0x0B, 0x33]);
+        cpu.registers.write_register(Register::StackPointer, 0xFFFF);
+        cpu.registers.write_register(Register::F, 0x50);
+
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::BC), 0xFFFF);
+
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0x0000);
+        assert_eq!(cpu.registers.read_register(Register::F), 0x50);
+    }
 
-        let instruction = Instruction::decode(opcode);
+    #[test]
+    fn relative_jumps_are_signed() {
+        // 0x0000: JR +2; 0x0002: NOP; 0x0003: NOP; 0x0004: JR -6
+        let mut cpu = cpu_with_program(&[0x18, 0x02, 0x00, 0x00, 0x18, 0xFA]);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0004);
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn relative_jump_to_itself() {
+        // JR -2 loops forever on the same instruction
+        let mut cpu = cpu_with_program(&[0x18, 0xFE]);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn program_counter_wraps_around() {
+        let mut cpu = test_cpu();
+        // JR +2 at 0xFFFD lands past the end of the address space
+        cpu.write_memory(0xFFFD, 0x18);
+        cpu.write_memory(0xFFFE, 0x02);
+        cpu.program_counter = 0xFFFD;
+
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0001);
+
+        // Fetching the last byte of memory moves on to 0x0000
+        cpu.write_memory(0xFFFF, 0x00);
+        cpu.program_counter = 0xFFFF;
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0000);
+    }
+
+    #[test]
+    fn conditional_jumps_report_cycles() {
+        let conditions = [
+            (JumpCondition::NotZero, 0x00, true),
+            (JumpCondition::NotZero, 0x80, false),
+            (JumpCondition::Zero, 0x80, true),
+            (JumpCondition::Zero, 0x00, false),
+            (JumpCondition::NotCarry, 0x00, true),
+            (JumpCondition::NotCarry, 0x10, false),
+            (JumpCondition::Carry, 0x10, true),
+            (JumpCondition::Carry, 0x00, false),
+        ];
+
+        for (condition, flags, taken) in conditions.iter() {
+            // JP cc, 0x1234 is followed by JR cc, -4
+            let mut cpu = cpu_with_program(&[0x00, 0x34, 0x12, 0x00, 0xFC]);
+            cpu.registers.write_register(Register::F, *flags);
+            cpu.program_counter = 1;
+
+            let cycles = cpu.execute_jump(*condition);
+            if *taken {
+                assert_eq!(cpu.program_counter, 0x1234);
+                assert_eq!(cycles, 4);
+            } else {
+                assert_eq!(cpu.program_counter, 0x0003);
+                assert_eq!(cycles, 3);
+            }
+
+            cpu.program_counter = 4;
+            let cycles = cpu.execute_jump_relative(*condition);
+            if *taken {
+                assert_eq!(cpu.program_counter, 0x0001);
+                assert_eq!(cycles, 3);
+            } else {
+                assert_eq!(cpu.program_counter, 0x0005);
+                assert_eq!(cycles, 2);
+            }
+        }
+    }
+
+    #[test]
+    fn jump_to_hl() {
+        // LD HL, 0xC123; JP HL
+        let mut cpu = cpu_with_program(&[0x21, 0x23, 0xC1, 0xE9]);
+        cpu.step();
+        cpu.step();
+
+        assert_eq!(cpu.program_counter, 0xC123);
+    }
+
+    #[test]
+    fn step_reports_t_cycles() {
+        // NOP; LD BC, d16; LD (HL), d8; PUSH BC; CB RLC (HL)
+        let mut cpu = cpu_with_program(&[0x00, 0x01, 0x00, 0x00, 0x36, 0x00, 0xC5, 0xCB, 0x06]);
+        cpu.registers.write_register(Register::HL, 0xC000);
+        cpu.registers.write_register(Register::StackPointer, 0xD000);
+
+        assert_eq!(cpu.step(), 4);
+        assert_eq!(cpu.step(), 12);
+        assert_eq!(cpu.step(), 12);
+        assert_eq!(cpu.step(), 16);
+        assert_eq!(cpu.step(), 16);
+        assert_eq!(cpu.total_cycles(), 60);
+    }
+
+    #[test]
+    fn conditional_returns_and_calls_take_longer_when_taken() {
+        // LD SP, 0xD000; CALL NZ, 0x0010; ... 0x0010: RET Z; RET NZ
+        let mut program = [0; 0x12];
+        program[..6].copy_from_slice(&[0x31, 0x00, 0xD0, 0xC4, 0x10, 0x00]);
+        program[0x10] = 0xC8;
+        program[0x11] = 0xC0;
+        let mut cpu = cpu_with_program(&program);
+
+        cpu.step();
+        assert_eq!(cpu.step(), 24);
+        assert_eq!(cpu.step(), 8);
+        assert_eq!(cpu.step(), 20);
+    }
+
+    #[test]
+    fn run_frame_keeps_in_step_with_the_clock() {
+        // A program made of LD (a16), SP which takes 20 T-cycles, so frames can't end exactly
+        let mut cpu = test_cpu();
+        for address in (0..0x8000).step_by(3) {
+            cpu.write_memory(address, 0x08);
+            cpu.write_memory(address + 1, 0x00);
+            cpu.write_memory(address + 2, 0xC0);
+        }
+
+        let mut elapsed = 0;
+        for _ in 0..3 {
+            elapsed += cpu.run_frame();
+        }
+
+        assert!(elapsed >= CYCLES_PER_FRAME * 3);
+        assert!(elapsed < CYCLES_PER_FRAME * 3 + 20);
+    }
+
+    #[test]
+    fn ppu_runs_alongside_cpu() {
+        // NOPs all the way, with the LCD switched on
+        let mut cpu = test_cpu();
+        cpu.write_memory(0xFF40, 0x80);
+
+        cpu.run_cycles(456 * 3);
+        assert_eq!(cpu.read_memory(0xFF44), 3);
+    }
+
+    #[test]
+    fn oam_dma_routine_in_high_ram() {
+        let mut cpu = test_cpu();
+        for index in 0..0xA0 {
+            cpu.write_memory(0xC100 + index, !index as u8);
+        }
+
+        // LD A, 0xC1; LDH (0x46), A; LD A, 40; DEC A; JR NZ, -3; JR -2
+        let routine = [
+            0x3E, 0xC1, 0xE0, 0x46, 0x3E, 0x28, 0x3D, 0x20, 0xFD, 0x18, 0xFE,
+        ];
+        for (address, &byte) in (0xFF80..).zip(routine.iter()) {
+            cpu.write_memory(address, byte);
+        }
+        cpu.program_counter = 0xFF80;
+
+        // The wait loop takes 160 M-cycles, just enough for the copy to finish
+        while cpu.program_counter != 0xFF89 {
+            cpu.step();
+        }
+        for index in 0..0xA0 {
+            assert_eq!(cpu.read_memory(0xFE00 + index), !index as u8);
+        }
+    }
+
+    #[test]
+    fn ppu_requests_vblank_interrupt() {
+        let mut cpu = test_cpu();
+        cpu.write_memory(0xFF40, 0x80);
+
+        cpu.run_cycles(456 * 143);
+        assert_eq!(cpu.read_memory(INTERRUPT_FLAG) & 0x01, 0x00);
+        cpu.run_cycles(456);
+        assert_eq!(cpu.read_memory(INTERRUPT_FLAG) & 0x01, 0x01);
+    }
+
+    #[test]
+    fn prefixed_rotates_and_shifts() {
+        // LD B, 0x85; RLC B; LD C, 0x81; SRA C; LD D, 0xF1; SWAP D
+        let mut cpu = cpu_with_program(&[
+            0x06, 0x85, 0xCB, 0x00, 0x0E, 0x81, 0xCB, 0x29, 0x16, 0xF1, 0xCB, 0x32,
+        ]);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::B), 0x0B);
+        assert!(cpu.registers.get_flag(Flag::C));
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::C), 0xC0);
+        assert!(cpu.registers.get_flag(Flag::C));
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::D), 0x1F);
+        assert!(!cpu.registers.get_flag(Flag::C));
+        assert!(!cpu.registers.get_flag(Flag::Z));
+    }
+
+    #[test]
+    fn prefixed_bit_operations_on_memory() {
+        // LD HL, 0xC000; SET 3, (HL); BIT 3, (HL); RES 3, (HL); BIT 3, (HL)
+        let mut cpu = cpu_with_program(&[
+            0x21, 0x00, 0xC0, 0xCB, 0xDE, 0xCB, 0x5E, 0xCB, 0x9E, 0xCB, 0x5E,
+        ]);
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.read_memory(0xC000), 0x08);
+
+        cpu.step();
+        assert!(!cpu.registers.get_flag(Flag::Z));
+
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.read_memory(0xC000), 0x00);
+        assert!(cpu.registers.get_flag(Flag::Z));
+    }
+
+    #[test]
+    fn prefixed_memory_forms_take_longer() {
+        assert_eq!(PrefixedInstruction::decode(0x00).cycles(), 2); // RLC B
+        assert_eq!(PrefixedInstruction::decode(0x06).cycles(), 4); // RLC (HL)
+        assert_eq!(PrefixedInstruction::decode(0x46).cycles(), 3); // BIT 0, (HL)
+        assert_eq!(PrefixedInstruction::decode(0xC6).cycles(), 4); // SET 0, (HL)
+        assert_eq!(PrefixedInstruction::decode(0x7C).cycles(), 2); // BIT 7, H
+    }
+
+    #[test]
+    fn runs_boot_rom_until_it_unmaps_itself() {
+        let mut cpu = test_cpu();
+        cpu.write_memory(0x0000, 0x00);
+        cpu.write_memory(0x0100, 0x3C);
+
+        // LD A, 0x01; LDH (0x50), A at the very end of the boot ROM, falling through into the
+        // cartridge at 0x0100
+        let mut boot_rom = vec![0x00; 0x0100];
+        boot_rom[0xFC..].copy_from_slice(&[0x3E, 0x01, 0xE0, 0x50]);
+        boot_rom[0x0000..0x0003].copy_from_slice(&[0xC3, 0xFC, 0x00]);
+        cpu.bus.insert_boot_rom(boot_rom).unwrap();
+
+        // JP 0x00FC, then the two loads
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x00FC);
+        cpu.step();
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x0100);
+        assert!(!cpu.bus.boot_rom_mapped());
+        assert_eq!(cpu.read_memory(0x0000), 0x00);
+
+        // INC A from the cartridge
+        cpu.step();
+        assert_eq!(cpu.registers.read_register(Register::A), 0x02);
+    }
+
+    #[test]
+    fn skipping_boot_rom_sets_post_boot_state() {
+        let mut cpu = test_cpu();
+        cpu.write_memory(0x014D, 0x42);
+        cpu.skip_boot_rom();
+
+        assert_eq!(cpu.program_counter, 0x0100);
+        assert_eq!(cpu.registers.read_register(Register::AF), 0x01B0);
+        assert_eq!(cpu.registers.read_register(Register::BC), 0x0013);
+        assert_eq!(cpu.registers.read_register(Register::DE), 0x00D8);
+        assert_eq!(cpu.registers.read_register(Register::HL), 0x014D);
+        assert_eq!(cpu.registers.read_register(Register::StackPointer), 0xFFFE);
+        assert_eq!(cpu.read_memory(0xFF40), 0x91);
+        assert_eq!(cpu.read_memory(0xFF04), 0xAB);
+        assert_eq!(cpu.read_memory(0xFF0F), 0xE1);
+        assert_eq!(cpu.read_memory(0xFF26), 0xF1);
+    }
+
+    #[test]
+    fn post_boot_state_depends_on_model() {
+        // A zero header checksum leaves half-carry and carry clear on the DMG and MGB
+        let mut cpu = test_cpu_with_model(Model::Mgb);
+        cpu.skip_boot_rom();
+        assert_eq!(cpu.registers.read_register(Register::AF), 0xFF80);
+
+        let mut cpu = test_cpu_with_model(Model::Sgb);
+        cpu.skip_boot_rom();
+        assert_eq!(cpu.registers.read_register(Register::AF), 0x0100);
+        assert_eq!(cpu.registers.read_register(Register::HL), 0xC060);
+        assert_eq!(cpu.read_memory(0xFF26), 0xF0);
+    }
+
+    #[test]
+    fn cgb_hardware_is_detectable() {
+        // A CGB game on a CGB
+        let mut cpu = test_cpu_with_model(Model::Cgb);
+        cpu.write_memory(0x0143, 0x80);
+        cpu.skip_boot_rom();
+        assert_eq!(cpu.registers.read_register(Register::A), 0x11);
+        assert_eq!(cpu.registers.read_register(Register::DE), 0xFF56);
+
+        // A DMG game on a CGB still sees A = 0x11
+        let mut cpu = test_cpu_with_model(Model::Cgb);
+        cpu.skip_boot_rom();
+        assert_eq!(cpu.registers.read_register(Register::A), 0x11);
+        assert_eq!(cpu.registers.read_register(Register::HL), 0x007C);
+
+        // The AGB also sets bit 0 of B
+        let mut cpu = test_cpu_with_model(Model::Agb);
+        cpu.write_memory(0x0143, 0x80);
+        cpu.skip_boot_rom();
+        assert_eq!(cpu.registers.read_register(Register::AF), 0x1100);
+        assert_eq!(cpu.registers.read_register(Register::B), 0x01);
+    }
+
+    #[test]
+    fn stop_switches_speed_when_armed() {
+        let mut cpu = test_cpu_with_model(Model::Cgb);
+        // LD A, 1; LDH (0x4D), A; STOP; NOP
+        for (address, &byte) in [0x3E, 0x01, 0xE0, 0x4D, 0x10, 0x00, 0x00]
+            .iter()
+            .enumerate()
+        {
+            cpu.write_memory(address as u16, byte);
+        }
+
+        for _ in 0..3 {
+            cpu.step();
+        }
+        assert!(cpu.bus.double_speed());
+        assert_eq!(cpu.read_memory(0xFF4D), 0xFE);
+
+        // The CPU sits out the switch, then runs at twice the speed
+        assert_eq!(cpu.step(), 2050 * 2);
+        assert_eq!(cpu.step(), 2);
+        assert_eq!(cpu.program_counter, 0x0007);
+    }
+
+    #[test]
+    fn stop_without_a_speed_switch_stops() {
+        let mut cpu = test_cpu_with_model(Model::Cgb);
+        cpu.write_memory(0x0000, 0x10);
+        cpu.step();
+
+        assert!(cpu.stopped);
+        assert!(!cpu.bus.double_speed());
+    }
+
+    #[test]
+    fn general_purpose_dma_halts_the_cpu() {
+        let mut cpu = test_cpu_with_model(Model::Cgb);
+        // LD A, 0x03; LDH (0x55), A; NOP
+        for (address, &byte) in [0x3E, 0x03, 0xE0, 0x55, 0x00].iter().enumerate() {
+            cpu.write_memory(address as u16, byte);
+        }
+        cpu.write_memory(0xFF51, 0xC0);
+        cpu.write_memory(0xFF53, 0x00);
 
-        self.execute(instruction);
+        cpu.step();
+        cpu.step();
+        // 4 blocks of 8 M-cycles
+        assert_eq!(cpu.step(), 4 * 32);
+        assert_eq!(cpu.program_counter, 0x0004);
     }
 }
@@ -0,0 +1,159 @@
+pub const DMA: u16 = 0xFF46;
+
+// Bytes copied into OAM by each transfer, one per M-cycle
+const TRANSFER_LENGTH: u8 = 0xA0;
+
+// Writing to DMA copies 160 bytes from XX00-XX9F into OAM, where XX is the value written. The
+// copy runs in the background for 160 M-cycles after a cycle to get started, and has the bus to
+// itself while it does. Only the registers and HRAM are still reachable, which is why games run
+// their DMA routine from HRAM.
+//
+// This only keeps track of where the transfer is up to. The bus does the copying.
+pub struct OamDma {
+    // Last value written to the register, which reads back
+    register: u8,
+    // Source address of a transfer that starts on the next M-cycle
+    starting: Option<u16>,
+    // Source address and progress of the running transfer
+    active: Option<(u16, u8)>,
+    // The last byte copied, which is what the CPU sees if it reads while the bus is busy
+    last_byte: u8,
+}
+
+impl Default for OamDma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OamDma {
+    pub fn new() -> Self {
+        OamDma {
+            register: 0xFF,
+            starting: None,
+            active: None,
+            last_byte: 0xFF,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    // Starting a new transfer while one is running takes over from it once the new one has
+    // started. Until then the old one carries on.
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+        self.starting = Some((value as u16) << 8);
+    }
+
+    pub fn active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    // Whether the CPU is locked out of the address by a running transfer
+    pub fn blocks(&self, address: u16) -> bool {
+        self.active() && address < 0xFF00
+    }
+
+    // What the CPU reads from a blocked address. OAM is being written so reads as 0xFF, while
+    // anything else picks up whatever the transfer has on the bus.
+    pub fn blocked_read(&self, address: u16) -> u8 {
+        match address {
+            0xFE00..=0xFEFF => 0xFF,
+            _ => self.last_byte,
+        }
+    }
+
+    pub fn set_last_byte(&mut self, value: u8) {
+        self.last_byte = value;
+    }
+
+    // Moves on by one M-cycle. Returns the address to copy from and the OAM index to copy to, if
+    // a byte is copied in this cycle.
+    pub fn tick(&mut self) -> Option<(u16, u8)> {
+        let copy = self.active.map(|(source, index)| {
+            // Sources past the end of work RAM read from its echo instead
+            let address = source + index as u16;
+            let address = if address >= 0xE000 {
+                address - 0x2000
+            } else {
+                address
+            };
+
+            (address, index)
+        });
+
+        self.active = match self.active {
+            Some((source, index)) if index + 1 < TRANSFER_LENGTH => Some((source, index + 1)),
+            _ => None,
+        };
+
+        if let Some(source) = self.starting.take() {
+            self.active = Some((source, 0));
+        }
+
+        copy
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copies_one_byte_a_cycle_after_a_cycle_to_start() {
+        let mut dma = OamDma::new();
+        dma.write(0xC1);
+        assert_eq!(dma.read(), 0xC1);
+        assert!(!dma.active());
+
+        assert_eq!(dma.tick(), None);
+        assert!(dma.active());
+
+        for index in 0..160 {
+            assert_eq!(dma.tick(), Some((0xC100 + index as u16, index)));
+        }
+        assert!(!dma.active());
+        assert_eq!(dma.tick(), None);
+    }
+
+    #[test]
+    fn sources_past_work_ram_use_the_echo() {
+        let mut dma = OamDma::new();
+        dma.write(0xFE);
+        dma.tick();
+
+        assert_eq!(dma.tick(), Some((0xDE00, 0)));
+    }
+
+    #[test]
+    fn restarting_takes_over_after_a_cycle() {
+        let mut dma = OamDma::new();
+        dma.write(0xC0);
+        for _ in 0..11 {
+            dma.tick();
+        }
+
+        // The old transfer carries on for the cycle the new one takes to start
+        dma.write(0xD0);
+        assert_eq!(dma.tick(), Some((0xC00A, 10)));
+        assert_eq!(dma.tick(), Some((0xD000, 0)));
+        assert!(dma.active());
+    }
+
+    #[test]
+    fn blocks_everything_below_the_registers() {
+        let mut dma = OamDma::new();
+        assert!(!dma.blocks(0xC000));
+
+        dma.write(0xC0);
+        dma.tick();
+        dma.set_last_byte(0x42);
+        assert!(dma.blocks(0x0000));
+        assert!(dma.blocks(0xFE00));
+        assert!(!dma.blocks(0xFF80));
+        assert_eq!(dma.blocked_read(0xC000), 0x42);
+        assert_eq!(dma.blocked_read(0xFE00), 0xFF);
+    }
+}
@@ -0,0 +1,157 @@
+pub const HDMA1: u16 = 0xFF51;
+pub const HDMA2: u16 = 0xFF52;
+pub const HDMA3: u16 = 0xFF53;
+pub const HDMA4: u16 = 0xFF54;
+pub const HDMA5: u16 = 0xFF55;
+
+// Bytes copied at a time, and the unit transfer lengths are given in
+pub const BLOCK_LENGTH: u16 = 0x10;
+
+// CGB mode's VRAM DMA, which copies blocks of 16 bytes into VRAM from a source set through
+// HDMA1-2 to a destination set through HDMA3-4. Writing the length in blocks to HDMA5 starts it
+// off in one of two ways:
+//
+// - General-purpose DMA (bit 7 clear) copies everything straight away, halting the CPU until it's
+//   done
+// - HBlank DMA (bit 7 set) copies one block at the start of each HBlank, halting the CPU only for
+//   that block. Writing HDMA5 with bit 7 clear while it's running stops it.
+//
+// Like OamDma this only keeps track of where the transfer is up to, and the bus does the copying.
+pub struct Hdma {
+    source: u16,
+    // Offset into VRAM
+    destination: u16,
+    // Blocks left to copy
+    remaining: u8,
+    hblank_active: bool,
+    general_pending: bool,
+}
+
+impl Default for Hdma {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hdma {
+    pub fn new() -> Self {
+        Hdma {
+            source: 0,
+            destination: 0,
+            remaining: 0,
+            hblank_active: false,
+            general_pending: false,
+        }
+    }
+
+    // Only HDMA5 can be read back. Bit 7 is clear while an HBlank DMA is running, and the rest
+    // is the number of blocks left minus 1, so a finished transfer reads 0xFF.
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            HDMA5 => {
+                let active = if self.hblank_active { 0x00 } else { 0x80 };
+                active | (self.remaining.wrapping_sub(1) & 0x7F)
+            }
+            _ => 0xFF,
+        }
+    }
+
+    // The lowest 4 bits of both addresses are ignored, and the destination is always in VRAM
+    pub fn write(&mut self, address: u16, value: u8) {
+        match address {
+            HDMA1 => self.source = (self.source & 0x00FF) | (value as u16) << 8,
+            HDMA2 => self.source = (self.source & 0xFF00) | (value & 0xF0) as u16,
+            HDMA3 => self.destination = (self.destination & 0x00FF) | ((value & 0x1F) as u16) << 8,
+            HDMA4 => self.destination = (self.destination & 0xFF00) | (value & 0xF0) as u16,
+            HDMA5 if self.hblank_active && value & 0x80 == 0 => self.hblank_active = false,
+            HDMA5 => {
+                self.remaining = (value & 0x7F) + 1;
+                self.hblank_active = value & 0x80 != 0;
+                self.general_pending = !self.hblank_active;
+            }
+            _ => (),
+        }
+    }
+
+    pub fn hblank_active(&self) -> bool {
+        self.hblank_active
+    }
+
+    // The number of blocks a general-purpose DMA that has just been started wants to copy
+    pub fn take_general(&mut self) -> Option<u8> {
+        std::mem::take(&mut self.general_pending).then_some(self.remaining)
+    }
+
+    // Moves on to the next block, returning its source address and VRAM offset
+    pub fn next_block(&mut self) -> (u16, u16) {
+        let block = (self.source, self.destination);
+
+        self.source = self.source.wrapping_add(BLOCK_LENGTH);
+        self.destination = (self.destination + BLOCK_LENGTH) & 0x1FF0;
+        self.remaining -= 1;
+        if self.remaining == 0 {
+            self.hblank_active = false;
+        }
+
+        block
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses_ignore_low_bits() {
+        let mut hdma = Hdma::new();
+        hdma.write(HDMA1, 0xC1);
+        hdma.write(HDMA2, 0x2F);
+        hdma.write(HDMA3, 0xF3);
+        hdma.write(HDMA4, 0x4F);
+        hdma.write(HDMA5, 0x00);
+
+        assert_eq!(hdma.next_block(), (0xC120, 0x1340));
+        assert_eq!(hdma.read(HDMA1), 0xFF);
+    }
+
+    #[test]
+    fn general_dma_copies_every_block_at_once() {
+        let mut hdma = Hdma::new();
+        hdma.write(HDMA5, 0x02);
+
+        assert_eq!(hdma.take_general(), Some(3));
+        assert_eq!(hdma.take_general(), None);
+        assert!(!hdma.hblank_active());
+        for _ in 0..3 {
+            hdma.next_block();
+        }
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+    }
+
+    #[test]
+    fn hblank_dma_reports_blocks_left() {
+        let mut hdma = Hdma::new();
+        hdma.write(HDMA5, 0x81);
+        assert_eq!(hdma.take_general(), None);
+        assert!(hdma.hblank_active());
+        assert_eq!(hdma.read(HDMA5), 0x01);
+
+        hdma.next_block();
+        assert_eq!(hdma.read(HDMA5), 0x00);
+        hdma.next_block();
+        assert!(!hdma.hblank_active());
+        assert_eq!(hdma.read(HDMA5), 0xFF);
+    }
+
+    #[test]
+    fn hblank_dma_can_be_stopped() {
+        let mut hdma = Hdma::new();
+        hdma.write(HDMA5, 0x85);
+        hdma.next_block();
+
+        hdma.write(HDMA5, 0x00);
+        assert!(!hdma.hblank_active());
+        assert_eq!(hdma.take_general(), None);
+        assert_eq!(hdma.read(HDMA5), 0x84);
+    }
+}
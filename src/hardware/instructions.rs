@@ -1,4 +1,4 @@
-use super::registers::{Flag, Register};
+use super::registers::Register;
 
 pub enum RegisterSideEffect {
     Inc,
@@ -14,61 +14,156 @@ pub enum LoadType {
     FromMemory(Register, Register),
     FromMemoryWithSideEffect(Register, RegisterSideEffect),
     StackPointerToMemory,
+    ToImmediateAddress,
+    FromImmediateAddress,
+    ToHighMemoryImmediate,
+    FromHighMemoryImmediate,
+    ToHighMemory(Register),
+    FromHighMemory(Register),
+    HLToStackPointer,
+    StackPointerOffsetToHL,
 }
 
-pub enum XORTarget {
+pub enum ArithmeticTarget {
     Register(Register),
+    Memory(Register),
+    Immediate,
 }
 
+pub enum IncDecTarget {
+    Register(Register),
+    Memory(Register),
+    Word(Register),
+}
+
+#[derive(Clone, Copy)]
 pub enum JumpCondition {
-    NegatedFlag(Flag),
+    Always,
+    NotZero,
+    Zero,
+    NotCarry,
+    Carry,
 }
 
 pub enum Instruction {
     Load(LoadType),
-    XOR(XORTarget),
+    Add(ArithmeticTarget),
+    AddWithCarry(ArithmeticTarget),
+    Subtract(ArithmeticTarget),
+    SubtractWithCarry(ArithmeticTarget),
+    And(ArithmeticTarget),
+    Xor(ArithmeticTarget),
+    Or(ArithmeticTarget),
+    Compare(ArithmeticTarget),
+    Increment(IncDecTarget),
+    Decrement(IncDecTarget),
+    AddWord(Register),
+    AddStackPointer,
+    RotateLeftCircularA,
+    RotateRightCircularA,
+    RotateLeftA,
+    RotateRightA,
+    DecimalAdjust,
+    Complement,
+    SetCarryFlag,
+    ComplementCarryFlag,
+    Push(Register),
+    Pop(Register),
+    Jump(JumpCondition),
+    JumpToHL,
     JumpRelative(JumpCondition),
+    Call(JumpCondition),
+    Return(JumpCondition),
+    ReturnFromInterrupt,
+    Restart(u8),
+    DisableInterrupts,
+    EnableInterrupts,
+    Halt,
+    Stop,
     Prefixed,
     NoOp,
+    Illegal,
 }
 
 impl Instruction {
     pub fn decode(opcode: u8) -> Self {
         match opcode {
+            0x00 => Instruction::NoOp,                                        // NOP
             0x01 => Instruction::Load(LoadType::ImmediateWord(Register::BC)), // LD BC, d16
             0x02 => Instruction::Load(LoadType::ToMemory(Register::BC, Register::A)), // LD (BC), A
+            0x03 => Instruction::Increment(IncDecTarget::Word(Register::BC)), // INC BC
+            0x04 => Instruction::Increment(IncDecTarget::Register(Register::B)), // INC B
+            0x05 => Instruction::Decrement(IncDecTarget::Register(Register::B)), // DEC B
             0x06 => Instruction::Load(LoadType::ImmediateByte(Register::B)),  // LD B, d8
+            0x07 => Instruction::RotateLeftCircularA,                         // RLCA
             0x08 => Instruction::Load(LoadType::StackPointerToMemory),        // LD (a16), SP
+            0x09 => Instruction::AddWord(Register::BC),                       // ADD HL, BC
             0x0A => Instruction::Load(LoadType::FromMemory(Register::A, Register::BC)), // LD A, (BC)
+            0x0B => Instruction::Decrement(IncDecTarget::Word(Register::BC)),           // DEC BC
+            0x0C => Instruction::Increment(IncDecTarget::Register(Register::C)),        // INC C
+            0x0D => Instruction::Decrement(IncDecTarget::Register(Register::C)),        // DEC C
             0x0E => Instruction::Load(LoadType::ImmediateByte(Register::C)),            // LD C, d8
+            0x0F => Instruction::RotateRightCircularA,                                  // RRCA
+            0x10 => Instruction::Stop,                                                  // STOP
             0x11 => Instruction::Load(LoadType::ImmediateWord(Register::DE)), // LD DE, d16
             0x12 => Instruction::Load(LoadType::ToMemory(Register::DE, Register::A)), // LD (DE), A
+            0x13 => Instruction::Increment(IncDecTarget::Word(Register::DE)), // INC DE
+            0x14 => Instruction::Increment(IncDecTarget::Register(Register::D)), // INC D
+            0x15 => Instruction::Decrement(IncDecTarget::Register(Register::D)), // DEC D
             0x16 => Instruction::Load(LoadType::ImmediateByte(Register::D)),  // LD D, d8
+            0x17 => Instruction::RotateLeftA,                                 // RLA
+            0x18 => Instruction::JumpRelative(JumpCondition::Always),         // JR s8
+            0x19 => Instruction::AddWord(Register::DE),                       // ADD HL, DE
             0x1A => Instruction::Load(LoadType::FromMemory(Register::A, Register::DE)), // LD A, (DE)
+            0x1B => Instruction::Decrement(IncDecTarget::Word(Register::DE)),           // DEC DE
+            0x1C => Instruction::Increment(IncDecTarget::Register(Register::E)),        // INC E
+            0x1D => Instruction::Decrement(IncDecTarget::Register(Register::E)),        // DEC E
             0x1E => Instruction::Load(LoadType::ImmediateByte(Register::E)),            // LD E, d8
-            0x20 => Instruction::JumpRelative(JumpCondition::NegatedFlag(Flag::Z)),     // JR NZ, s8
+            0x1F => Instruction::RotateRightA,                                          // RRA
+            0x20 => Instruction::JumpRelative(JumpCondition::NotZero),                  // JR NZ, s8
             0x21 => Instruction::Load(LoadType::ImmediateWord(Register::HL)), // LD HL, d16
             0x22 => Instruction::Load(LoadType::ToMemoryWithSideEffect(
                 Register::HL,
                 RegisterSideEffect::Inc,
             )), // LD (HL+), A
+            0x23 => Instruction::Increment(IncDecTarget::Word(Register::HL)), // INC HL
+            0x24 => Instruction::Increment(IncDecTarget::Register(Register::H)), // INC H
+            0x25 => Instruction::Decrement(IncDecTarget::Register(Register::H)), // DEC H
             0x26 => Instruction::Load(LoadType::ImmediateByte(Register::H)),  // LD H, d8
+            0x27 => Instruction::DecimalAdjust,                               // DAA
+            0x28 => Instruction::JumpRelative(JumpCondition::Zero),           // JR Z, s8
+            0x29 => Instruction::AddWord(Register::HL),                       // ADD HL, HL
             0x2A => Instruction::Load(LoadType::FromMemoryWithSideEffect(
                 Register::HL,
                 RegisterSideEffect::Inc,
             )), // LD A, (HL+)
+            0x2B => Instruction::Decrement(IncDecTarget::Word(Register::HL)), // DEC HL
+            0x2C => Instruction::Increment(IncDecTarget::Register(Register::L)), // INC L
+            0x2D => Instruction::Decrement(IncDecTarget::Register(Register::L)), // DEC L
             0x2E => Instruction::Load(LoadType::ImmediateByte(Register::L)),  // LD L, d8
+            0x2F => Instruction::Complement,                                  // CPL
+            0x30 => Instruction::JumpRelative(JumpCondition::NotCarry),       // JR NC, s8
             0x31 => Instruction::Load(LoadType::ImmediateWord(Register::StackPointer)), // LD SP, d16
             0x32 => Instruction::Load(LoadType::ToMemoryWithSideEffect(
                 Register::HL,
                 RegisterSideEffect::Dec,
             )), // LD (HL-), A
+            0x33 => Instruction::Increment(IncDecTarget::Word(Register::StackPointer)), // INC SP
+            0x34 => Instruction::Increment(IncDecTarget::Memory(Register::HL)),         // INC (HL)
+            0x35 => Instruction::Decrement(IncDecTarget::Memory(Register::HL)),         // DEC (HL)
             0x36 => Instruction::Load(LoadType::ImmediateByteToMemory(Register::HL)), // LD (HL), d8
+            0x37 => Instruction::SetCarryFlag,                                        // SCF
+            0x38 => Instruction::JumpRelative(JumpCondition::Carry),                  // JR C, s8
+            0x39 => Instruction::AddWord(Register::StackPointer),                     // ADD HL, SP
             0x3A => Instruction::Load(LoadType::FromMemoryWithSideEffect(
                 Register::HL,
                 RegisterSideEffect::Dec,
             )), // LD A, (HL-)
+            0x3B => Instruction::Decrement(IncDecTarget::Word(Register::StackPointer)), // DEC SP
+            0x3C => Instruction::Increment(IncDecTarget::Register(Register::A)),      // INC A
+            0x3D => Instruction::Decrement(IncDecTarget::Register(Register::A)),      // DEC A
             0x3E => Instruction::Load(LoadType::ImmediateByte(Register::A)),          // LD A, d8
+            0x3F => Instruction::ComplementCarryFlag,                                 // CCF
             0x40 => Instruction::Load(LoadType::RegToReg(Register::B, Register::B)),  // LD B, B
             0x41 => Instruction::Load(LoadType::RegToReg(Register::B, Register::C)),  // LD B, C
             0x42 => Instruction::Load(LoadType::RegToReg(Register::B, Register::D)),  // LD B, D
@@ -123,6 +218,7 @@ impl Instruction {
             0x73 => Instruction::Load(LoadType::ToMemory(Register::HL, Register::E)), // LD (HL), E
             0x74 => Instruction::Load(LoadType::ToMemory(Register::HL, Register::H)), // LD (HL), H
             0x75 => Instruction::Load(LoadType::ToMemory(Register::HL, Register::L)), // LD (HL), L
+            0x76 => Instruction::Halt,                                                // HALT
             0x77 => Instruction::Load(LoadType::ToMemory(Register::HL, Register::A)), // LD (HL), A
             0x78 => Instruction::Load(LoadType::RegToReg(Register::A, Register::B)),  // LD A, B
             0x79 => Instruction::Load(LoadType::RegToReg(Register::A, Register::C)),  // LD A, C
@@ -132,23 +228,271 @@ impl Instruction {
             0x7D => Instruction::Load(LoadType::RegToReg(Register::A, Register::L)),  // LD A, L
             0x7E => Instruction::Load(LoadType::FromMemory(Register::A, Register::HL)), // LD A, (HL)
             0x7F => Instruction::Load(LoadType::RegToReg(Register::A, Register::A)),    // LD A, A
-            0xAF => Instruction::XOR(XORTarget::Register(Register::A)),                 // XOR A
+            0x80 => Instruction::Add(ArithmeticTarget::Register(Register::B)),          // ADD A, B
+            0x81 => Instruction::Add(ArithmeticTarget::Register(Register::C)),          // ADD A, C
+            0x82 => Instruction::Add(ArithmeticTarget::Register(Register::D)),          // ADD A, D
+            0x83 => Instruction::Add(ArithmeticTarget::Register(Register::E)),          // ADD A, E
+            0x84 => Instruction::Add(ArithmeticTarget::Register(Register::H)),          // ADD A, H
+            0x85 => Instruction::Add(ArithmeticTarget::Register(Register::L)),          // ADD A, L
+            0x86 => Instruction::Add(ArithmeticTarget::Memory(Register::HL)), // ADD A, (HL)
+            0x87 => Instruction::Add(ArithmeticTarget::Register(Register::A)), // ADD A, A
+            0x88 => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::B)), // ADC A, B
+            0x89 => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::C)), // ADC A, C
+            0x8A => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::D)), // ADC A, D
+            0x8B => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::E)), // ADC A, E
+            0x8C => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::H)), // ADC A, H
+            0x8D => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::L)), // ADC A, L
+            0x8E => Instruction::AddWithCarry(ArithmeticTarget::Memory(Register::HL)), // ADC A, (HL)
+            0x8F => Instruction::AddWithCarry(ArithmeticTarget::Register(Register::A)), // ADC A, A
+            0x90 => Instruction::Subtract(ArithmeticTarget::Register(Register::B)),    // SUB B
+            0x91 => Instruction::Subtract(ArithmeticTarget::Register(Register::C)),    // SUB C
+            0x92 => Instruction::Subtract(ArithmeticTarget::Register(Register::D)),    // SUB D
+            0x93 => Instruction::Subtract(ArithmeticTarget::Register(Register::E)),    // SUB E
+            0x94 => Instruction::Subtract(ArithmeticTarget::Register(Register::H)),    // SUB H
+            0x95 => Instruction::Subtract(ArithmeticTarget::Register(Register::L)),    // SUB L
+            0x96 => Instruction::Subtract(ArithmeticTarget::Memory(Register::HL)),     // SUB (HL)
+            0x97 => Instruction::Subtract(ArithmeticTarget::Register(Register::A)),    // SUB A
+            0x98 => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::B)), // SBC A, B
+            0x99 => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::C)), // SBC A, C
+            0x9A => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::D)), // SBC A, D
+            0x9B => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::E)), // SBC A, E
+            0x9C => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::H)), // SBC A, H
+            0x9D => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::L)), // SBC A, L
+            0x9E => Instruction::SubtractWithCarry(ArithmeticTarget::Memory(Register::HL)), // SBC A, (HL)
+            0x9F => Instruction::SubtractWithCarry(ArithmeticTarget::Register(Register::A)), // SBC A, A
+            0xA0 => Instruction::And(ArithmeticTarget::Register(Register::B)), // AND B
+            0xA1 => Instruction::And(ArithmeticTarget::Register(Register::C)), // AND C
+            0xA2 => Instruction::And(ArithmeticTarget::Register(Register::D)), // AND D
+            0xA3 => Instruction::And(ArithmeticTarget::Register(Register::E)), // AND E
+            0xA4 => Instruction::And(ArithmeticTarget::Register(Register::H)), // AND H
+            0xA5 => Instruction::And(ArithmeticTarget::Register(Register::L)), // AND L
+            0xA6 => Instruction::And(ArithmeticTarget::Memory(Register::HL)),  // AND (HL)
+            0xA7 => Instruction::And(ArithmeticTarget::Register(Register::A)), // AND A
+            0xA8 => Instruction::Xor(ArithmeticTarget::Register(Register::B)), // XOR B
+            0xA9 => Instruction::Xor(ArithmeticTarget::Register(Register::C)), // XOR C
+            0xAA => Instruction::Xor(ArithmeticTarget::Register(Register::D)), // XOR D
+            0xAB => Instruction::Xor(ArithmeticTarget::Register(Register::E)), // XOR E
+            0xAC => Instruction::Xor(ArithmeticTarget::Register(Register::H)), // XOR H
+            0xAD => Instruction::Xor(ArithmeticTarget::Register(Register::L)), // XOR L
+            0xAE => Instruction::Xor(ArithmeticTarget::Memory(Register::HL)),  // XOR (HL)
+            0xAF => Instruction::Xor(ArithmeticTarget::Register(Register::A)), // XOR A
+            0xB0 => Instruction::Or(ArithmeticTarget::Register(Register::B)),  // OR B
+            0xB1 => Instruction::Or(ArithmeticTarget::Register(Register::C)),  // OR C
+            0xB2 => Instruction::Or(ArithmeticTarget::Register(Register::D)),  // OR D
+            0xB3 => Instruction::Or(ArithmeticTarget::Register(Register::E)),  // OR E
+            0xB4 => Instruction::Or(ArithmeticTarget::Register(Register::H)),  // OR H
+            0xB5 => Instruction::Or(ArithmeticTarget::Register(Register::L)),  // OR L
+            0xB6 => Instruction::Or(ArithmeticTarget::Memory(Register::HL)),   // OR (HL)
+            0xB7 => Instruction::Or(ArithmeticTarget::Register(Register::A)),  // OR A
+            0xB8 => Instruction::Compare(ArithmeticTarget::Register(Register::B)), // CP B
+            0xB9 => Instruction::Compare(ArithmeticTarget::Register(Register::C)), // CP C
+            0xBA => Instruction::Compare(ArithmeticTarget::Register(Register::D)), // CP D
+            0xBB => Instruction::Compare(ArithmeticTarget::Register(Register::E)), // CP E
+            0xBC => Instruction::Compare(ArithmeticTarget::Register(Register::H)), // CP H
+            0xBD => Instruction::Compare(ArithmeticTarget::Register(Register::L)), // CP L
+            0xBE => Instruction::Compare(ArithmeticTarget::Memory(Register::HL)), // CP (HL)
+            0xBF => Instruction::Compare(ArithmeticTarget::Register(Register::A)), // CP A
+            0xC0 => Instruction::Return(JumpCondition::NotZero),               // RET NZ
+            0xC1 => Instruction::Pop(Register::BC),                            // POP BC
+            0xC2 => Instruction::Jump(JumpCondition::NotZero),                 // JP NZ, a16
+            0xC3 => Instruction::Jump(JumpCondition::Always),                  // JP a16
+            0xC4 => Instruction::Call(JumpCondition::NotZero),                 // CALL NZ, a16
+            0xC5 => Instruction::Push(Register::BC),                           // PUSH BC
+            0xC6 => Instruction::Add(ArithmeticTarget::Immediate),             // ADD A, d8
+            0xC7 => Instruction::Restart(0x00),                                // RST 00H
+            0xC8 => Instruction::Return(JumpCondition::Zero),                  // RET Z
+            0xC9 => Instruction::Return(JumpCondition::Always),                // RET
+            0xCA => Instruction::Jump(JumpCondition::Zero),                    // JP Z, a16
             0xCB => Instruction::Prefixed, // Any instruction that starts 0xCB
-            _ => Instruction::NoOp,        // Not implemented
+            0xCC => Instruction::Call(JumpCondition::Zero), // CALL Z, a16
+            0xCD => Instruction::Call(JumpCondition::Always), // CALL a16
+            0xCE => Instruction::AddWithCarry(ArithmeticTarget::Immediate), // ADC A, d8
+            0xCF => Instruction::Restart(0x08), // RST 08H
+            0xD0 => Instruction::Return(JumpCondition::NotCarry), // RET NC
+            0xD1 => Instruction::Pop(Register::DE), // POP DE
+            0xD2 => Instruction::Jump(JumpCondition::NotCarry), // JP NC, a16
+            0xD4 => Instruction::Call(JumpCondition::NotCarry), // CALL NC, a16
+            0xD5 => Instruction::Push(Register::DE), // PUSH DE
+            0xD6 => Instruction::Subtract(ArithmeticTarget::Immediate), // SUB d8
+            0xD7 => Instruction::Restart(0x10), // RST 10H
+            0xD8 => Instruction::Return(JumpCondition::Carry), // RET C
+            0xD9 => Instruction::ReturnFromInterrupt, // RETI
+            0xDA => Instruction::Jump(JumpCondition::Carry), // JP C, a16
+            0xDC => Instruction::Call(JumpCondition::Carry), // CALL C, a16
+            0xDE => Instruction::SubtractWithCarry(ArithmeticTarget::Immediate), // SBC A, d8
+            0xDF => Instruction::Restart(0x18), // RST 18H
+            0xE0 => Instruction::Load(LoadType::ToHighMemoryImmediate), // LD (a8), A
+            0xE1 => Instruction::Pop(Register::HL), // POP HL
+            0xE2 => Instruction::Load(LoadType::ToHighMemory(Register::C)), // LD (C), A
+            0xE5 => Instruction::Push(Register::HL), // PUSH HL
+            0xE6 => Instruction::And(ArithmeticTarget::Immediate), // AND d8
+            0xE7 => Instruction::Restart(0x20), // RST 20H
+            0xE8 => Instruction::AddStackPointer, // ADD SP, s8
+            0xE9 => Instruction::JumpToHL, // JP HL
+            0xEA => Instruction::Load(LoadType::ToImmediateAddress), // LD (a16), A
+            0xEE => Instruction::Xor(ArithmeticTarget::Immediate), // XOR d8
+            0xEF => Instruction::Restart(0x28), // RST 28H
+            0xF0 => Instruction::Load(LoadType::FromHighMemoryImmediate), // LD A, (a8)
+            0xF1 => Instruction::Pop(Register::AF), // POP AF
+            0xF2 => Instruction::Load(LoadType::FromHighMemory(Register::C)), // LD A, (C)
+            0xF3 => Instruction::DisableInterrupts, // DI
+            0xF5 => Instruction::Push(Register::AF), // PUSH AF
+            0xF6 => Instruction::Or(ArithmeticTarget::Immediate), // OR d8
+            0xF7 => Instruction::Restart(0x30), // RST 30H
+            0xF8 => Instruction::Load(LoadType::StackPointerOffsetToHL), // LD HL, SP+s8
+            0xF9 => Instruction::Load(LoadType::HLToStackPointer), // LD SP, HL
+            0xFA => Instruction::Load(LoadType::FromImmediateAddress), // LD A, (a16)
+            0xFB => Instruction::EnableInterrupts, // EI
+            0xFE => Instruction::Compare(ArithmeticTarget::Immediate), // CP d8
+            0xFF => Instruction::Restart(0x38), // RST 38H
+            _ => Instruction::Illegal,     // Locks up the CPU on hardware
         }
     }
 }
 
+impl Instruction {
+    // Number of M-cycles taken, including the opcode fetch. Conditional branches report their
+    // not-taken timing here, as the branch itself adds the cost of a taken branch and prefixed
+    // instructions are timed by PrefixedInstruction::cycles.
+    pub fn cycles(&self) -> u8 {
+        match self {
+            Instruction::Load(load_type) => match load_type {
+                LoadType::ImmediateWord(_) => 3,
+                LoadType::ImmediateByte(_) => 2,
+                LoadType::RegToReg(..) => 1,
+                LoadType::ImmediateByteToMemory(_) => 3,
+                LoadType::ToMemory(..)
+                | LoadType::ToMemoryWithSideEffect(..)
+                | LoadType::FromMemory(..)
+                | LoadType::FromMemoryWithSideEffect(..) => 2,
+                LoadType::StackPointerToMemory => 5,
+                LoadType::ToImmediateAddress | LoadType::FromImmediateAddress => 4,
+                LoadType::ToHighMemoryImmediate | LoadType::FromHighMemoryImmediate => 3,
+                LoadType::ToHighMemory(_) | LoadType::FromHighMemory(_) => 2,
+                LoadType::HLToStackPointer => 2,
+                LoadType::StackPointerOffsetToHL => 3,
+            },
+            Instruction::Add(target)
+            | Instruction::AddWithCarry(target)
+            | Instruction::Subtract(target)
+            | Instruction::SubtractWithCarry(target)
+            | Instruction::And(target)
+            | Instruction::Xor(target)
+            | Instruction::Or(target)
+            | Instruction::Compare(target) => match target {
+                ArithmeticTarget::Register(_) => 1,
+                ArithmeticTarget::Memory(_) | ArithmeticTarget::Immediate => 2,
+            },
+            Instruction::Increment(target) | Instruction::Decrement(target) => match target {
+                IncDecTarget::Register(_) => 1,
+                IncDecTarget::Memory(_) => 3,
+                IncDecTarget::Word(_) => 2,
+            },
+            Instruction::AddWord(_) => 2,
+            Instruction::AddStackPointer => 4,
+            Instruction::Push(_) => 4,
+            Instruction::Pop(_) => 3,
+            Instruction::Jump(JumpCondition::Always) => 4,
+            Instruction::Jump(_) => 3,
+            Instruction::JumpToHL => 1,
+            Instruction::JumpRelative(JumpCondition::Always) => 3,
+            Instruction::JumpRelative(_) => 2,
+            Instruction::Call(JumpCondition::Always) => 6,
+            Instruction::Call(_) => 3,
+            Instruction::Return(JumpCondition::Always) => 4,
+            Instruction::Return(_) => 2,
+            Instruction::ReturnFromInterrupt => 4,
+            Instruction::Restart(_) => 4,
+            Instruction::RotateLeftCircularA
+            | Instruction::RotateRightCircularA
+            | Instruction::RotateLeftA
+            | Instruction::RotateRightA
+            | Instruction::DecimalAdjust
+            | Instruction::Complement
+            | Instruction::SetCarryFlag
+            | Instruction::ComplementCarryFlag
+            | Instruction::DisableInterrupts
+            | Instruction::EnableInterrupts
+            | Instruction::Halt
+            | Instruction::Stop
+            | Instruction::Prefixed
+            | Instruction::NoOp
+            | Instruction::Illegal => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum PrefixedTarget {
+    Register(Register),
+    Memory(Register),
+}
+
 pub enum PrefixedInstruction {
-    Bit(u8, Register),
-    NoOp,
+    RotateLeftCircular(PrefixedTarget),
+    RotateRightCircular(PrefixedTarget),
+    RotateLeft(PrefixedTarget),
+    RotateRight(PrefixedTarget),
+    ShiftLeftArithmetic(PrefixedTarget),
+    ShiftRightArithmetic(PrefixedTarget),
+    Swap(PrefixedTarget),
+    ShiftRightLogical(PrefixedTarget),
+    Bit(u8, PrefixedTarget),
+    Reset(u8, PrefixedTarget),
+    Set(u8, PrefixedTarget),
 }
 
 impl PrefixedInstruction {
     pub fn decode(opcode: u8) -> Self {
-        match opcode {
-            0x7C => PrefixedInstruction::Bit(7, Register::H),
-            _ => PrefixedInstruction::NoOp,
+        // The low three bits select the operand and, for BIT/RES/SET, bits 3-5 select the bit
+        let target = match opcode & 0x07 {
+            0x00 => PrefixedTarget::Register(Register::B),
+            0x01 => PrefixedTarget::Register(Register::C),
+            0x02 => PrefixedTarget::Register(Register::D),
+            0x03 => PrefixedTarget::Register(Register::E),
+            0x04 => PrefixedTarget::Register(Register::H),
+            0x05 => PrefixedTarget::Register(Register::L),
+            0x06 => PrefixedTarget::Memory(Register::HL),
+            _ => PrefixedTarget::Register(Register::A),
+        };
+        let index = (opcode >> 3) & 0x07;
+
+        match opcode >> 3 {
+            0x00 => PrefixedInstruction::RotateLeftCircular(target), // RLC
+            0x01 => PrefixedInstruction::RotateRightCircular(target), // RRC
+            0x02 => PrefixedInstruction::RotateLeft(target),         // RL
+            0x03 => PrefixedInstruction::RotateRight(target),        // RR
+            0x04 => PrefixedInstruction::ShiftLeftArithmetic(target), // SLA
+            0x05 => PrefixedInstruction::ShiftRightArithmetic(target), // SRA
+            0x06 => PrefixedInstruction::Swap(target),               // SWAP
+            0x07 => PrefixedInstruction::ShiftRightLogical(target),  // SRL
+            0x08..=0x0F => PrefixedInstruction::Bit(index, target),  // BIT n
+            0x10..=0x17 => PrefixedInstruction::Reset(index, target), // RES n
+            _ => PrefixedInstruction::Set(index, target),            // SET n
+        }
+    }
+
+    // Number of M-cycles taken, including the fetch of the 0xCB prefix. The (HL) forms pay for
+    // an extra memory read, plus a write back for everything other than BIT.
+    pub fn cycles(&self) -> u8 {
+        let target = match self {
+            PrefixedInstruction::RotateLeftCircular(target)
+            | PrefixedInstruction::RotateRightCircular(target)
+            | PrefixedInstruction::RotateLeft(target)
+            | PrefixedInstruction::RotateRight(target)
+            | PrefixedInstruction::ShiftLeftArithmetic(target)
+            | PrefixedInstruction::ShiftRightArithmetic(target)
+            | PrefixedInstruction::Swap(target)
+            | PrefixedInstruction::ShiftRightLogical(target)
+            | PrefixedInstruction::Bit(_, target)
+            | PrefixedInstruction::Reset(_, target)
+            | PrefixedInstruction::Set(_, target) => target,
+        };
+
+        match (self, target) {
+            (PrefixedInstruction::Bit(..), PrefixedTarget::Memory(_)) => 3,
+            (_, PrefixedTarget::Memory(_)) => 4,
+            _ => 2,
         }
     }
 }
@@ -0,0 +1,131 @@
+pub const INTERRUPT_FLAG: u16 = 0xFF0F;
+pub const INTERRUPT_ENABLE: u16 = 0xFFFF;
+
+// In priority order, highest first. The discriminant is the bit used in IE and IF.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interrupt {
+    VBlank = 0,
+    LcdStat = 1,
+    Timer = 2,
+    Serial = 3,
+    Joypad = 4,
+}
+
+const INTERRUPTS: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::LcdStat,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Joypad,
+];
+
+impl Interrupt {
+    fn bitmask(self) -> u8 {
+        1 << self as u8
+    }
+
+    // Address the CPU jumps to when servicing the interrupt: 0x40, 0x48, 0x50, 0x58 or 0x60
+    pub fn vector(self) -> u16 {
+        0x40 + 8 * self as u16
+    }
+}
+
+pub struct InterruptController {
+    enabled: u8,
+    requested: u8,
+}
+
+impl InterruptController {
+    pub fn new() -> Self {
+        InterruptController {
+            enabled: 0,
+            requested: 0,
+        }
+    }
+
+    // IE is a full 8-bit register even though only the lower 5 bits do anything
+    pub fn read_enabled(&self) -> u8 {
+        self.enabled
+    }
+
+    pub fn write_enabled(&mut self, value: u8) {
+        self.enabled = value;
+    }
+
+    // The upper 3 bits of IF are unused and always read as 1
+    pub fn read_requested(&self) -> u8 {
+        self.requested | 0xE0
+    }
+
+    pub fn write_requested(&mut self, value: u8) {
+        self.requested = value & 0x1F;
+    }
+
+    pub fn request(&mut self, interrupt: Interrupt) {
+        self.requested |= interrupt.bitmask();
+    }
+
+    pub fn acknowledge(&mut self, interrupt: Interrupt) {
+        self.requested &= !interrupt.bitmask();
+    }
+
+    // Highest priority interrupt that is both requested and enabled, if any
+    pub fn pending(&self) -> Option<Interrupt> {
+        let pending = self.enabled & self.requested;
+
+        INTERRUPTS
+            .iter()
+            .copied()
+            .find(|interrupt| pending & interrupt.bitmask() != 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectors() {
+        assert_eq!(Interrupt::VBlank.vector(), 0x40);
+        assert_eq!(Interrupt::LcdStat.vector(), 0x48);
+        assert_eq!(Interrupt::Timer.vector(), 0x50);
+        assert_eq!(Interrupt::Serial.vector(), 0x58);
+        assert_eq!(Interrupt::Joypad.vector(), 0x60);
+    }
+
+    #[test]
+    fn pending_needs_request_and_enable() {
+        let mut controller = InterruptController::new();
+        controller.request(Interrupt::Timer);
+        assert_eq!(controller.pending(), None);
+
+        controller.write_enabled(0x04);
+        assert_eq!(controller.pending(), Some(Interrupt::Timer));
+
+        controller.acknowledge(Interrupt::Timer);
+        assert_eq!(controller.pending(), None);
+    }
+
+    #[test]
+    fn pending_respects_priority() {
+        let mut controller = InterruptController::new();
+        controller.write_enabled(0x1F);
+        controller.request(Interrupt::Joypad);
+        controller.request(Interrupt::LcdStat);
+        controller.request(Interrupt::Serial);
+
+        assert_eq!(controller.pending(), Some(Interrupt::LcdStat));
+    }
+
+    #[test]
+    fn unused_flag_bits_read_as_set() {
+        let mut controller = InterruptController::new();
+        assert_eq!(controller.read_requested(), 0xE0);
+
+        controller.write_requested(0xFF);
+        assert_eq!(controller.read_requested(), 0xFF);
+
+        controller.write_enabled(0xFF);
+        assert_eq!(controller.read_enabled(), 0xFF);
+    }
+}
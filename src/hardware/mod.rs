@@ -1,5 +1,26 @@
+mod alu;
+mod apu;
+mod bus;
+mod cartridge;
 mod cpu;
+mod dma;
+mod hdma;
 mod instructions;
+mod interrupts;
+mod model;
+mod ppu;
 mod registers;
+#[cfg(test)]
+mod test_roms;
+mod timer;
 
-pub use cpu::CPU;
+pub use apu::{Apu, SAMPLE_RATE};
+pub use bus::{BootRomSizeError, Bus, MemoryMapped, Ram};
+pub use cartridge::{
+    Cartridge, CartridgeError, CartridgeHeader, CartridgeType, CgbSupport, Clock, ImageSource,
+    Licensee, Mapper, StaticImage, SystemClock, CAMERA_HEIGHT, CAMERA_WIDTH,
+};
+pub use cpu::{CLOCK_SPEED, CPU, CYCLES_PER_FRAME};
+pub use interrupts::Interrupt;
+pub use model::Model;
+pub use ppu::{rgb888, Mode, Ppu, Renderer, SCREEN_HEIGHT, SCREEN_WIDTH};
@@ -0,0 +1,132 @@
+use std::str::FromStr;
+
+// The Game Boy models we can emulate. They run the same CPU, but each boot ROM leaves the
+// registers in a different state, which some games check to work out what they're running on.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Model {
+    // Original Game Boy
+    Dmg,
+    // Game Boy Pocket
+    Mgb,
+    // Super Game Boy
+    Sgb,
+    // Game Boy Color
+    Cgb,
+    // Game Boy Advance, which runs Game Boy games on CGB hardware
+    Agb,
+}
+
+// Values left in the I/O registers by the DMG boot ROM. DIV depends on exactly how long the boot
+// ROM ran for and DMA isn't written, as writing it would start a transfer.
+const DMG_IO_REGISTERS: [(u16, u8); 35] = [
+    (0xFF00, 0xCF), // P1
+    (0xFF01, 0x00), // SB
+    (0xFF02, 0x7E), // SC
+    (0xFF04, 0xAB), // DIV
+    (0xFF05, 0x00), // TIMA
+    (0xFF06, 0x00), // TMA
+    (0xFF07, 0xF8), // TAC
+    (0xFF0F, 0xE1), // IF
+    (0xFF10, 0x80), // NR10
+    (0xFF11, 0xBF), // NR11
+    (0xFF12, 0xF3), // NR12
+    (0xFF13, 0xFF), // NR13
+    (0xFF14, 0xBF), // NR14
+    (0xFF16, 0x3F), // NR21
+    (0xFF17, 0x00), // NR22
+    (0xFF18, 0xFF), // NR23
+    (0xFF19, 0xBF), // NR24
+    (0xFF1A, 0x7F), // NR30
+    (0xFF1B, 0xFF), // NR31
+    (0xFF1C, 0x9F), // NR32
+    (0xFF1D, 0xFF), // NR33
+    (0xFF1E, 0xBF), // NR34
+    (0xFF20, 0xFF), // NR41
+    (0xFF21, 0x00), // NR42
+    (0xFF22, 0x00), // NR43
+    (0xFF23, 0xBF), // NR44
+    (0xFF24, 0x77), // NR50
+    (0xFF25, 0xF3), // NR51
+    (0xFF26, 0xF1), // NR52
+    (0xFF40, 0x91), // LCDC
+    (0xFF41, 0x85), // STAT
+    (0xFF42, 0x00), // SCY
+    (0xFF43, 0x00), // SCX
+    (0xFF45, 0x00), // LYC
+    (0xFF47, 0xFC), // BGP
+];
+
+impl Model {
+    // Models with the CGB's extra hardware, which is only switched on for CGB games
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    pub fn boot_rom_size(self) -> usize {
+        if self.is_cgb() {
+            0x0900
+        } else {
+            0x0100
+        }
+    }
+
+    // Name the boot ROM for this model is usually dumped under
+    pub fn boot_rom_file_name(self) -> &'static str {
+        match self {
+            Model::Dmg => "dmg_boot.bin",
+            Model::Mgb => "mgb_boot.bin",
+            Model::Sgb => "sgb_boot.bin",
+            Model::Cgb => "cgb_boot.bin",
+            Model::Agb => "agb_boot.bin",
+        }
+    }
+
+    // AF, BC, DE and HL as the boot ROM leaves them. Games tell the models apart by these: A is
+    // 0x11 on CGB hardware, and bit 0 of B is set on the AGB. On the DMG and MGB the half-carry
+    // and carry flags are only set if the cartridge header checksum is non-zero.
+    //
+    // When a CGB runs a DMG game, DE and HL depend on the game's title. The values here are the
+    // ones left for most games.
+    pub fn post_boot_registers(self, header_checksum: u8, cgb_mode: bool) -> [u16; 4] {
+        let flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+
+        match (self, cgb_mode) {
+            (Model::Dmg, _) => [0x0100 | flags, 0x0013, 0x00D8, 0x014D],
+            (Model::Mgb, _) => [0xFF00 | flags, 0x0013, 0x00D8, 0x014D],
+            (Model::Sgb, _) => [0x0100, 0x0014, 0x0000, 0xC060],
+            (Model::Cgb, true) => [0x1180, 0x0000, 0xFF56, 0x000D],
+            (Model::Cgb, false) => [0x1180, 0x0000, 0x0008, 0x007C],
+            (Model::Agb, true) => [0x1100, 0x0100, 0xFF56, 0x000D],
+            (Model::Agb, false) => [0x1100, 0x0100, 0x0008, 0x007C],
+        }
+    }
+
+    // I/O registers as the boot ROM leaves them, as (address, value) pairs
+    pub fn post_boot_io_registers(self) -> Vec<(u16, u8)> {
+        let mut registers = DMG_IO_REGISTERS.to_vec();
+
+        match self {
+            Model::Dmg | Model::Mgb => (),
+            // The SGB boot ROM leaves sound switched off
+            Model::Sgb => registers.push((0xFF26, 0xF0)),
+            Model::Cgb | Model::Agb => registers.push((0xFF02, 0x7F)),
+        }
+
+        registers
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "cgb" => Ok(Model::Cgb),
+            "agb" => Ok(Model::Agb),
+            _ => Err(format!("unknown model {}", name)),
+        }
+    }
+}
@@ -0,0 +1,398 @@
+use std::collections::VecDeque;
+
+use super::{
+    BgPixel, Object, ObjectPixel, Ppu, BG_TILE_MAP, OBJ_ENABLE, SCREEN_WIDTH, WINDOW_ENABLE,
+    WINDOW_TILE_MAP,
+};
+
+// The first tile of a line is fetched twice, holding up the first pixel by one fetch
+const STARTUP_DOTS: u8 = 6;
+
+// Fetching 8 pixels takes 2 dots each to read the tile number and its two bitplanes
+const FETCH_DOTS: u8 = 6;
+
+// State of mode 3 when drawn a pixel at a time. The fetcher reads background or window tiles into
+// the background FIFO, and one pixel leaves it each dot, mixed with any object pixel at the front
+// of the object FIFO. Anything that stalls the pixels makes mode 3 longer:
+//
+// - SCX mod 8 pixels are thrown away at the start of the line
+// - Starting the window empties the background FIFO and restarts the fetcher, costing 6 dots
+// - Each object costs 6 dots to fetch, plus up to 5 waiting for a background fetch to finish
+pub(super) struct PixelFifo {
+    bg: VecDeque<BgPixel>,
+    obj: VecDeque<ObjectPixel>,
+    startup: u8,
+    // Dots into the current background fetch
+    fetch_dots: u8,
+    // Tile column being fetched, counting from the left of the screen or window
+    fetch_x: u8,
+    // Map entry and row of the tile being fetched, read in its first step
+    fetch_tile: ((usize, u8), u8),
+    // A fetched tile waiting for the background FIFO to empty
+    fetched: Option<[BgPixel; 8]>,
+    // Pixels to throw away before drawing: SCX mod 8 at the start of the line, or the part of the
+    // window left of the screen when WX < 7
+    discard: u8,
+    // Next pixel on the line to be drawn
+    x: u8,
+    in_window: bool,
+    // Index into the line's objects of the next one to fetch
+    next_object: usize,
+    fetching_object: Option<Object>,
+    // Dots left until the object being fetched is mixed into the object FIFO
+    object_stall: u8,
+}
+
+impl PixelFifo {
+    pub(super) fn new() -> Self {
+        PixelFifo {
+            bg: VecDeque::with_capacity(16),
+            obj: VecDeque::with_capacity(8),
+            startup: 0,
+            fetch_dots: 0,
+            fetch_x: 0,
+            fetch_tile: ((0, 0), 0),
+            fetched: None,
+            discard: 0,
+            x: 0,
+            in_window: false,
+            next_object: 0,
+            fetching_object: None,
+            object_stall: 0,
+        }
+    }
+
+    pub(super) fn start_line(&mut self, scroll_x: u8) {
+        *self = PixelFifo {
+            startup: STARTUP_DOTS,
+            discard: scroll_x % 8,
+            ..PixelFifo::new()
+        };
+    }
+
+    fn restart_fetch(&mut self) {
+        self.fetch_dots = 0;
+        self.fetch_x = 0;
+        self.fetched = None;
+    }
+
+    // How many more dots until the current background fetch is done
+    fn fetch_remaining(&self) -> u8 {
+        match self.fetched {
+            Some(_) => 0,
+            None => FETCH_DOTS - 1 - self.fetch_dots.min(FETCH_DOTS - 1),
+        }
+    }
+}
+
+impl Ppu {
+    // Runs one dot of mode 3, returning true once the last pixel of the line has been drawn
+    pub(super) fn step_fifo(&mut self) -> bool {
+        if self.pixel_fifo.startup > 0 {
+            self.pixel_fifo.startup -= 1;
+            return false;
+        }
+
+        if self.pixel_fifo.object_stall > 0 {
+            // The background fetch carries on until it's done, then waits while the object is
+            // fetched
+            if self.pixel_fifo.object_stall >= FETCH_DOTS {
+                self.step_fetcher();
+            }
+            self.pixel_fifo.object_stall -= 1;
+            if self.pixel_fifo.object_stall == 0 {
+                self.mix_object();
+            }
+            return false;
+        }
+
+        self.step_fetcher();
+        if self.pixel_fifo.bg.is_empty() {
+            return false;
+        }
+
+        if self.pixel_fifo.discard > 0 {
+            self.pixel_fifo.discard -= 1;
+            self.pixel_fifo.bg.pop_front();
+            return false;
+        }
+
+        if self.window_starts_here() {
+            self.pixel_fifo.in_window = true;
+            self.pixel_fifo.bg.clear();
+            self.pixel_fifo.restart_fetch();
+            self.pixel_fifo.discard = 7u8.saturating_sub(self.window_x);
+            self.step_fetcher();
+            return false;
+        }
+
+        if self.object_starts_here() {
+            return false;
+        }
+
+        self.draw_pixel();
+        if self.pixel_fifo.x as usize == SCREEN_WIDTH {
+            if self.pixel_fifo.in_window {
+                self.window_line += 1;
+            }
+            return true;
+        }
+        false
+    }
+
+    fn step_fetcher(&mut self) {
+        if let Some(pixels) = self.pixel_fifo.fetched {
+            // A fetched tile can only be pushed once the background FIFO has emptied
+            if self.pixel_fifo.bg.is_empty() {
+                self.pixel_fifo.bg.extend(pixels);
+                self.pixel_fifo.fetched = None;
+                self.pixel_fifo.fetch_dots = 0;
+                self.pixel_fifo.fetch_x = self.pixel_fifo.fetch_x.wrapping_add(1);
+            }
+            return;
+        }
+
+        self.pixel_fifo.fetch_dots += 1;
+        match self.pixel_fifo.fetch_dots {
+            // The tile number and attributes are read from the map using the scroll registers as
+            // they are now
+            2 => {
+                let tile_x = self.pixel_fifo.fetch_x.wrapping_mul(8);
+                self.pixel_fifo.fetch_tile = if self.pixel_fifo.in_window {
+                    let y = self.window_line;
+                    (self.map_entry(WINDOW_TILE_MAP, tile_x, y), y % 8)
+                } else {
+                    let x = (self.scroll_x & !7).wrapping_add(tile_x);
+                    let y = self.scroll_y.wrapping_add(self.line);
+                    (self.map_entry(BG_TILE_MAP, x, y), y % 8)
+                };
+            }
+            FETCH_DOTS => {
+                let (entry, row) = self.pixel_fifo.fetch_tile;
+                self.pixel_fifo.fetched = Some(self.bg_row(entry, row));
+            }
+            _ => (),
+        }
+    }
+
+    fn window_starts_here(&self) -> bool {
+        self.lcd_control & WINDOW_ENABLE != 0
+            && self.window_y_reached
+            && !self.pixel_fifo.in_window
+            && self.window_x <= 166
+            && self.pixel_fifo.x + 7 >= self.window_x
+    }
+
+    // Starts fetching the next object if it begins at the current pixel
+    fn object_starts_here(&mut self) -> bool {
+        if self.lcd_control & OBJ_ENABLE == 0 {
+            return false;
+        }
+
+        let Some(&object) = self.line_objects.get(self.pixel_fifo.next_object) else {
+            return false;
+        };
+        if object.x > self.pixel_fifo.x + 8 {
+            return false;
+        }
+
+        // This dot is the first of the stall
+        let penalty = FETCH_DOTS + self.pixel_fifo.fetch_remaining();
+        self.pixel_fifo.next_object += 1;
+        self.pixel_fifo.fetching_object = Some(object);
+        self.pixel_fifo.object_stall = penalty - 1;
+        true
+    }
+
+    // Objects are fetched in X order, so pixels already in the object FIFO have priority on the
+    // DMG and only transparent ones are replaced. In CGB mode an object earlier in OAM takes over
+    // from one later in OAM.
+    fn mix_object(&mut self) {
+        let Some(object) = self.pixel_fifo.fetching_object.take() else {
+            return;
+        };
+
+        let pixels = self.object_row(object);
+        let oam_priority = self.oam_priority();
+        // Objects hanging off the left of the screen start partway through
+        let skip = (self.pixel_fifo.x as usize + 8 - object.x as usize).min(8);
+
+        let fifo = &mut self.pixel_fifo.obj;
+        fifo.resize(8, ObjectPixel::default());
+        for (slot, &pixel) in fifo.iter_mut().zip(&pixels[skip..]) {
+            let takes_over =
+                slot.colour == 0 || (oam_priority && pixel.colour != 0 && pixel.index < slot.index);
+            if takes_over {
+                *slot = pixel;
+            }
+        }
+    }
+
+    fn draw_pixel(&mut self) {
+        let bg_pixel = self.pixel_fifo.bg.pop_front().unwrap_or_default();
+        let bg_pixel = if self.bg_visible() {
+            bg_pixel
+        } else {
+            BgPixel::default()
+        };
+        let object_pixel = self.pixel_fifo.obj.pop_front();
+
+        let x = self.pixel_fifo.x as usize;
+        self.framebuffer[self.line as usize * SCREEN_WIDTH + x] =
+            self.pixel_colour(bg_pixel, object_pixel);
+        self.pixel_fifo.x += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        Mode, Renderer, BGP, BG_ENABLE, DMG_SHADES, LCDC, LCD_ENABLE, SCX, TILE_DATA, WX,
+    };
+    use super::*;
+    use crate::hardware::{bus::MemoryMapped, model::Model};
+
+    // Number of dots mode 3 takes on line 1, the first with an OAM scan, with the given setup done
+    // before the LCD goes on
+    fn drawing_length(setup: impl Fn(&mut Ppu)) -> u32 {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Fifo);
+        setup(&mut ppu);
+        ppu.write(LCDC, ppu.read(LCDC) | LCD_ENABLE);
+        ppu.tick(456 + 80);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+
+        let mut dots = 0;
+        while ppu.mode() == Mode::Drawing {
+            ppu.tick(1);
+            dots += 1;
+        }
+        dots
+    }
+
+    fn set_object_x(ppu: &mut Ppu, index: u16, x: u8) {
+        ppu.write(0xFE00 + index * 4, 16);
+        ppu.write(0xFE01 + index * 4, x);
+    }
+
+    #[test]
+    fn drawing_takes_172_dots_at_least() {
+        assert_eq!(drawing_length(|_| ()), 172);
+    }
+
+    #[test]
+    fn fetcher_only_pushes_into_an_empty_fifo() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Fifo);
+        ppu.write(LCDC, ppu.read(LCDC) | LCD_ENABLE);
+        ppu.tick(80);
+
+        while ppu.mode() == Mode::Drawing {
+            ppu.tick(1);
+            assert!(ppu.pixel_fifo.bg.len() <= 8);
+        }
+    }
+
+    #[test]
+    fn fine_scroll_lengthens_drawing() {
+        for scroll_x in 0..8 {
+            let length = drawing_length(|ppu| ppu.write(SCX, scroll_x));
+            assert_eq!(length, 172 + scroll_x as u32);
+        }
+
+        // Only the fine scroll matters
+        assert_eq!(drawing_length(|ppu| ppu.write(SCX, 0x13)), 175);
+    }
+
+    #[test]
+    fn window_costs_six_dots() {
+        for window_x in [7, 50, 166] {
+            let length = drawing_length(|ppu| {
+                ppu.write(WX, window_x);
+                ppu.write(LCDC, WINDOW_ENABLE);
+            });
+            assert_eq!(length, 178, "WX = {}", window_x);
+        }
+
+        // Off the right of the screen, so never started
+        let length = drawing_length(|ppu| {
+            ppu.write(WX, 167);
+            ppu.write(LCDC, WINDOW_ENABLE);
+        });
+        assert_eq!(length, 172);
+    }
+
+    #[test]
+    fn objects_cost_six_to_eleven_dots() {
+        // An object at the start of a tile waits the longest for the background fetch
+        let length = drawing_length(|ppu| {
+            set_object_x(ppu, 0, 8);
+            ppu.write(LCDC, OBJ_ENABLE);
+        });
+        assert_eq!(length, 183);
+
+        // A second object at the same place finds the fetch already done
+        let length = drawing_length(|ppu| {
+            set_object_x(ppu, 0, 8);
+            set_object_x(ppu, 1, 8);
+            ppu.write(LCDC, OBJ_ENABLE);
+        });
+        assert_eq!(length, 189);
+
+        let length = drawing_length(|ppu| {
+            set_object_x(ppu, 0, 8);
+            ppu.write(LCDC, OBJ_ENABLE);
+        });
+        let later = drawing_length(|ppu| {
+            set_object_x(ppu, 0, 13);
+            ppu.write(LCDC, OBJ_ENABLE);
+        });
+        assert!(later < length);
+        assert!(later >= 178);
+
+        // Disabled objects aren't fetched
+        assert_eq!(drawing_length(|ppu| set_object_x(ppu, 0, 8)), 172);
+    }
+
+    #[test]
+    fn palette_writes_apply_partway_through_a_line() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Fifo);
+        for address in 0x8010..0x8020 {
+            ppu.write(address, 0xFF);
+        }
+        for address in 0x9800..0x9820 {
+            ppu.write(address, 1);
+        }
+        ppu.write(BGP, 0xFF);
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE);
+
+        // The first pixel comes out 13 dots into mode 3, then one a dot
+        ppu.tick(80 + 13 + 50);
+        ppu.write(BGP, 0x00);
+        ppu.tick(456);
+
+        assert_eq!(ppu.framebuffer()[50], DMG_SHADES[3]);
+        assert_eq!(ppu.framebuffer()[51], DMG_SHADES[0]);
+    }
+
+    #[test]
+    fn scroll_writes_apply_to_later_tiles() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Fifo);
+        for address in 0x8010..0x8020 {
+            ppu.write(address, 0xFF);
+        }
+        // Tiles 16 onwards in the map are filled
+        for address in 0x9810..0x9820 {
+            ppu.write(address, 1);
+        }
+        ppu.write(BGP, 0xE4);
+        ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE);
+
+        // Scrolling 8 tiles across halfway along the line only moves the rest of it
+        ppu.tick(80 + 13 + 80);
+        ppu.write(SCX, 64);
+        ppu.tick(456);
+
+        assert_eq!(ppu.framebuffer()[0], DMG_SHADES[0]);
+        assert_eq!(ppu.framebuffer()[79], DMG_SHADES[0]);
+        assert_eq!(ppu.framebuffer()[159], DMG_SHADES[3]);
+    }
+}
@@ -0,0 +1,1284 @@
+mod fifo;
+mod palette;
+mod scanline;
+
+use std::str::FromStr;
+
+use super::{bus::MemoryMapped, interrupts::Interrupt, model::Model};
+
+use self::{fifo::PixelFifo, palette::ColourPalettes};
+
+pub use self::palette::rgb888;
+
+pub const SCREEN_WIDTH: usize = 160;
+pub const SCREEN_HEIGHT: usize = 144;
+
+pub const LCDC: u16 = 0xFF40;
+pub const STAT: u16 = 0xFF41;
+pub const SCY: u16 = 0xFF42;
+pub const SCX: u16 = 0xFF43;
+pub const LY: u16 = 0xFF44;
+pub const LYC: u16 = 0xFF45;
+pub const BGP: u16 = 0xFF47;
+pub const OBP0: u16 = 0xFF48;
+pub const OBP1: u16 = 0xFF49;
+pub const WY: u16 = 0xFF4A;
+pub const WX: u16 = 0xFF4B;
+
+// CGB only
+pub const VBK: u16 = 0xFF4F;
+pub const BCPS: u16 = 0xFF68;
+pub const BCPD: u16 = 0xFF69;
+pub const OCPS: u16 = 0xFF6A;
+pub const OCPD: u16 = 0xFF6B;
+pub const OPRI: u16 = 0xFF6C;
+
+// LCDC bits
+const BG_ENABLE: u8 = 0x01;
+const OBJ_ENABLE: u8 = 0x02;
+const OBJ_SIZE: u8 = 0x04;
+const BG_TILE_MAP: u8 = 0x08;
+const TILE_DATA: u8 = 0x10;
+const WINDOW_ENABLE: u8 = 0x20;
+const WINDOW_TILE_MAP: u8 = 0x40;
+const LCD_ENABLE: u8 = 0x80;
+
+// STAT interrupt select bits
+const STAT_HBLANK: u8 = 0x08;
+const STAT_VBLANK: u8 = 0x10;
+const STAT_OAM_SCAN: u8 = 0x20;
+const STAT_LINE_COMPARE: u8 = 0x40;
+
+// Object attribute flags
+const OBJ_BEHIND_BG: u8 = 0x80;
+const OBJ_Y_FLIP: u8 = 0x40;
+const OBJ_X_FLIP: u8 = 0x20;
+const OBJ_PALETTE: u8 = 0x10;
+const OBJ_BANK: u8 = 0x08;
+const OBJ_CGB_PALETTE: u8 = 0x07;
+
+// Background map attribute bits, kept in VRAM bank 1 at the same place as the tile numbers
+const ATTR_PRIORITY: u8 = 0x80;
+const ATTR_Y_FLIP: u8 = 0x40;
+const ATTR_X_FLIP: u8 = 0x20;
+const ATTR_BANK: u8 = 0x08;
+const ATTR_PALETTE: u8 = 0x07;
+
+// The 4 DMG shades as RGB555, from white to black
+const DMG_SHADES: [u16; 4] = [0x7FFF, 0x56B5, 0x294A, 0x0000];
+
+const DOTS_PER_LINE: u16 = 456;
+const OAM_SCAN_DOTS: u16 = 80;
+const LINES_PER_FRAME: u8 = 154;
+
+// The hardware only draws this many objects on a line, however many overlap it
+const OBJECTS_PER_LINE: usize = 10;
+
+// Value the STAT register reports in its lower 2 bits
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    HBlank = 0,
+    VBlank = 1,
+    OamScan = 2,
+    Drawing = 3,
+}
+
+// How mode 3 is drawn
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Renderer {
+    // A whole line at a time, with mode 3 always 172 dots long. Fast, and right for most games.
+    Scanline,
+    // Pixel by pixel through the background and object FIFOs, as the hardware does. Picks up
+    // register writes partway through a line and gives mode 3 its true, variable length.
+    Fifo,
+}
+
+impl FromStr for Renderer {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "scanline" => Ok(Renderer::Scanline),
+            "fifo" => Ok(Renderer::Fifo),
+            _ => Err(format!("unknown renderer {}", name)),
+        }
+    }
+}
+
+// An entry in OAM
+#[derive(Clone, Copy)]
+struct Object {
+    y: u8,
+    x: u8,
+    tile: u8,
+    flags: u8,
+    // Position in OAM, which decides priority between objects in CGB mode
+    index: u8,
+}
+
+// A background or window pixel before its palette is applied
+#[derive(Clone, Copy, Default)]
+struct BgPixel {
+    colour: u8,
+    palette: u8,
+    // Set by the CGB map attributes to draw colours 1-3 over objects
+    priority: bool,
+}
+
+// An object pixel before its palette is applied
+#[derive(Clone, Copy, Default)]
+struct ObjectPixel {
+    // 0 is transparent
+    colour: u8,
+    palette: u8,
+    behind_bg: bool,
+    index: u8,
+}
+
+// Each line runs for 456 dots (T-cycles): 80 scanning OAM for objects, 172 or more drawing and
+// the rest in HBlank. Lines 144-153 are VBlank.
+pub struct Ppu {
+    model: Model,
+    renderer: Renderer,
+    // Whether CGB features are switched on, which the bus decides
+    cgb_mode: bool,
+    // 2 banks of 8KB on CGB hardware. In DMG mode only the first is used.
+    video_ram: Vec<u8>,
+    video_ram_bank: u8,
+    object_attribute_memory: Vec<u8>,
+    lcd_control: u8,
+    // Only the interrupt select bits, the rest of STAT is worked out when read
+    stat: u8,
+    // The STAT interrupt is requested when any selected condition becomes true, all of them
+    // ORed into one line. While the line stays high no more interrupts are requested, so one
+    // condition can block another that follows straight on from it.
+    stat_line: bool,
+    vblank_requested: bool,
+    stat_requested: bool,
+    // Set as each visible line enters HBlank, which is when HBlank DMA copies a block
+    hblank_started: bool,
+    scroll_y: u8,
+    scroll_x: u8,
+    line: u8,
+    line_compare: u8,
+    bg_palette: u8,
+    obj_palettes: [u8; 2],
+    bg_colour_palettes: ColourPalettes,
+    obj_colour_palettes: ColourPalettes,
+    // Bit 0 set gives objects DMG priority, by X position rather than OAM order
+    object_priority: u8,
+    window_y: u8,
+    window_x: u8,
+    mode: Mode,
+    // Position within the current line
+    dot: u16,
+    // The window has its own line counter, which only moves on lines where it was drawn
+    window_line: u8,
+    // Set once LY has matched WY this frame, after which the window can be drawn
+    window_y_reached: bool,
+    // The first line after the LCD is switched on has no OAM scan. It reads as mode 0 until
+    // drawing starts, and finds no objects.
+    first_line: bool,
+    // Objects found by the OAM scan, ordered by drawing priority
+    line_objects: Vec<Object>,
+    pixel_fifo: PixelFifo,
+    // RGB555 colours after the palettes have been applied
+    framebuffer: Vec<u16>,
+    frame_ready: bool,
+}
+
+impl Ppu {
+    pub fn new(model: Model, renderer: Renderer) -> Self {
+        Ppu {
+            model,
+            renderer,
+            cgb_mode: model.is_cgb(),
+            video_ram: vec![0; 0x4000],
+            video_ram_bank: 0,
+            object_attribute_memory: vec![0; 0xA0],
+            lcd_control: 0,
+            stat: 0,
+            stat_line: false,
+            vblank_requested: false,
+            stat_requested: false,
+            hblank_started: false,
+            scroll_y: 0,
+            scroll_x: 0,
+            line: 0,
+            line_compare: 0,
+            bg_palette: 0,
+            obj_palettes: [0; 2],
+            bg_colour_palettes: ColourPalettes::new(),
+            obj_colour_palettes: ColourPalettes::new(),
+            object_priority: 0,
+            window_y: 0,
+            window_x: 0,
+            mode: Mode::HBlank,
+            dot: 0,
+            window_line: 0,
+            window_y_reached: false,
+            first_line: false,
+            line_objects: Vec::new(),
+            pixel_fifo: PixelFifo::new(),
+            framebuffer: vec![DMG_SHADES[0]; SCREEN_WIDTH * SCREEN_HEIGHT],
+            frame_ready: false,
+        }
+    }
+
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    // RGB555, with red in the lowest 5 bits. See rgb888 for turning it into something to show.
+    pub fn framebuffer(&self) -> &[u16] {
+        &self.framebuffer
+    }
+
+    // CGB hardware running a DMG game turns its CGB features off, leaving only the first VRAM
+    // bank and the DMG palettes
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+        if !cgb_mode {
+            self.video_ram_bank = 0;
+        }
+    }
+
+    // Whether a whole frame has been drawn since this was last called
+    pub fn take_frame(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
+    }
+
+    fn lcd_enabled(&self) -> bool {
+        self.lcd_control & LCD_ENABLE != 0
+    }
+
+    // The CPU can't get at VRAM while it's being drawn from, or OAM while it's being scanned
+    fn video_ram_accessible(&self) -> bool {
+        self.mode != Mode::Drawing
+    }
+
+    fn object_attribute_memory_accessible(&self) -> bool {
+        !matches!(self.mode, Mode::OamScan | Mode::Drawing)
+    }
+
+    fn video_ram_offset(&self, address: u16) -> usize {
+        self.video_ram_bank as usize * 0x2000 + (address - 0x8000) as usize
+    }
+
+    // OAM DMA writes straight into OAM, whatever the PPU is doing
+    pub fn write_oam(&mut self, index: u8, value: u8) {
+        self.object_attribute_memory[index as usize] = value;
+    }
+
+    // VRAM DMA writes to the selected bank whatever the PPU is doing
+    pub fn write_video_ram(&mut self, address: u16, value: u8) {
+        let offset = self.video_ram_offset(address);
+        self.video_ram[offset] = value;
+    }
+
+    // Whether a line has entered HBlank since this was last called
+    pub fn take_hblank(&mut self) -> bool {
+        std::mem::take(&mut self.hblank_started)
+    }
+
+    // Interrupts requested since this was last called
+    pub fn take_interrupts(&mut self) -> impl Iterator<Item = Interrupt> {
+        let vblank = std::mem::take(&mut self.vblank_requested).then_some(Interrupt::VBlank);
+        let stat = std::mem::take(&mut self.stat_requested).then_some(Interrupt::LcdStat);
+
+        vblank.into_iter().chain(stat)
+    }
+
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.tick_dot();
+        }
+    }
+
+    fn tick_dot(&mut self) {
+        if !self.lcd_enabled() {
+            return;
+        }
+
+        self.dot += 1;
+        match self.mode {
+            Mode::OamScan if self.dot == OAM_SCAN_DOTS => self.start_drawing(),
+            Mode::HBlank if self.first_line && self.dot == OAM_SCAN_DOTS => {
+                self.first_line = false;
+                self.start_drawing();
+                self.line_objects.clear();
+            }
+            Mode::Drawing => {
+                let finished = match self.renderer {
+                    Renderer::Scanline => self.dot == OAM_SCAN_DOTS + 172,
+                    Renderer::Fifo => self.step_fifo(),
+                };
+
+                if finished {
+                    if self.renderer == Renderer::Scanline {
+                        self.render_line();
+                    }
+                    self.mode = Mode::HBlank;
+                    self.hblank_started = true;
+                }
+            }
+            Mode::HBlank | Mode::VBlank if self.dot == DOTS_PER_LINE => self.next_line(),
+            _ => (),
+        }
+
+        self.update_stat_line();
+    }
+
+    fn stat_conditions(&self) -> u8 {
+        if !self.lcd_enabled() {
+            return 0;
+        }
+
+        let mut conditions = match self.mode {
+            // Not a real HBlank, so it doesn't meet the mode 0 condition
+            Mode::HBlank if self.first_line => 0,
+            Mode::HBlank => STAT_HBLANK,
+            Mode::VBlank => STAT_VBLANK,
+            Mode::OamScan => STAT_OAM_SCAN,
+            Mode::Drawing => 0,
+        };
+        // The mode 2 condition is also met as VBlank starts
+        if self.line == SCREEN_HEIGHT as u8 && self.dot == 0 {
+            conditions |= STAT_OAM_SCAN;
+        }
+        if self.line == self.line_compare {
+            conditions |= STAT_LINE_COMPARE;
+        }
+        conditions
+    }
+
+    fn update_stat_line(&mut self) {
+        let stat_line = self.stat & self.stat_conditions() != 0;
+        if stat_line && !self.stat_line {
+            self.stat_requested = true;
+        }
+        self.stat_line = stat_line;
+    }
+
+    // On the DMG, writing to STAT selects every condition for a cycle before the written value
+    // takes effect. Whatever is true at the time raises an interrupt, which some games rely on.
+    fn write_stat(&mut self, value: u8) {
+        if !self.model.is_cgb() {
+            self.stat = 0x78;
+            self.update_stat_line();
+        }
+
+        self.stat = value & 0x78;
+        self.update_stat_line();
+    }
+
+    fn start_drawing(&mut self) {
+        self.mode = Mode::Drawing;
+        self.line_objects = self.scan_objects();
+
+        if self.renderer == Renderer::Fifo {
+            self.pixel_fifo.start_line(self.scroll_x);
+        }
+    }
+
+    fn next_line(&mut self) {
+        self.dot = 0;
+        self.line += 1;
+
+        if self.line == SCREEN_HEIGHT as u8 {
+            self.mode = Mode::VBlank;
+            self.frame_ready = true;
+            self.vblank_requested = true;
+        } else if self.line == LINES_PER_FRAME {
+            self.line = 0;
+            self.window_line = 0;
+            self.window_y_reached = false;
+            self.start_line();
+        } else if self.line < SCREEN_HEIGHT as u8 {
+            self.start_line();
+        }
+    }
+
+    fn start_line(&mut self) {
+        self.mode = Mode::OamScan;
+        if self.line == self.window_y {
+            self.window_y_reached = true;
+        }
+    }
+
+    fn set_lcd_control(&mut self, value: u8) {
+        let was_enabled = self.lcd_enabled();
+        self.lcd_control = value;
+
+        match (was_enabled, self.lcd_enabled()) {
+            // Switching off resets the PPU to the top of the screen
+            (true, false) => {
+                self.line = 0;
+                self.dot = 0;
+                self.mode = Mode::HBlank;
+            }
+            (false, true) => {
+                self.window_line = 0;
+                self.window_y_reached = self.window_y == 0;
+                self.mode = Mode::HBlank;
+                self.first_line = true;
+            }
+            _ => (),
+        }
+    }
+
+    fn read_stat(&self) -> u8 {
+        let mut value = 0x80 | self.stat;
+        if self.line == self.line_compare {
+            value |= 0x04;
+        }
+        if self.lcd_enabled() {
+            value |= self.mode as u8;
+        }
+        value
+    }
+
+    fn object(&self, index: usize) -> Object {
+        let entry = &self.object_attribute_memory[index * 4..index * 4 + 4];
+
+        Object {
+            y: entry[0],
+            x: entry[1],
+            tile: entry[2],
+            flags: entry[3],
+            index: index as u8,
+        }
+    }
+
+    fn object_height(&self) -> u8 {
+        if self.lcd_control & OBJ_SIZE != 0 {
+            16
+        } else {
+            8
+        }
+    }
+
+    // The first 10 objects in OAM that overlap the line. Their X position doesn't matter, so
+    // objects off the side of the screen still count towards the limit.
+    //
+    // On the DMG the object with the lowest X is drawn on top, with ties going to the first in OAM.
+    // Objects are fetched in X order however they're prioritised, so they're sorted by X either
+    // way. A stable sort keeps the OAM order for equal X.
+    fn scan_objects(&self) -> Vec<Object> {
+        let height = self.object_height();
+        let line = self.line as u16 + 16;
+
+        let mut objects: Vec<Object> = (0..40)
+            .map(|index| self.object(index))
+            .filter(|object| line >= object.y as u16 && line < object.y as u16 + height as u16)
+            .take(OBJECTS_PER_LINE)
+            .collect();
+        objects.sort_by_key(|object| object.x);
+
+        objects
+    }
+
+    // In CGB mode objects earlier in OAM are drawn on top, unless OPRI asks for DMG priority
+    fn oam_priority(&self) -> bool {
+        self.cgb_mode && self.object_priority & 0x01 == 0
+    }
+
+    // The object's 8 pixels on the current line, left to right
+    fn object_row(&self, object: Object) -> [ObjectPixel; 8] {
+        let height = self.object_height();
+        let mut row = (self.line + 16 - object.y) % height;
+        if object.flags & OBJ_Y_FLIP != 0 {
+            row = height - 1 - row;
+        }
+
+        // 8x16 objects use an even and odd pair of tiles, ignoring bit 0 of the tile number
+        let tile = if height == 16 {
+            (object.tile & 0xFE) + row / 8
+        } else {
+            object.tile
+        };
+
+        let (bank, palette) = if self.cgb_mode {
+            (
+                (object.flags & OBJ_BANK != 0) as usize,
+                object.flags & OBJ_CGB_PALETTE,
+            )
+        } else {
+            (0, (object.flags & OBJ_PALETTE != 0) as u8)
+        };
+
+        let mut colours = self.tile_row(bank, tile as usize * 16, row % 8);
+        if object.flags & OBJ_X_FLIP != 0 {
+            colours.reverse();
+        }
+        colours.map(|colour| ObjectPixel {
+            colour,
+            palette,
+            behind_bg: object.flags & OBJ_BEHIND_BG != 0,
+            index: object.index,
+        })
+    }
+
+    // Colour numbers 0-3 of a row of a tile, from its 2 bitplanes
+    fn tile_row(&self, bank: usize, tile_address: usize, row: u8) -> [u8; 8] {
+        let address = bank * 0x2000 + tile_address + row as usize * 2;
+        let low = self.video_ram[address];
+        let high = self.video_ram[address + 1];
+
+        let mut colours = [0; 8];
+        for (column, colour) in colours.iter_mut().enumerate() {
+            let bit = 7 - column;
+            *colour = (((high >> bit) & 1) << 1) | ((low >> bit) & 1);
+        }
+        colours
+    }
+
+    // Background and window tiles are numbered either from 0x8000 or, signed, from 0x9000.
+    // Returns an offset into VRAM.
+    fn bg_tile_address(&self, tile: u8) -> usize {
+        if self.lcd_control & TILE_DATA != 0 {
+            tile as usize * 16
+        } else {
+            (0x1000 + tile as i8 as i32 * 16) as usize
+        }
+    }
+
+    // VRAM offset of the tile at the given pixel position in the background or window map, along
+    // with its attributes from the same place in bank 1
+    fn map_entry(&self, map_select: u8, x: u8, y: u8) -> (usize, u8) {
+        let map = if self.lcd_control & map_select != 0 {
+            0x1C00
+        } else {
+            0x1800
+        };
+        let offset = map + (y as usize / 8) * 32 + x as usize / 8;
+        let attributes = if self.cgb_mode {
+            self.video_ram[0x2000 + offset]
+        } else {
+            0
+        };
+
+        (self.bg_tile_address(self.video_ram[offset]), attributes)
+    }
+
+    // A row of a background or window tile, given its map entry
+    fn bg_row(&self, (tile_address, attributes): (usize, u8), row: u8) -> [BgPixel; 8] {
+        let row = if attributes & ATTR_Y_FLIP != 0 {
+            7 - row
+        } else {
+            row
+        };
+        let bank = (attributes & ATTR_BANK != 0) as usize;
+
+        let mut colours = self.tile_row(bank, tile_address, row);
+        if attributes & ATTR_X_FLIP != 0 {
+            colours.reverse();
+        }
+        colours.map(|colour| BgPixel {
+            colour,
+            palette: attributes & ATTR_PALETTE,
+            priority: attributes & ATTR_PRIORITY != 0,
+        })
+    }
+
+    fn map_pixel(&self, map_select: u8, x: u8, y: u8) -> BgPixel {
+        self.bg_row(self.map_entry(map_select, x, y), y % 8)[x as usize % 8]
+    }
+
+    // In DMG mode clearing LCDC bit 0 blanks the background and window. In CGB mode they're
+    // always drawn, and the bit takes away their priority over objects instead.
+    fn bg_visible(&self) -> bool {
+        self.cgb_mode || self.lcd_control & BG_ENABLE != 0
+    }
+
+    // Picks between the background and object pixel at a position and looks up its colour.
+    // Background colour 0 is always behind objects. Colours 1-3 are in front of objects that ask
+    // to be behind, and in CGB mode of any object over a tile that asks to be in front.
+    fn pixel_colour(&self, bg: BgPixel, object: Option<ObjectPixel>) -> u16 {
+        let object = object.filter(|pixel| pixel.colour != 0 && self.lcd_control & OBJ_ENABLE != 0);
+        let bg_priority = !(self.cgb_mode && self.lcd_control & BG_ENABLE == 0);
+
+        match object {
+            Some(pixel) if !(bg_priority && bg.colour != 0 && (pixel.behind_bg || bg.priority)) => {
+                if self.cgb_mode {
+                    self.obj_colour_palettes.colour(pixel.palette, pixel.colour)
+                } else {
+                    let palette = self.obj_palettes[pixel.palette as usize];
+                    DMG_SHADES[shade(palette, pixel.colour) as usize]
+                }
+            }
+            _ => {
+                if self.cgb_mode {
+                    self.bg_colour_palettes.colour(bg.palette, bg.colour)
+                } else {
+                    DMG_SHADES[shade(self.bg_palette, bg.colour) as usize]
+                }
+            }
+        }
+    }
+}
+
+// Looks up a colour number in a DMG palette register
+fn shade(palette: u8, colour: u8) -> u8 {
+    (palette >> (colour * 2)) & 0x03
+}
+
+impl MemoryMapped for Ppu {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x8000..=0x9FFF if self.video_ram_accessible() => {
+                self.video_ram[self.video_ram_offset(address)]
+            }
+            0xFE00..=0xFE9F if self.object_attribute_memory_accessible() => {
+                self.object_attribute_memory[(address - 0xFE00) as usize]
+            }
+            LCDC => self.lcd_control,
+            STAT => self.read_stat(),
+            SCY => self.scroll_y,
+            SCX => self.scroll_x,
+            LY => self.line,
+            LYC => self.line_compare,
+            BGP => self.bg_palette,
+            OBP0 => self.obj_palettes[0],
+            OBP1 => self.obj_palettes[1],
+            WY => self.window_y,
+            WX => self.window_x,
+            VBK if self.cgb_mode => 0xFE | self.video_ram_bank,
+            BCPS if self.cgb_mode => self.bg_colour_palettes.read_index(),
+            BCPD if self.cgb_mode && self.video_ram_accessible() => {
+                self.bg_colour_palettes.read_data()
+            }
+            OCPS if self.cgb_mode => self.obj_colour_palettes.read_index(),
+            OCPD if self.cgb_mode && self.video_ram_accessible() => {
+                self.obj_colour_palettes.read_data()
+            }
+            OPRI if self.cgb_mode => 0xFE | self.object_priority,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x8000..=0x9FFF if self.video_ram_accessible() => {
+                let offset = self.video_ram_offset(address);
+                self.video_ram[offset] = value;
+            }
+            0xFE00..=0xFE9F if self.object_attribute_memory_accessible() => {
+                self.object_attribute_memory[(address - 0xFE00) as usize] = value;
+            }
+            LCDC => {
+                self.set_lcd_control(value);
+                self.update_stat_line();
+            }
+            STAT => self.write_stat(value),
+            SCY => self.scroll_y = value,
+            SCX => self.scroll_x = value,
+            LYC => {
+                self.line_compare = value;
+                self.update_stat_line();
+            }
+            BGP => self.bg_palette = value,
+            OBP0 => self.obj_palettes[0] = value,
+            OBP1 => self.obj_palettes[1] = value,
+            WY => self.window_y = value,
+            WX => self.window_x = value,
+            VBK if self.cgb_mode => self.video_ram_bank = value & 0x01,
+            BCPS if self.cgb_mode => self.bg_colour_palettes.write_index(value),
+            // Palette RAM can't be written while the PPU is drawing
+            BCPD if self.cgb_mode => {
+                let accessible = self.video_ram_accessible();
+                self.bg_colour_palettes.write_data(value, accessible);
+            }
+            OCPS if self.cgb_mode => self.obj_colour_palettes.write_index(value),
+            OCPD if self.cgb_mode => {
+                let accessible = self.video_ram_accessible();
+                self.obj_colour_palettes.write_data(value, accessible);
+            }
+            OPRI if self.cgb_mode => self.object_priority = value & 0x01,
+            // LY is read only
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RENDERERS: [Renderer; 2] = [Renderer::Scanline, Renderer::Fifo];
+
+    // Runs until the start of the given line, with the LCD on
+    fn run_to_line(ppu: &mut Ppu, line: u8) {
+        while ppu.line != line || ppu.dot != 0 {
+            ppu.tick(1);
+        }
+    }
+
+    fn ppu_with_tiles(renderer: Renderer) -> Ppu {
+        ppu_with_tiles_on(Model::Dmg, renderer)
+    }
+
+    // Tile 1 filled with colour 3, and tile 2 with colour 1
+    fn ppu_with_tiles_on(model: Model, renderer: Renderer) -> Ppu {
+        let mut ppu = Ppu::new(model, renderer);
+        for address in 0x8010..0x8020 {
+            ppu.write(address, 0xFF);
+        }
+        for address in (0x8020..0x8030).step_by(2) {
+            ppu.write(address, 0xFF);
+        }
+        ppu.write(BGP, 0xE4);
+        ppu.write(OBP0, 0xE4);
+        ppu.write(OBP1, 0x1B);
+        ppu
+    }
+
+    // Switches the LCD on and runs to the start of the next frame, since the first line after
+    // switching on doesn't draw objects
+    fn switch_on(ppu: &mut Ppu, lcd_control: u8) {
+        ppu.write(LCDC, lcd_control);
+        run_to_line(ppu, 1);
+        run_to_line(ppu, 0);
+    }
+
+    // The DMG shade 0-3 at a position
+    fn pixel(ppu: &Ppu, x: usize, y: usize) -> u8 {
+        let colour = ppu.framebuffer()[y * SCREEN_WIDTH + x];
+        DMG_SHADES
+            .iter()
+            .position(|&shade| shade == colour)
+            .unwrap() as u8
+    }
+
+    fn set_colour(ppu: &mut Ppu, index_register: u16, palette: u8, colour: u8, value: u16) {
+        ppu.write(index_register, 0x80 | (palette * 8 + colour * 2));
+        for byte in value.to_le_bytes() {
+            ppu.write(index_register + 1, byte);
+        }
+    }
+
+    fn set_object(ppu: &mut Ppu, index: u16, y: u8, x: u8, tile: u8, flags: u8) {
+        let address = 0xFE00 + index * 4;
+        ppu.write(address, y);
+        ppu.write(address + 1, x);
+        ppu.write(address + 2, tile);
+        ppu.write(address + 3, flags);
+    }
+
+    #[test]
+    fn modes_follow_dot_timing() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(LCDC, LCD_ENABLE);
+
+            // The first line after switching on skips the OAM scan
+            assert_eq!(ppu.mode(), Mode::HBlank);
+            ppu.tick(79);
+            assert_eq!(ppu.mode(), Mode::HBlank);
+            ppu.tick(1);
+            assert_eq!(ppu.mode(), Mode::Drawing);
+            ppu.tick(172);
+            assert_eq!(ppu.mode(), Mode::HBlank);
+            ppu.tick(204);
+            assert_eq!(ppu.mode(), Mode::OamScan);
+            assert_eq!(ppu.read(LY), 1);
+
+            ppu.tick(79);
+            assert_eq!(ppu.mode(), Mode::OamScan);
+            ppu.tick(1);
+            assert_eq!(ppu.mode(), Mode::Drawing);
+
+            run_to_line(&mut ppu, 144);
+            assert_eq!(ppu.mode(), Mode::VBlank);
+            assert!(ppu.take_frame());
+            assert!(!ppu.take_frame());
+
+            run_to_line(&mut ppu, 0);
+            assert_eq!(ppu.mode(), Mode::OamScan);
+        }
+    }
+
+    #[test]
+    fn frame_takes_70224_dots() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(LCDC, LCD_ENABLE);
+
+            ppu.tick(70223);
+            assert_eq!(ppu.read(LY), 153);
+            ppu.tick(1);
+            assert_eq!(ppu.read(LY), 0);
+        }
+    }
+
+    #[test]
+    fn stat_reports_mode_and_coincidence() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(STAT, 0xFF);
+            ppu.write(LYC, 1);
+            assert_eq!(ppu.read(STAT), 0xF8);
+
+            ppu.write(LCDC, LCD_ENABLE);
+            assert_eq!(ppu.read(STAT), 0xF8);
+            ppu.tick(80);
+            assert_eq!(ppu.read(STAT), 0xFB);
+
+            run_to_line(&mut ppu, 1);
+            assert_eq!(ppu.read(STAT), 0xFE);
+
+            // Turning the LCD off goes back to line 0 and reads as mode 0
+            ppu.write(LCDC, 0x00);
+            assert_eq!(ppu.read(LY), 0);
+            assert_eq!(ppu.read(STAT), 0xF8);
+        }
+    }
+
+    #[test]
+    fn ly_is_read_only() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(LCDC, LCD_ENABLE);
+            run_to_line(&mut ppu, 5);
+
+            ppu.write(LY, 0x00);
+            assert_eq!(ppu.read(LY), 5);
+        }
+    }
+
+    #[test]
+    fn memory_is_blocked_while_in_use() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(0x8000, 0x12);
+            ppu.write(0xFE00, 0x34);
+            ppu.write(LCDC, LCD_ENABLE);
+
+            // Nothing is blocked until drawing starts on the first line after switching on
+            assert_eq!(ppu.read(0x8000), 0x12);
+            assert_eq!(ppu.read(0xFE00), 0x34);
+
+            // OAM scan
+            run_to_line(&mut ppu, 1);
+            assert_eq!(ppu.read(0x8000), 0x12);
+            assert_eq!(ppu.read(0xFE00), 0xFF);
+
+            // Drawing
+            ppu.tick(80);
+            ppu.write(0x8000, 0x56);
+            assert_eq!(ppu.read(0x8000), 0xFF);
+            assert_eq!(ppu.read(0xFE00), 0xFF);
+
+            // HBlank
+            ppu.tick(172);
+            assert_eq!(ppu.read(0x8000), 0x12);
+            assert_eq!(ppu.read(0xFE00), 0x34);
+        }
+    }
+
+    #[test]
+    fn renders_scrolled_background() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // Tile 1 at the second column of the 0x9800 map, tile data from 0x8000
+            ppu.write(0x9801, 1);
+            ppu.write(SCX, 4);
+            ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE);
+            run_to_line(&mut ppu, 1);
+
+            assert_eq!(pixel(&ppu, 3, 0), 0);
+            assert_eq!(pixel(&ppu, 4, 0), 3);
+            assert_eq!(pixel(&ppu, 11, 0), 3);
+            assert_eq!(pixel(&ppu, 12, 0), 0);
+        }
+    }
+
+    #[test]
+    fn signed_tile_data_addressing() {
+        for renderer in RENDERERS {
+            let mut ppu = Ppu::new(Model::Dmg, renderer);
+            ppu.write(BGP, 0xE4);
+            // Tile -1 lives at 0x8FF0, just before tile 0 at 0x9000
+            for address in 0x8FF0..0x9000 {
+                ppu.write(address, 0xFF);
+            }
+            ppu.write(0x9800, 0xFF);
+            ppu.write(LCDC, LCD_ENABLE | BG_ENABLE);
+            run_to_line(&mut ppu, 1);
+
+            assert_eq!(pixel(&ppu, 0, 0), 3);
+            assert_eq!(pixel(&ppu, 8, 0), 0);
+        }
+    }
+
+    #[test]
+    fn window_covers_background() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // Window from the 0x9C00 map filled with tile 2, starting at x = 80, y = 2
+            for address in 0x9C00..0x9C20 {
+                ppu.write(address, 2);
+            }
+            ppu.write(WY, 2);
+            ppu.write(WX, 87);
+            ppu.write(
+                LCDC,
+                LCD_ENABLE | TILE_DATA | BG_ENABLE | WINDOW_ENABLE | WINDOW_TILE_MAP,
+            );
+            run_to_line(&mut ppu, 4);
+
+            assert_eq!(pixel(&ppu, 100, 1), 0);
+            assert_eq!(pixel(&ppu, 79, 2), 0);
+            assert_eq!(pixel(&ppu, 80, 2), 1);
+            assert_eq!(pixel(&ppu, 159, 3), 1);
+        }
+    }
+
+    #[test]
+    fn window_line_counter_skips_hidden_lines() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // Only the window's second row of tiles is filled
+            for address in 0x9C20..0x9C40 {
+                ppu.write(address, 1);
+            }
+            ppu.write(WX, 7);
+            ppu.write(
+                LCDC,
+                LCD_ENABLE | TILE_DATA | BG_ENABLE | WINDOW_ENABLE | WINDOW_TILE_MAP,
+            );
+            run_to_line(&mut ppu, 4);
+
+            // Hiding the window for a few lines pauses its line counter
+            ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE | WINDOW_TILE_MAP);
+            run_to_line(&mut ppu, 20);
+            ppu.write(
+                LCDC,
+                LCD_ENABLE | TILE_DATA | BG_ENABLE | WINDOW_ENABLE | WINDOW_TILE_MAP,
+            );
+            run_to_line(&mut ppu, 24);
+
+            assert_eq!(pixel(&ppu, 0, 23), 0);
+            run_to_line(&mut ppu, 25);
+            assert_eq!(pixel(&ppu, 0, 24), 3);
+        }
+    }
+
+    #[test]
+    fn objects_use_their_palette_and_flips() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // Tile 3 has only its top-left pixel set, to colour 3
+            ppu.write(0x8030, 0x80);
+            ppu.write(0x8031, 0x80);
+            ppu.write(OBP1, 0x80);
+            set_object(&mut ppu, 0, 16, 8, 3, 0);
+            set_object(
+                &mut ppu,
+                1,
+                16,
+                24,
+                3,
+                OBJ_X_FLIP | OBJ_Y_FLIP | OBJ_PALETTE,
+            );
+            switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+            run_to_line(&mut ppu, 8);
+
+            assert_eq!(pixel(&ppu, 0, 0), 3);
+            assert_eq!(pixel(&ppu, 1, 0), 0);
+            assert_eq!(pixel(&ppu, 16, 0), 0);
+            assert_eq!(pixel(&ppu, 23, 7), 2);
+        }
+    }
+
+    #[test]
+    fn first_line_after_switching_on_has_no_objects() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            set_object(&mut ppu, 0, 16, 8, 1, 0);
+            ppu.write(LCDC, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+            run_to_line(&mut ppu, 2);
+
+            assert_eq!(pixel(&ppu, 0, 0), 0);
+            assert_eq!(pixel(&ppu, 0, 1), 3);
+        }
+    }
+
+    #[test]
+    fn tall_objects_span_two_tiles() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            for address in 0x8030..0x8040 {
+                ppu.write(address, 0xFF);
+            }
+            // Tile 2 on top and tile 3 below, whichever of the pair is asked for
+            set_object(&mut ppu, 0, 16, 8, 0x03, 0);
+            ppu.write(LCDC, LCD_ENABLE | TILE_DATA | OBJ_ENABLE | OBJ_SIZE);
+            run_to_line(&mut ppu, 17);
+
+            assert_eq!(pixel(&ppu, 0, 7), 1);
+            assert_eq!(pixel(&ppu, 0, 8), 3);
+            assert_eq!(pixel(&ppu, 0, 15), 3);
+            assert_eq!(pixel(&ppu, 0, 16), 0);
+        }
+    }
+
+    #[test]
+    fn only_ten_objects_per_line() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // The first object is off screen but still uses up a slot
+            set_object(&mut ppu, 0, 16, 0, 1, 0);
+            for index in 1..12 {
+                set_object(&mut ppu, index, 16, 8 * index as u8, 1, 0);
+            }
+            switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+            run_to_line(&mut ppu, 1);
+
+            assert_eq!(pixel(&ppu, 8 * 8, 0), 3);
+            assert_eq!(pixel(&ppu, 8 * 9, 0), 0);
+            assert_eq!(pixel(&ppu, 8 * 10, 0), 0);
+        }
+    }
+
+    #[test]
+    fn lower_x_wins_then_oam_order() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            // Colour 1 object later in OAM but further left, overlapping a colour 3 object
+            set_object(&mut ppu, 0, 16, 12, 1, 0);
+            set_object(&mut ppu, 1, 16, 8, 2, 0);
+            // Same X: the first in OAM wins
+            set_object(&mut ppu, 2, 16, 40, 2, 0);
+            set_object(&mut ppu, 3, 16, 40, 1, 0);
+            switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+            run_to_line(&mut ppu, 1);
+
+            assert_eq!(pixel(&ppu, 4, 0), 1);
+            assert_eq!(pixel(&ppu, 8, 0), 3);
+            assert_eq!(pixel(&ppu, 32, 0), 1);
+        }
+    }
+
+    #[test]
+    fn objects_behind_background() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles(renderer);
+            ppu.write(0x9800, 2);
+            set_object(&mut ppu, 0, 16, 12, 1, OBJ_BEHIND_BG);
+            switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | BG_ENABLE | OBJ_ENABLE);
+            run_to_line(&mut ppu, 1);
+
+            // Hidden by background colours 1-3 but drawn over colour 0
+            assert_eq!(pixel(&ppu, 7, 0), 1);
+            assert_eq!(pixel(&ppu, 8, 0), 3);
+        }
+    }
+
+    fn stat_interrupt_taken(ppu: &mut Ppu) -> bool {
+        ppu.take_interrupts()
+            .any(|interrupt| interrupt == Interrupt::LcdStat)
+    }
+
+    #[test]
+    fn vblank_interrupt_once_a_frame() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Scanline);
+        ppu.write(LCDC, LCD_ENABLE);
+
+        run_to_line(&mut ppu, 143);
+        assert_eq!(ppu.take_interrupts().count(), 0);
+
+        run_to_line(&mut ppu, 144);
+        assert!(ppu.take_interrupts().eq([Interrupt::VBlank]));
+
+        run_to_line(&mut ppu, 0);
+        assert_eq!(ppu.take_interrupts().count(), 0);
+    }
+
+    #[test]
+    fn stat_interrupt_on_each_selected_mode() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Scanline);
+        ppu.write(STAT, STAT_HBLANK);
+        ppu.write(LCDC, LCD_ENABLE);
+        ppu.take_interrupts().count();
+
+        ppu.tick(80 + 171);
+        assert!(!stat_interrupt_taken(&mut ppu));
+        ppu.tick(1);
+        assert!(stat_interrupt_taken(&mut ppu));
+
+        // Once per line
+        ppu.tick(456);
+        assert!(stat_interrupt_taken(&mut ppu));
+        ppu.tick(100);
+        assert!(!stat_interrupt_taken(&mut ppu));
+    }
+
+    #[test]
+    fn stat_line_blocks_back_to_back_conditions() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Scanline);
+        ppu.write(STAT, STAT_HBLANK | STAT_OAM_SCAN);
+        ppu.write(LCDC, LCD_ENABLE);
+        run_to_line(&mut ppu, 1);
+        ppu.take_interrupts().count();
+
+        // HBlank runs straight into the next line's OAM scan, so only HBlank raises an interrupt
+        let mut count = 0;
+        for _ in 0..456 * 3 {
+            ppu.tick(1);
+            count += ppu.take_interrupts().count();
+        }
+        assert_eq!(count, 3);
+    }
+
+    #[test]
+    fn line_compare_interrupt() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Scanline);
+        ppu.write(STAT, STAT_LINE_COMPARE);
+        ppu.write(LYC, 10);
+        ppu.write(LCDC, LCD_ENABLE);
+
+        run_to_line(&mut ppu, 9);
+        assert!(!stat_interrupt_taken(&mut ppu));
+        ppu.tick(1);
+        assert!(!stat_interrupt_taken(&mut ppu));
+        run_to_line(&mut ppu, 10);
+        assert!(stat_interrupt_taken(&mut ppu));
+
+        // Writing LYC to match the current line also counts
+        run_to_line(&mut ppu, 20);
+        ppu.write(LYC, 20);
+        assert!(stat_interrupt_taken(&mut ppu));
+    }
+
+    #[test]
+    fn oam_scan_condition_fires_as_vblank_starts() {
+        let mut ppu = Ppu::new(Model::Dmg, Renderer::Scanline);
+        ppu.write(STAT, STAT_OAM_SCAN);
+        ppu.write(LCDC, LCD_ENABLE);
+        run_to_line(&mut ppu, 143);
+        ppu.tick(100);
+        ppu.take_interrupts().count();
+
+        run_to_line(&mut ppu, 144);
+        assert!(stat_interrupt_taken(&mut ppu));
+    }
+
+    #[test]
+    fn dmg_stat_write_bug() {
+        for (model, interrupt) in [(Model::Dmg, true), (Model::Cgb, false)] {
+            let mut ppu = Ppu::new(model, Renderer::Scanline);
+            ppu.write(LCDC, LCD_ENABLE);
+            ppu.tick(300);
+            assert_eq!(ppu.mode(), Mode::HBlank);
+            ppu.take_interrupts().count();
+
+            ppu.write(STAT, 0x00);
+            assert_eq!(stat_interrupt_taken(&mut ppu), interrupt, "{:?}", model);
+        }
+    }
+
+    const RED: u16 = 0x001F;
+    const GREEN: u16 = 0x03E0;
+    const BLUE: u16 = 0x7C00;
+
+    #[test]
+    fn cgb_background_attributes() {
+        for renderer in RENDERERS {
+            let mut ppu = ppu_with_tiles_on(Model::Cgb, renderer);
+            set_colour(&mut ppu, BCPS, 0, 0, GREEN);
+            set_colour(&mut ppu, BCPS, 2, 0, BLUE);
+            set_colour(&mut ppu, BCPS, 2, 3, RED);
+
+            // Tile 1 in bank 1 has only its top-left pixel set
+            ppu.write(VBK, 1);
+            ppu.write(0x8010, 0x80);
+            ppu.write(0x8011, 0x80);
+            ppu.write(0x9800, ATTR_BANK | ATTR_X_FLIP | 2);
+            ppu.write(0x9801, ATTR_BANK | ATTR_Y_FLIP);
+            ppu.write(VBK, 0);
+            ppu.write(0x9800, 1);
+            ppu.write(0x9801, 1);
+            ppu.write(LCDC, LCD_ENABLE | TILE_DATA | BG_ENABLE);
+            run_to_line(&mut ppu, 8);
+
+            let colour = |x: usize, y: usize| ppu.framebuffer()[y * SCREEN_WIDTH + x];
+            assert_eq!(colour(0, 0), BLUE);
+            assert_eq!(colour(7, 0), RED);
+            assert_eq!(colour(8, 0), GREEN);
+            assert_eq!(colour(8, 7), 0x7FFF);
+        }
+    }
+
+    #[test]
+    fn cgb_objects_prioritised_by_oam_order() {
+        for renderer in RENDERERS {
+            for (object_priority, winner) in [(0, RED), (1, BLUE)] {
+                let mut ppu = ppu_with_tiles_on(Model::Cgb, renderer);
+                set_colour(&mut ppu, OCPS, 1, 3, RED);
+                set_colour(&mut ppu, OCPS, 2, 1, BLUE);
+                ppu.write(OPRI, object_priority);
+
+                // Further right but first in OAM
+                set_object(&mut ppu, 0, 16, 12, 1, 1);
+                set_object(&mut ppu, 1, 16, 8, 2, 2);
+                switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | OBJ_ENABLE);
+                run_to_line(&mut ppu, 1);
+
+                assert_eq!(ppu.framebuffer()[4], winner, "{:?}", renderer);
+            }
+        }
+    }
+
+    #[test]
+    fn cgb_bg_priority_and_master_priority() {
+        for renderer in RENDERERS {
+            for (lcd_control, winner) in [(BG_ENABLE, GREEN), (0, RED)] {
+                let mut ppu = ppu_with_tiles_on(Model::Cgb, renderer);
+                set_colour(&mut ppu, BCPS, 0, 3, GREEN);
+                set_colour(&mut ppu, OCPS, 0, 3, RED);
+
+                ppu.write(VBK, 1);
+                ppu.write(0x9800, ATTR_PRIORITY);
+                ppu.write(VBK, 0);
+                ppu.write(0x9800, 1);
+                ppu.write(0x9801, 1);
+                set_object(&mut ppu, 0, 16, 8, 1, 0);
+                switch_on(&mut ppu, LCD_ENABLE | TILE_DATA | OBJ_ENABLE | lcd_control);
+                run_to_line(&mut ppu, 1);
+
+                // The background is drawn either way, but only has priority with LCDC bit 0 set
+                assert_eq!(ppu.framebuffer()[0], winner, "{:?}", renderer);
+                assert_eq!(ppu.framebuffer()[8], GREEN, "{:?}", renderer);
+            }
+        }
+    }
+
+    #[test]
+    fn palette_ram_is_blocked_while_drawing() {
+        let mut ppu = Ppu::new(Model::Cgb, Renderer::Scanline);
+        ppu.write(LCDC, LCD_ENABLE);
+        ppu.tick(80);
+        assert_eq!(ppu.mode(), Mode::Drawing);
+
+        ppu.write(BCPS, 0x80);
+        ppu.write(BCPD, 0x00);
+        assert_eq!(ppu.read(BCPS), 0xC1);
+        assert_eq!(ppu.read(BCPD), 0xFF);
+
+        ppu.tick(172);
+        ppu.write(BCPS, 0x00);
+        assert_eq!(ppu.read(BCPD), 0xFF);
+    }
+
+    #[test]
+    fn cgb_registers_only_in_cgb_mode() {
+        let mut ppu = Ppu::new(Model::Cgb, Renderer::Scanline);
+        ppu.set_cgb_mode(false);
+
+        ppu.write(BCPS, 0x80);
+        ppu.write(OPRI, 0x01);
+        assert_eq!(ppu.read(BCPS), 0xFF);
+        assert_eq!(ppu.read(OPRI), 0xFF);
+    }
+}
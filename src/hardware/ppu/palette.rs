@@ -0,0 +1,119 @@
+// The CGB's 8 background or 8 object palettes of 4 colours each, stored as little-endian RGB555.
+// Games reach the 64 bytes through an index register and a data register, with the index
+// optionally moving on after each write so a whole palette can be written in a row.
+pub struct ColourPalettes {
+    data: [u8; 64],
+    index: u8,
+    auto_increment: bool,
+}
+
+impl ColourPalettes {
+    // The boot ROM leaves every colour white
+    pub fn new() -> Self {
+        ColourPalettes {
+            data: [0xFF; 64],
+            index: 0,
+            auto_increment: false,
+        }
+    }
+
+    // Bit 6 is unused and reads as 1
+    pub fn read_index(&self) -> u8 {
+        let auto_increment = if self.auto_increment { 0x80 } else { 0x00 };
+        auto_increment | 0x40 | self.index
+    }
+
+    pub fn write_index(&mut self, value: u8) {
+        self.index = value & 0x3F;
+        self.auto_increment = value & 0x80 != 0;
+    }
+
+    pub fn read_data(&self) -> u8 {
+        self.data[self.index as usize]
+    }
+
+    // The index moves on even when the write itself is ignored because the PPU is drawing
+    pub fn write_data(&mut self, value: u8, accessible: bool) {
+        if accessible {
+            self.data[self.index as usize] = value;
+        }
+        if self.auto_increment {
+            self.index = (self.index + 1) & 0x3F;
+        }
+    }
+
+    pub fn colour(&self, palette: u8, colour: u8) -> u16 {
+        let offset = palette as usize * 8 + colour as usize * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]]) & 0x7FFF
+    }
+}
+
+// Converts an RGB555 colour to 0x00RRGGBB. The CGB's screen doesn't show colours the way a modern
+// one does: they come out washed out and bleed into each other. Colour correction mimics that, so
+// games look the way their artists saw them.
+pub fn rgb888(colour: u16, colour_correction: bool) -> u32 {
+    let red = (colour & 0x1F) as u32;
+    let green = ((colour >> 5) & 0x1F) as u32;
+    let blue = ((colour >> 10) & 0x1F) as u32;
+
+    let (red, green, blue) = if colour_correction {
+        (
+            (red * 26 + green * 4 + blue * 2).min(960) >> 2,
+            (green * 24 + blue * 8).min(960) >> 2,
+            (red * 6 + green * 4 + blue * 22).min(960) >> 2,
+        )
+    } else {
+        // Copy the top bits into the bottom so white comes out as 0xFF
+        (
+            (red << 3) | (red >> 2),
+            (green << 3) | (green >> 2),
+            (blue << 3) | (blue >> 2),
+        )
+    };
+
+    (red << 16) | (green << 8) | blue
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn auto_increment_moves_on_after_writes() {
+        let mut palettes = ColourPalettes::new();
+        palettes.write_index(0x80 | 0x3E);
+        assert_eq!(palettes.read_index(), 0xFE);
+
+        palettes.write_data(0x1F, true);
+        palettes.write_data(0x00, true);
+        palettes.write_data(0xE0, true);
+        assert_eq!(palettes.read_index(), 0xC1);
+
+        assert_eq!(palettes.colour(7, 3), 0x001F);
+        palettes.write_index(0x00);
+        assert_eq!(palettes.read_data(), 0xE0);
+    }
+
+    #[test]
+    fn blocked_writes_still_move_the_index() {
+        let mut palettes = ColourPalettes::new();
+        palettes.write_index(0x80);
+        palettes.write_data(0x00, false);
+
+        assert_eq!(palettes.read_index(), 0xC1);
+        assert_eq!(palettes.colour(0, 0), 0x7FFF);
+    }
+
+    #[test]
+    fn converts_to_rgb888() {
+        assert_eq!(rgb888(0x7FFF, false), 0xFFFFFF);
+        assert_eq!(rgb888(0x001F, false), 0xFF0000);
+        assert_eq!(rgb888(0x0000, true), 0x000000);
+        assert_eq!(rgb888(0x7FFF, true), 0xF0F0F0);
+
+        // Pure green picks up some red and blue
+        let corrected = rgb888(0x03E0, true);
+        assert!(corrected & 0xFF0000 != 0);
+        assert!(corrected & 0x0000FF != 0);
+    }
+}
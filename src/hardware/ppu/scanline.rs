@@ -0,0 +1,74 @@
+use super::{
+    BgPixel, ObjectPixel, Ppu, BG_TILE_MAP, OBJ_ENABLE, SCREEN_WIDTH, WINDOW_ENABLE,
+    WINDOW_TILE_MAP,
+};
+
+// Draws a whole line at once at the end of mode 3, so changes to the registers partway through a
+// line only show up on the next one. Mode 3 always takes 172 dots.
+impl Ppu {
+    pub(super) fn render_line(&mut self) {
+        let line = self.line;
+
+        // Pixels before the palette, which objects need to know whether they're behind
+        let mut bg_pixels = [BgPixel::default(); SCREEN_WIDTH];
+        if self.bg_visible() {
+            let window_visible = self.lcd_control & WINDOW_ENABLE != 0
+                && self.window_y_reached
+                && self.window_x <= 166;
+            let window_start = self.window_x as i16 - 7;
+
+            for (x, pixel) in bg_pixels.iter_mut().enumerate() {
+                *pixel = if window_visible && x as i16 >= window_start {
+                    let window_x = (x as i16 - window_start) as u8;
+                    self.map_pixel(WINDOW_TILE_MAP, window_x, self.window_line)
+                } else {
+                    let bg_x = self.scroll_x.wrapping_add(x as u8);
+                    let bg_y = self.scroll_y.wrapping_add(line);
+                    self.map_pixel(BG_TILE_MAP, bg_x, bg_y)
+                };
+            }
+
+            if window_visible && window_start < SCREEN_WIDTH as i16 {
+                self.window_line += 1;
+            }
+        }
+
+        let object_pixels = if self.lcd_control & OBJ_ENABLE != 0 {
+            self.object_pixels()
+        } else {
+            [None; SCREEN_WIDTH]
+        };
+
+        let mut row = [0; SCREEN_WIDTH];
+        for (x, colour) in row.iter_mut().enumerate() {
+            *colour = self.pixel_colour(bg_pixels[x], object_pixels[x]);
+        }
+        self.framebuffer[line as usize * SCREEN_WIDTH..][..SCREEN_WIDTH].copy_from_slice(&row);
+    }
+
+    // Which object pixel, if any, won each position on the line. A transparent pixel doesn't win,
+    // letting the next object show through.
+    fn object_pixels(&self) -> [Option<ObjectPixel>; SCREEN_WIDTH] {
+        let mut objects = self.line_objects.clone();
+        if self.oam_priority() {
+            objects.sort_by_key(|object| object.index);
+        }
+
+        let mut winners = [None; SCREEN_WIDTH];
+        for object in objects {
+            let pixels = self.object_row(object);
+
+            for (column, &pixel) in pixels.iter().enumerate() {
+                let x = object.x as i16 - 8 + column as i16;
+                if !(0..SCREEN_WIDTH as i16).contains(&x) || winners[x as usize].is_some() {
+                    continue;
+                }
+
+                if pixel.colour != 0 {
+                    winners[x as usize] = Some(pixel);
+                }
+            }
+        }
+        winners
+    }
+}
@@ -1,4 +1,4 @@
-#[derive(Clone)]
+#[derive(Clone, Copy)]
 pub enum Register {
     A, // Accumulator
     F, // Flags
@@ -17,6 +17,7 @@ pub enum Register {
     StackPointer, // Stack Pointer
 }
 
+#[derive(Clone, Copy)]
 pub enum Flag {
     Z, // Zero Flag
     N, // Add / Sub Flag
@@ -57,7 +58,8 @@ impl RegisterFile {
     pub fn write_register(&mut self, reg: Register, value: u16) {
         match reg {
             Register::A => self.register_data[0] = value as u8,
-            Register::F => self.register_data[1] = value as u8,
+            // The lower nibble of the flags register doesn't exist in hardware and always reads 0
+            Register::F => self.register_data[1] = value as u8 & 0xF0,
             Register::B => self.register_data[2] = value as u8,
             Register::C => self.register_data[3] = value as u8,
             Register::D => self.register_data[4] = value as u8,
@@ -65,7 +67,7 @@ impl RegisterFile {
             Register::H => self.register_data[6] = value as u8,
             Register::L => self.register_data[7] = value as u8,
 
-            Register::AF => self.set_word(0, value),
+            Register::AF => self.set_word(0, value & 0xFFF0),
             Register::BC => self.set_word(2, value),
             Register::DE => self.set_word(4, value),
             Register::HL => self.set_word(6, value),
@@ -85,8 +87,6 @@ impl RegisterFile {
         let higher_byte = self.register_data[index] as u16;
         let lower_byte = self.register_data[index + 1] as u16;
 
-        println!("{}, {}", higher_byte, lower_byte);
-
         (higher_byte << 8) | lower_byte
     }
 
@@ -117,6 +117,7 @@ impl RegisterFile {
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
     use super::*;
 
@@ -169,4 +170,14 @@ mod tests {
         assert_eq!(register_file.get_flag(Flag::Z), false);
         assert_eq!(register_file.get_flag(Flag::N), false);
     }
+
+    #[test]
+    fn flags_low_nibble_is_always_zero() {
+        let mut register_file = RegisterFile::new();
+        register_file.write_register(Register::AF, 0x12FF);
+        assert_eq!(register_file.read_register(Register::AF), 0x12F0);
+
+        register_file.write_register(Register::F, 0x0F);
+        assert_eq!(register_file.read_register(Register::F), 0x00);
+    }
 }
@@ -0,0 +1,214 @@
+// Runs test ROMs from other projects, which aren't distributed with the emulator. They're looked
+// for in test-roms/ at the top of the repository, or wherever RUSTBOY_TEST_ROMS points:
+//
+// - mooneye/: the built mooneye test suite, keeping its directory layout
+// - dmg-acid2.gb, along with its reference screenshot converted to a binary PGM as dmg-acid2.pgm
+//
+// The tests that need them are ignored unless asked for with `cargo test -- --ignored`, and
+// fail if any ROM is missing.
+
+use std::{env, fs, path::PathBuf};
+
+use super::{
+    bus::Bus,
+    cartridge::Cartridge,
+    cpu::{CPU, CYCLES_PER_FRAME},
+    model::Model,
+    ppu::{rgb888, Renderer, SCREEN_HEIGHT, SCREEN_WIDTH},
+};
+
+// Mooneye ROMs pass by leaving the start of the Fibonacci sequence in B, C, D, E, H and L
+const MOONEYE_PASS: [u8; 6] = [3, 5, 8, 13, 21, 34];
+
+// Emulated time a ROM gets to reach its breakpoint before it's failed
+const TIMEOUT_FRAMES: u64 = 60 * 10;
+
+fn rom_path(name: &str) -> PathBuf {
+    let directory = env::var_os("RUSTBOY_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("test-roms"));
+    directory.join(name)
+}
+
+fn read_test_file(name: &str) -> Vec<u8> {
+    let path = rom_path(name);
+    fs::read(&path).unwrap_or_else(|error| panic!("can't read {}: {}", path.display(), error))
+}
+
+// Starts a ROM from the post-boot state
+fn start_rom(name: &str, model: Model, renderer: Renderer) -> CPU {
+    let rom = read_test_file(name);
+
+    let cartridge = Cartridge::from_bytes(rom).unwrap();
+    let mut bus = Bus::with_renderer(model, renderer);
+    bus.insert_cartridge(Box::new(cartridge));
+
+    let mut cpu = CPU::new(bus);
+    cpu.skip_boot_rom();
+    cpu
+}
+
+// Returns the registers at the breakpoint, or None if the ROM never got there
+fn run_to_breakpoint(cpu: &mut CPU) -> Option<[u8; 6]> {
+    let timeout = cpu.total_cycles() + TIMEOUT_FRAMES * CYCLES_PER_FRAME as u64;
+    while cpu.total_cycles() < timeout {
+        if let Some(registers) = cpu.breakpoint() {
+            return Some(registers);
+        }
+        cpu.step();
+    }
+
+    None
+}
+
+// Runs each of the ROMs under mooneye/, failing with every one that doesn't pass
+fn run_mooneye(names: &[&str], model: Model, renderer: Renderer) {
+    let mut failures = Vec::new();
+    for name in names {
+        let mut cpu = start_rom(&format!("mooneye/{}.gb", name), model, renderer);
+
+        match run_to_breakpoint(&mut cpu) {
+            Some(MOONEYE_PASS) => (),
+            Some(registers) => failures.push(format!("{}: failed with {:02X?}", name, registers)),
+            None => failures.push(format!("{}: timed out", name)),
+        }
+    }
+
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+// Reads a binary PGM with one byte per pixel and no comments, as image converters write them
+fn read_pgm(data: &[u8]) -> Option<(usize, usize, &[u8])> {
+    let mut fields = data
+        .split(|byte| byte.is_ascii_whitespace())
+        .filter(|field| !field.is_empty());
+    if fields.next()? != b"P5" {
+        return None;
+    }
+
+    let mut number = || {
+        std::str::from_utf8(fields.next()?)
+            .ok()?
+            .parse::<usize>()
+            .ok()
+    };
+    let (width, height, maximum) = (number()?, number()?, number()?);
+    if maximum > 0xFF {
+        return None;
+    }
+
+    let pixels = data.get(data.len().checked_sub(width * height)?..)?;
+    Some((width, height, pixels))
+}
+
+// Turns a grey into one of the 4 DMG shades, from black to white
+fn shade(grey: u8) -> u8 {
+    ((grey as u16 + 0x2A) / 0x55) as u8
+}
+
+#[test]
+#[ignore = "needs the dmg-acid2 ROM"]
+fn dmg_acid2() {
+    let reference = read_test_file("dmg-acid2.pgm");
+    let mut cpu = start_rom("dmg-acid2.gb", Model::Dmg, Renderer::Fifo);
+
+    let (width, height, expected) = read_pgm(&reference).expect("bad reference image");
+    assert_eq!((width, height), (SCREEN_WIDTH, SCREEN_HEIGHT));
+
+    assert!(run_to_breakpoint(&mut cpu).is_some(), "timed out");
+    // Make sure the finished picture has made it to the framebuffer
+    cpu.run_frame();
+    cpu.run_frame();
+
+    let wrong: Vec<(usize, usize)> = cpu
+        .framebuffer()
+        .iter()
+        .zip(expected)
+        .enumerate()
+        .filter(|&(_, (&colour, &grey))| shade(rgb888(colour, false) as u8) != shade(grey))
+        .map(|(index, _)| (index % SCREEN_WIDTH, index / SCREEN_WIDTH))
+        .collect();
+    assert!(
+        wrong.is_empty(),
+        "{} pixels differ, starting at {:?}",
+        wrong.len(),
+        wrong[0]
+    );
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn mooneye_ppu_timing() {
+    run_mooneye(
+        &[
+            "acceptance/ppu/hblank_ly_scx_timing-GS",
+            "acceptance/ppu/intr_1_2_timing-GS",
+            "acceptance/ppu/intr_2_0_timing",
+            "acceptance/ppu/intr_2_mode0_timing",
+            "acceptance/ppu/intr_2_mode0_timing_sprites",
+            "acceptance/ppu/intr_2_mode3_timing",
+            "acceptance/ppu/intr_2_oam_ok_timing",
+            "acceptance/ppu/lcdon_timing-GS",
+            "acceptance/ppu/lcdon_write_timing-GS",
+            "acceptance/ppu/stat_irq_blocking",
+            "acceptance/ppu/stat_lyc_onoff",
+            "acceptance/ppu/vblank_stat_intr-GS",
+        ],
+        Model::Dmg,
+        Renderer::Fifo,
+    );
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn mooneye_oam_dma() {
+    run_mooneye(
+        &[
+            "acceptance/oam_dma_restart",
+            "acceptance/oam_dma_start",
+            "acceptance/oam_dma_timing",
+            "acceptance/oam_dma/basic",
+            "acceptance/oam_dma/reg_read",
+            "acceptance/oam_dma/sources-GS",
+        ],
+        Model::Dmg,
+        Renderer::Scanline,
+    );
+}
+
+#[test]
+#[ignore = "needs the mooneye test ROMs"]
+fn mooneye_timer() {
+    run_mooneye(
+        &[
+            "acceptance/timer/div_write",
+            "acceptance/timer/rapid_toggle",
+            "acceptance/timer/tim00",
+            "acceptance/timer/tim00_div_trigger",
+            "acceptance/timer/tim01",
+            "acceptance/timer/tim01_div_trigger",
+            "acceptance/timer/tim10",
+            "acceptance/timer/tim10_div_trigger",
+            "acceptance/timer/tim11",
+            "acceptance/timer/tim11_div_trigger",
+            "acceptance/timer/tima_reload",
+            "acceptance/timer/tima_write_reloading",
+            "acceptance/timer/tma_write_reloading",
+        ],
+        Model::Dmg,
+        Renderer::Scanline,
+    );
+}
+
+#[test]
+fn reads_binary_pgm() {
+    let (width, height, pixels) = read_pgm(b"P5\n2 1\n255\n\x00\xFF").unwrap();
+    assert_eq!((width, height), (2, 1));
+    assert_eq!(pixels, [0x00, 0xFF]);
+    assert_eq!(
+        pixels.iter().map(|&grey| shade(grey)).collect::<Vec<_>>(),
+        [0, 3]
+    );
+
+    assert!(read_pgm(b"P2\n2 1\n255\n0 255").is_none());
+}
@@ -0,0 +1,291 @@
+use super::{bus::MemoryMapped, interrupts::Interrupt};
+
+pub const DIV: u16 = 0xFF04;
+pub const TIMA: u16 = 0xFF05;
+pub const TMA: u16 = 0xFF06;
+pub const TAC: u16 = 0xFF07;
+
+const TAC_ENABLE: u8 = 0x04;
+
+// Bit of the divider that TIMA counts the falling edges of, for each clock select in TAC. That
+// gives 4096Hz, 262144Hz, 65536Hz and 16384Hz at normal speed.
+const CLOCK_BITS: [u16; 4] = [9, 3, 5, 7];
+
+// T-cycles between TIMA overflowing and being reloaded from TMA
+const RELOAD_DELAY: u8 = 4;
+
+// The timer is built around a 16-bit divider that counts T-cycles, of which DIV is the top 8
+// bits. TAC picks one of its bits, ANDs it with the enable bit and TIMA goes up whenever that
+// signal falls. Counting edges rather than cycles is what makes the glitches happen: resetting the
+// divider by writing DIV, or changing TAC, can make the signal fall and TIMA go up early.
+//
+// When TIMA overflows it reads 0 for an M-cycle before being reloaded from TMA, and the interrupt
+// is requested at the same time as the reload. Writing TIMA in the M-cycle before the reload
+// cancels it, while in the M-cycle after, TIMA can't be written but writes to TMA go through to
+// TIMA too.
+pub struct Timer {
+    divider: u16,
+    counter: u8,
+    modulo: u8,
+    control: u8,
+    // T-cycles left until an overflowed TIMA is reloaded
+    reload_delay: u8,
+    // T-cycles left of the M-cycle in which TIMA was reloaded
+    reloading: u8,
+    interrupt_requested: bool,
+}
+
+impl Default for Timer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Timer {
+    pub fn new() -> Self {
+        Timer {
+            divider: 0,
+            counter: 0,
+            modulo: 0,
+            control: 0,
+            reload_delay: 0,
+            reloading: 0,
+            interrupt_requested: false,
+        }
+    }
+
+    pub fn divider(&self) -> u16 {
+        self.divider
+    }
+
+    // Sets the divider without the side effects of writing DIV, for starting up without a boot ROM
+    pub fn set_divider(&mut self, divider: u16) {
+        self.divider = divider;
+    }
+
+    pub fn take_interrupt(&mut self) -> Option<Interrupt> {
+        std::mem::take(&mut self.interrupt_requested).then_some(Interrupt::Timer)
+    }
+
+    fn signal(&self, divider: u16, control: u8) -> bool {
+        let bit = CLOCK_BITS[(control & 0x03) as usize];
+        control & TAC_ENABLE != 0 && divider & (1 << bit) != 0
+    }
+
+    fn increment_counter(&mut self) {
+        let (counter, overflow) = self.counter.overflowing_add(1);
+        self.counter = counter;
+        if overflow {
+            self.reload_delay = RELOAD_DELAY;
+        }
+    }
+
+    // Moves on by the given number of T-cycles, which run twice as fast in double speed
+    pub fn tick(&mut self, cycles: u32) {
+        for _ in 0..cycles {
+            self.reloading = self.reloading.saturating_sub(1);
+
+            if self.reload_delay > 0 {
+                self.reload_delay -= 1;
+                if self.reload_delay == 0 {
+                    self.counter = self.modulo;
+                    self.interrupt_requested = true;
+                    self.reloading = RELOAD_DELAY;
+                }
+            }
+
+            let divider = self.divider.wrapping_add(1);
+            self.set_signal_inputs(divider, self.control);
+        }
+    }
+
+    // Changes the divider and TAC, moving TIMA on if the signal falls as a result
+    fn set_signal_inputs(&mut self, divider: u16, control: u8) {
+        let falling = self.signal(self.divider, self.control) && !self.signal(divider, control);
+        self.divider = divider;
+        self.control = control;
+
+        if falling {
+            self.increment_counter();
+        }
+    }
+}
+
+impl MemoryMapped for Timer {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            DIV => (self.divider >> 8) as u8,
+            TIMA => self.counter,
+            TMA => self.modulo,
+            TAC => 0xF8 | self.control,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            DIV => self.set_signal_inputs(0, self.control),
+            // Ignored while being reloaded
+            TIMA if self.reloading > 0 => (),
+            TIMA => {
+                self.counter = value;
+                self.reload_delay = 0;
+            }
+            TMA => {
+                self.modulo = value;
+                if self.reloading > 0 {
+                    self.counter = value;
+                }
+            }
+            TAC => self.set_signal_inputs(self.divider, value & 0x07),
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Enabled at 262144Hz, so TIMA goes up every 16 T-cycles
+    fn fast_timer() -> Timer {
+        let mut timer = Timer::new();
+        timer.write(TAC, TAC_ENABLE | 0x01);
+        timer
+    }
+
+    #[test]
+    fn div_counts_every_256_cycles() {
+        let mut timer = Timer::new();
+        timer.tick(255);
+        assert_eq!(timer.read(DIV), 0);
+        timer.tick(1);
+        assert_eq!(timer.read(DIV), 1);
+
+        timer.tick(256 * 300);
+        assert_eq!(timer.read(DIV), 45);
+
+        // Any write resets it
+        timer.write(DIV, 0x42);
+        assert_eq!(timer.read(DIV), 0);
+    }
+
+    #[test]
+    fn tima_counts_at_the_selected_rate() {
+        for (select, period) in [(0, 1024), (1, 16), (2, 64), (3, 256)] {
+            let mut timer = Timer::new();
+            timer.write(TAC, TAC_ENABLE | select);
+
+            timer.tick(period - 1);
+            assert_eq!(timer.read(TIMA), 0, "TAC = {}", select);
+            timer.tick(1);
+            assert_eq!(timer.read(TIMA), 1, "TAC = {}", select);
+            timer.tick(period * 10);
+            assert_eq!(timer.read(TIMA), 11, "TAC = {}", select);
+        }
+    }
+
+    #[test]
+    fn disabled_timer_doesnt_count() {
+        let mut timer = Timer::new();
+        timer.write(TAC, 0x01);
+        timer.tick(1000);
+
+        assert_eq!(timer.read(TIMA), 0);
+        assert_eq!(timer.read(TAC), 0xF9);
+    }
+
+    #[test]
+    fn overflow_reloads_after_a_cycle() {
+        let mut timer = fast_timer();
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xFF);
+
+        timer.tick(16);
+        assert_eq!(timer.read(TIMA), 0x00);
+        assert_eq!(timer.take_interrupt(), None);
+
+        timer.tick(3);
+        assert_eq!(timer.read(TIMA), 0x00);
+        timer.tick(1);
+        assert_eq!(timer.read(TIMA), 0x42);
+        assert_eq!(timer.take_interrupt(), Some(Interrupt::Timer));
+        assert_eq!(timer.take_interrupt(), None);
+    }
+
+    #[test]
+    fn writing_tima_before_reload_cancels_it() {
+        let mut timer = fast_timer();
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xFF);
+        timer.tick(16);
+
+        timer.write(TIMA, 0x10);
+        timer.tick(4);
+        assert_eq!(timer.read(TIMA), 0x10);
+        assert_eq!(timer.take_interrupt(), None);
+    }
+
+    #[test]
+    fn tima_is_locked_while_reloading() {
+        let mut timer = fast_timer();
+        timer.write(TMA, 0x42);
+        timer.write(TIMA, 0xFF);
+        timer.tick(20);
+
+        // Writes to TIMA are ignored, and writes to TMA go through to TIMA
+        timer.write(TIMA, 0x10);
+        assert_eq!(timer.read(TIMA), 0x42);
+        timer.write(TMA, 0x24);
+        assert_eq!(timer.read(TIMA), 0x24);
+
+        // Back to normal on the next M-cycle
+        timer.tick(4);
+        timer.write(TIMA, 0x10);
+        assert_eq!(timer.read(TIMA), 0x10);
+        timer.write(TMA, 0x33);
+        assert_eq!(timer.read(TIMA), 0x10);
+    }
+
+    #[test]
+    fn resetting_div_can_move_tima_on() {
+        let mut timer = fast_timer();
+
+        // Bit 3 of the divider is clear, so resetting it doesn't make the signal fall
+        timer.tick(4);
+        timer.write(DIV, 0);
+        assert_eq!(timer.read(TIMA), 0);
+
+        // Bit 3 is set, so it does
+        timer.tick(8);
+        timer.write(DIV, 0);
+        assert_eq!(timer.read(TIMA), 1);
+
+        // And the count starts again from there
+        timer.tick(15);
+        assert_eq!(timer.read(TIMA), 1);
+        timer.tick(1);
+        assert_eq!(timer.read(TIMA), 2);
+    }
+
+    #[test]
+    fn changing_tac_can_move_tima_on() {
+        // Disabling the timer while the selected bit is set
+        let mut timer = fast_timer();
+        timer.tick(8);
+        timer.write(TAC, 0x01);
+        assert_eq!(timer.read(TIMA), 1);
+
+        // Selecting a bit that's clear while the old one is set
+        let mut timer = fast_timer();
+        timer.tick(8);
+        timer.write(TAC, TAC_ENABLE | 0x02);
+        assert_eq!(timer.read(TIMA), 1);
+
+        // Selecting a bit that's also set
+        let mut timer = fast_timer();
+        timer.tick(0x28);
+        timer.write(TAC, TAC_ENABLE | 0x02);
+        assert_eq!(timer.read(TIMA), 2);
+    }
+}
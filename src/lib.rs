@@ -0,0 +1,2 @@
+pub mod hardware;
+mod utils;
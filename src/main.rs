@@ -1,9 +1,102 @@
-mod hardware;
-mod utils;
+use std::{env, fs, path::Path, process};
 
-use crate::hardware::CPU;
+use rustboy::hardware::{Bus, Cartridge, CgbSupport, Model, Renderer, CPU};
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: rustboy <rom> [--model dmg|mgb|sgb|cgb|agb] [--renderer scanline|fifo] [--boot-rom <file or directory>]"
+    );
+    process::exit(1);
+}
 
 fn main() {
-    let mut cpu = CPU::new();
-    cpu.step();
+    let mut rom_path = None;
+    let mut boot_rom_path = None;
+    let mut model = None;
+    let mut renderer = Renderer::Scanline;
+
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--boot-rom" => boot_rom_path = Some(args.next().unwrap_or_else(|| usage())),
+            "--model" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                model = Some(name.parse::<Model>().unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    usage()
+                }));
+            }
+            "--renderer" => {
+                let name = args.next().unwrap_or_else(|| usage());
+                renderer = name.parse::<Renderer>().unwrap_or_else(|error| {
+                    eprintln!("{}", error);
+                    usage()
+                });
+            }
+            _ if rom_path.is_none() => rom_path = Some(arg),
+            _ => usage(),
+        }
+    }
+    let path = rom_path.unwrap_or_else(|| usage());
+
+    let cartridge = match Cartridge::load(&path) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            eprintln!("Failed to load {}: {}", path, error);
+            process::exit(1);
+        }
+    };
+    println!("Loaded {}", cartridge.header());
+    if !cartridge.header().global_checksum_valid {
+        eprintln!("Warning: global checksum mismatch, the ROM may be a bad dump");
+    }
+    if let Some(save_path) = cartridge.save_path() {
+        println!("Saving to {}", save_path.display());
+    }
+
+    // Default to the oldest model the game supports
+    let model = model.unwrap_or(match cartridge.header().cgb_support {
+        CgbSupport::None => Model::Dmg,
+        _ => Model::Cgb,
+    });
+
+    let mut bus = Bus::with_renderer(model, renderer);
+    bus.insert_cartridge(Box::new(cartridge));
+
+    // Given a directory, use the usual name for the model's boot ROM
+    let boot_rom = boot_rom_path.map(|boot_rom_path| {
+        let mut boot_rom_path = Path::new(&boot_rom_path).to_path_buf();
+        if boot_rom_path.is_dir() {
+            boot_rom_path.push(model.boot_rom_file_name());
+        }
+
+        match fs::read(&boot_rom_path) {
+            Ok(boot_rom) => boot_rom,
+            Err(error) => {
+                eprintln!(
+                    "Failed to load boot ROM {}: {}",
+                    boot_rom_path.display(),
+                    error
+                );
+                process::exit(1);
+            }
+        }
+    });
+
+    // Battery-backed RAM is saved when the CPU, and the cartridge with it, is dropped
+    let mut cpu = match boot_rom {
+        Some(boot_rom) => {
+            if let Err(error) = bus.insert_boot_rom(boot_rom) {
+                eprintln!("{}", error);
+                process::exit(1);
+            }
+            CPU::new(bus)
+        }
+        None => {
+            let mut cpu = CPU::new(bus);
+            cpu.skip_boot_rom();
+            cpu
+        }
+    };
+    cpu.run_frame();
 }